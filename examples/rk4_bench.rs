@@ -0,0 +1,42 @@
+//! Timing of the Rk4 integration of data/reactions.ron.
+//!
+//! Run with `cargo run --release --example rk4_bench`.
+use std::time::Instant;
+
+use radiobio::ode_solver::rk4::Rk4;
+use radiobio::reactions::parse_reactions_file;
+use radiobio::{Beam, ODESolver};
+
+const REPEATS: usize = 20;
+
+fn main() {
+    let reaction_file = format!(
+        "{}/data/reactions.ron",
+        env!("CARGO_MANIFEST_DIR")
+    );
+
+    let mut timings = vec![];
+    let mut steps = 0;
+    let mut checksum = 0_f64;
+    for _ in 0..REPEATS {
+        let sim_env = parse_reactions_file(&reaction_file).unwrap();
+        let beam = Beam::new_pulsed(String::from("e"), 1e3, 1e-5, 1e-6).unwrap();
+        let sim = ODESolver::new(sim_env, beam);
+        let y0 = sim.env().get_initial_values();
+
+        let mut stepper = Rk4::new(sim, 0.0, y0, 1e-4, 1e-9);
+        let start = Instant::now();
+        let stats = stepper.integrate().unwrap();
+        timings.push(start.elapsed().as_secs_f64());
+        steps = stats.accepted_steps;
+        checksum = stepper.y_out().last().unwrap().sum();
+    }
+    timings.sort_by(|a, b| a.total_cmp(b));
+    let median = timings[REPEATS / 2];
+    println!("{} steps of Rk4 on data/reactions.ron", steps);
+    println!("median: {:.2} ms ({:.1} ns/step), best: {:.2} ms",
+             median * 1e3,
+             median * 1e9 / steps as f64,
+             timings[0] * 1e3);
+    println!("checksum of the final state: {:e}", checksum);
+}
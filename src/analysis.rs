@@ -0,0 +1,283 @@
+/* ---------------------------- External imports ---------------------------- */
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/* ---------------------------- Internal imports ---------------------------- */
+use crate::{State, Time};
+use crate::simulation::Trajectory;
+use crate::units::{self, to_state, OutputUnits, Quantity};
+
+/* -------------------------------------------------------------------------- */
+/*                         FUNCTION/STRUCT DEFINITIONS                        */
+/* -------------------------------------------------------------------------- */
+// Scalar outcome of a trajectory, computed in the units of the solver
+// ([µmol/l], [s]) and converted for the output
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum Metric {
+    Final(String),
+    Peak(String),
+    PeakTime(String),
+    // Final minus initial value
+    Change(String),
+    // Time integral of the concentration, trapezoidal rule
+    Auc(String),
+    // Same with the derivatives at the samples (cubic Hermite), see
+    // Simulation::add_slopes
+    DenseAuc(String),
+    // First time the concentration crosses the value, NaN if it never does
+    Crossing(String, Quantity),
+}
+
+impl Metric {
+    pub fn species(&self) -> &str {
+        match self {
+            Metric::Final(sp) | Metric::Peak(sp) | Metric::PeakTime(sp) | Metric::Change(sp)
+            | Metric::Auc(sp) | Metric::DenseAuc(sp) | Metric::Crossing(sp, _) => sp,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Metric::Final(sp) => format!("final({sp})"),
+            Metric::Peak(sp) => format!("peak({sp})"),
+            Metric::PeakTime(sp) => format!("peak_time({sp})"),
+            Metric::Change(sp) => format!("change({sp})"),
+            Metric::Auc(sp) => format!("auc({sp})"),
+            Metric::DenseAuc(sp) => format!("dense_auc({sp})"),
+            Metric::Crossing(sp, value) => format!("crossing({sp}, {value})"),
+        }
+    }
+
+    pub fn header(&self, units:OutputUnits) -> String {
+        let cc = units.concentration.symbol();
+        let t = units.time.symbol();
+        match self {
+            Metric::Auc(_) | Metric::DenseAuc(_) => format!("{} [{cc}.{t}]", self.name()),
+            Metric::PeakTime(_) | Metric::Crossing(..) => format!("{} [{t}]", self.name()),
+            _ => format!("{} [{cc}]", self.name()),
+        }
+    }
+
+    // Whether the trajectory needs its slopes
+    pub fn is_dense(&self) -> bool {
+        matches!(self, Metric::DenseAuc(_))
+    }
+
+    pub fn evaluate(&self, trajectory:&Trajectory) -> Result<f64> {
+        let values = trajectory.series(self.species())?;
+        if values.is_empty() {
+            anyhow::bail!("Empty trajectory, cannot compute {}", self.name());
+        }
+        let times = &trajectory.times;
+        match self {
+            Metric::Final(_) => Ok(values[values.len()-1]),
+            Metric::Peak(_) => Ok(peak(times, &values).1),
+            Metric::PeakTime(_) => Ok(peak(times, &values).0),
+            Metric::Change(_) => Ok(values[values.len()-1] - values[0]),
+            Metric::Auc(_) => Ok(trapezoid(times, &values)),
+            Metric::DenseAuc(sp) => {
+                let slopes = trajectory.slopes.as_ref().with_context(|| format!(
+                    "{} needs the slopes of the trajectory", self.name()))?;
+                // Same column as the values
+                let idx = trajectory.species_index(sp).unwrap_or_default();
+                let slopes: Vec<(f64, f64)> = slopes.iter().map(|(a, b)| (a[idx], b[idx]))
+                                                    .collect();
+                Ok(hermite(times, &values, &slopes))
+            },
+            Metric::Crossing(sp, value) => {
+                let threshold = to_state(value.concentration(sp)?);
+                Ok(crossing(times, &values, threshold).unwrap_or(f64::NAN))
+            },
+        }
+    }
+
+    // From the units of the solver to the output units
+    pub fn convert(&self, value:f64, units:OutputUnits) -> f64 {
+        match self {
+            Metric::Auc(_) | Metric::DenseAuc(_) =>
+                units.time.from_seconds(units.concentration.from_state(value)),
+            Metric::PeakTime(_) | Metric::Crossing(..) => units.time.from_seconds(value),
+            _ => units.concentration.from_state(value),
+        }
+    }
+}
+
+pub fn trapezoid(times:&[Time], values:&[f64]) -> f64 {
+    times.windows(2)
+         .zip(values.windows(2))
+         .map(|(t, y)| 0.5 * (y[0] + y[1]) * (t[1] - t[0]))
+         .sum()
+}
+
+// Integral of the cubic Hermite interpolant, exact for cubics. `slopes`
+// holds the derivatives at both ends of each interval, one-sided as the
+// beam may switch at a sample.
+pub fn hermite(times:&[Time], values:&[f64], slopes:&[(f64, f64)]) -> f64 {
+    times.windows(2)
+         .zip(values.windows(2))
+         .zip(slopes.iter())
+         .map(|((t, y), (d0, d1))| {
+             let h = t[1] - t[0];
+             0.5 * h * (y[0] + y[1]) + h * h * (d0 - d1) / 12.0
+         })
+         .sum()
+}
+
+// Highest sample and its time, the first one if reached several times
+pub fn peak(times:&[Time], values:&[f64]) -> (Time, f64) {
+    let mut best = (times[0], values[0]);
+    for (t, y) in times.iter().zip(values.iter()) {
+        if *y > best.1 {
+            best = (*t, *y);
+        }
+    }
+    best
+}
+
+// First time the values reach the threshold from the side they start on,
+// linearly interpolated between the samples
+pub fn crossing(times:&[Time], values:&[f64], threshold:f64) -> Option<Time> {
+    let side = (values[0] - threshold).signum();
+    if values[0] == threshold {
+        return Some(times[0]);
+    }
+    for (t, y) in times.windows(2).zip(values.windows(2)) {
+        if (y[1] - threshold).signum() != side || y[1] == threshold {
+            let fraction = (threshold - y[0]) / (y[1] - y[0]);
+            return Some(t[0] + fraction * (t[1] - t[0]));
+        }
+    }
+    None
+}
+
+// Metrics written next to the trajectory of a simulation
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct AnalysisConfig {
+    // Crossing times reported for these values, e.g. { "O2": "10 mmHg" }
+    #[serde(default, deserialize_with = "units::concentrations",
+            skip_serializing_if = "HashMap::is_empty")]
+    pub thresholds: HashMap<String, f64>, // [mol/l]
+    // Integrals with the derivatives at the samples (cubic Hermite)
+    #[serde(default)]
+    pub dense_auc: bool,
+}
+
+// Outcomes of one species, in the units of the solver ([µmol/l], [s])
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeciesMetrics {
+    pub species: String,
+    pub initial: f64,
+    pub final_value: f64,
+    // Final minus initial: > 0 when produced, < 0 when consumed
+    pub net: f64,
+    pub peak: f64,
+    pub peak_time: Time,
+    pub auc: f64,
+    pub threshold: Option<f64>,
+    pub crossing: Option<Time>,
+}
+
+impl SpeciesMetrics {
+    // Dense integrals if the trajectory has its slopes
+    pub fn compute(trajectory:&Trajectory, species:&str, threshold:Option<f64>)
+    -> Result<Self> {
+        let metric = match trajectory.slopes.is_some() {
+            true => Metric::DenseAuc(species.to_string()),
+            false => Metric::Auc(species.to_string()),
+        };
+        let auc = metric.evaluate(trajectory)?;
+        let values = trajectory.series(species)?;
+        let times = &trajectory.times;
+        let (peak_time, peak) = peak(times, &values);
+        let (initial, final_value) = (values[0], values[values.len()-1]);
+        Ok(Self {
+            species: species.to_string(),
+            initial,
+            final_value,
+            net: final_value - initial,
+            peak,
+            peak_time,
+            auc,
+            threshold,
+            crossing: threshold.and_then(|t| crossing(times, &values, t)),
+        })
+    }
+}
+
+// Metrics of every species of the trajectory. Thresholds in [mol/l].
+pub fn analyse(trajectory:&Trajectory, thresholds:&HashMap<String, f64>)
+-> Result<Vec<SpeciesMetrics>> {
+    for sp in thresholds.keys() {
+        if trajectory.species_index(sp).is_none() {
+            anyhow::bail!("Threshold given for {sp}, which is not simulated");
+        }
+    }
+    trajectory.labels.iter().map(|label| {
+        // Given for the label or one species of its couple
+        let threshold = thresholds.iter()
+            .find(|(sp, _)| trajectory.species_index(sp) == trajectory.species_index(label))
+            .map(|(_, value)| to_state(*value));
+        SpeciesMetrics::compute(trajectory, label, threshold)
+    }).collect()
+}
+
+// CSV with one row per species in the output units, empty cells without
+// threshold or crossing
+pub fn write_metrics(metrics:&[SpeciesMetrics], path:&Path, units:OutputUnits) -> Result<()> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Cannot create {}", dir.display()))?;
+    }
+    let file = File::create(path)
+        .with_context(|| format!("Cannot create {}", path.display()))?;
+    let mut buf = BufWriter::new(file);
+    let (cc, t) = (units.concentration, units.time);
+    let (c_sym, t_sym) = (cc.symbol(), t.symbol());
+    writeln!(buf, "species, initial [{c_sym}], final [{c_sym}], net [{c_sym}], \
+                   peak [{c_sym}], peak time [{t_sym}], auc [{c_sym}.{t_sym}], \
+                   threshold [{c_sym}], crossing [{t_sym}]")?;
+    let optional = |value:Option<f64>| value.map(|v| v.to_string()).unwrap_or_default();
+    for m in metrics.iter() {
+        writeln!(buf, "{}, {}, {}, {}, {}, {}, {}, {}, {}",
+                 m.species, cc.from_state(m.initial), cc.from_state(m.final_value),
+                 cc.from_state(m.net), cc.from_state(m.peak), t.from_seconds(m.peak_time),
+                 t.from_seconds(cc.from_state(m.auc)),
+                 optional(m.threshold.map(|v| cc.from_state(v))),
+                 optional(m.crossing.map(|v| t.from_seconds(v))))?;
+    }
+    buf.flush()?;
+    Ok(())
+}
+
+// Derivatives at both ends of each interval, see `hermite`
+pub type Slopes = Vec<(State, State)>;
+
+/* -------------------------------------------------------------------------- */
+/*                                   TESTING                                  */
+/* -------------------------------------------------------------------------- */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn series_are_summarised() {
+        // y = t^3 - 3t, y' = 3t^2 - 3 on [0, 2]
+        let times: Vec<f64> = (0..=4).map(|i| 0.5 * i as f64).collect();
+        let values: Vec<f64> = times.iter().map(|t| t*t*t - 3.0*t).collect();
+        let slope = |t:f64| 3.0*t*t - 3.0;
+        let slopes: Vec<(f64, f64)> = times.windows(2).map(|t| (slope(t[0]), slope(t[1])))
+                                                        .collect();
+        assert_float_absolute_eq!(hermite(&times, &values, &slopes), -2.0, 1e-12);
+        assert!((trapezoid(&times, &values) + 2.0).abs() > 0.1);
+
+        assert_eq!(peak(&times, &values), (2.0, 2.0));
+        // Down from 0, linear between the samples
+        assert_float_absolute_eq!(crossing(&times, &values, -1.0).unwrap(), 0.5 / 1.375, 1e-12);
+        assert_eq!(crossing(&times, &values, 0.0), Some(0.0));
+        assert_eq!(crossing(&times, &values, 5.0), None);
+    }
+}
@@ -0,0 +1,328 @@
+/* ---------------------------- External imports ---------------------------- */
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+
+/* ---------------------------- Internal imports ---------------------------- */
+use crate::analysis::Metric;
+use crate::reactions::errors::RadioBioError;
+use crate::simulation::{default_particle, BeamConfig, FractionConfig, Simulation, Trajectory};
+use crate::units;
+
+/* -------------------------------------------------------------------------- */
+/*                         FUNCTION/STRUCT DEFINITIONS                        */
+/* -------------------------------------------------------------------------- */
+// Same dose delivered with a conventional and a FLASH beam, e.g.
+// data/comparison.ron. Both runs use the reactions, solver and output
+// units of the base simulation.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ComparisonConfig {
+    // Base simulation, relative to the comparison file
+    pub simulation: String,
+    #[serde(default = "conventional_beam")]
+    pub conventional: BeamConfig,
+    #[serde(default = "flash_beam")]
+    pub flash: BeamConfig,
+    #[serde(deserialize_with = "units::gray")]
+    pub dose: f64, // [Gy]
+    // Simulated time after each irradiation, included in the metrics
+    #[serde(default, deserialize_with = "units::seconds")]
+    pub follow_up: f64,
+    // Species compared by their AUC, the X_r species and e_aq by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub radicals: Option<Vec<String>>,
+    // Species summed in the peroxyl exposure, the XO2_r species by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peroxyls: Option<Vec<String>>,
+    #[serde(default = "default_output")]
+    pub output: String,
+}
+
+fn conventional_beam() -> BeamConfig {
+    BeamConfig::Constant { particle: default_particle(), dose_rate: 0.1 }
+}
+fn flash_beam() -> BeamConfig {
+    BeamConfig::Constant { particle: default_particle(), dose_rate: 100.0 }
+}
+fn default_output() -> String { String::from("output/comparison.csv") }
+
+// Radicals are labelled X_r in the mechanisms, "OH_r/O_r_minus" for a couple
+fn is_radical(label:&str) -> bool {
+    label.split('/').any(|sp| sp == "e_aq" || sp.split('_').any(|part| part == "r"))
+}
+fn is_peroxyl(label:&str) -> bool {
+    label.split('/').any(|sp| sp.contains("O2_r"))
+}
+
+// Comparison ready to run: its configuration and the base simulation
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub config: ComparisonConfig,
+    pub base: Simulation,
+    // Directory of the comparison file
+    base_dir: PathBuf,
+}
+
+impl Comparison {
+    pub fn from_file(path:&str) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|source| RadioBioError::FileAccess {
+                path: path.to_string(),
+                source,
+            })?;
+        let config: ComparisonConfig = ron::de::from_str(&content)
+            .map_err(|e| RadioBioError::Parse {
+                path: path.to_string(),
+                line: e.position.line,
+                column: e.position.col,
+                message: e.code.to_string(),
+            })?;
+        let base_dir = Path::new(path).parent().unwrap_or(Path::new(""));
+        Comparison::new(config, base_dir)
+            .with_context(|| format!("While loading the comparison {path}"))
+    }
+
+    pub fn new(config:ComparisonConfig, base_dir:&Path) -> Result<Self> {
+        let simulation = base_dir.join(&config.simulation);
+        let base = Simulation::from_file(&simulation.to_string_lossy())?;
+        if config.dose <= 0.0 {
+            bail!("The dose must be positive, got {} Gy", config.dose);
+        }
+        if config.follow_up < 0.0 {
+            bail!("The follow-up cannot be negative, got {} s", config.follow_up);
+        }
+        let comparison = Self { config, base, base_dir: base_dir.to_path_buf() };
+        // Long conventional runs are caught here, not after max_steps steps
+        comparison.conventional().and_then(|sim| sim.check_steps())
+            .context("Conventional irradiation")?;
+        comparison.flash().and_then(|sim| sim.check_steps())
+            .context("FLASH irradiation")?;
+        Ok(comparison)
+    }
+
+    pub fn output_path(&self) -> PathBuf {
+        self.base_dir.join(&self.config.output)
+    }
+
+    pub fn conventional(&self) -> Result<Simulation> {
+        self.irradiation(&self.config.conventional)
+    }
+    pub fn flash(&self) -> Result<Simulation> {
+        self.irradiation(&self.config.flash)
+    }
+
+    // The dose in one fraction, then the follow-up. The Env is shared.
+    fn irradiation(&self, beam:&BeamConfig) -> Result<Simulation> {
+        let mut config = self.base.config.clone();
+        config.beam = beam.clone();
+        config.fractions = vec![FractionConfig { dose: Some(self.config.dose), ..Default::default() }];
+        config.end_time = config.schedule()?.total_duration() + self.config.follow_up;
+        self.base.modified(config, self.base.env.clone())
+    }
+
+    // Both irradiations run side by side
+    pub fn run(&self) -> Result<ComparisonReport> {
+        let (conventional, flash) = (self.conventional()?, self.flash()?);
+        let (conventional, flash) = thread::scope(|scope| {
+            let handle = scope.spawn(|| conventional.run());
+            let flash = flash.run();
+            (handle.join(), flash)
+        });
+        let conventional = conventional
+            .map_err(|_| anyhow!("The conventional irradiation panicked"))?
+            .context("Conventional irradiation")?;
+        let flash = flash.context("FLASH irradiation")?;
+        self.report(&conventional, &flash)
+    }
+
+    pub fn report(&self, conventional:&Trajectory, flash:&Trajectory) -> Result<ComparisonReport> {
+        let units = self.base.config.output.units;
+        let row = |name:String, metric:&Metric| -> Result<ComparisonRow> {
+            Ok(ComparisonRow {
+                name: name,
+                conventional: metric.convert(metric.evaluate(conventional)?, units),
+                flash: metric.convert(metric.evaluate(flash)?, units),
+            })
+        };
+        let labels = &conventional.labels;
+        let chosen = |given:&Option<Vec<String>>, default:fn(&str) -> bool| match given {
+            Some(species) => species.clone(),
+            None => labels.iter().filter(|l| default(l)).cloned().collect(),
+        };
+        let cc = units.concentration.symbol();
+        let exposure = format!("{cc}.{}", units.time.symbol());
+
+        let mut endpoints = vec![];
+        if conventional.species_index("O2").is_some() {
+            let mut consumed = row(format!("O2 consumed [{cc}]"),
+                                   &Metric::Change(String::from("O2")))?;
+            // Subtracted, a negated 0 would print as -0
+            consumed.conventional = 0.0 - consumed.conventional;
+            consumed.flash = 0.0 - consumed.flash;
+            endpoints.push(consumed);
+        }
+        for sp in chosen(&self.config.radicals, is_radical) {
+            let metric = Metric::Auc(sp);
+            endpoints.push(row(metric.header(units), &metric)?);
+        }
+        let peroxyls = chosen(&self.config.peroxyls, is_peroxyl);
+        if !peroxyls.is_empty() {
+            let mut exposure = ComparisonRow {
+                name: format!("peroxyl exposure [{exposure}]"),
+                conventional: 0.0,
+                flash: 0.0,
+            };
+            for sp in peroxyls {
+                let auc = row(String::new(), &Metric::Auc(sp))?;
+                exposure.conventional += auc.conventional;
+                exposure.flash += auc.flash;
+            }
+            endpoints.push(exposure);
+        }
+        if conventional.species_index("H2O2").is_some() {
+            let metric = Metric::Final(String::from("H2O2"));
+            endpoints.push(row(metric.header(units), &metric)?);
+        }
+
+        let mut species = vec![];
+        for label in labels.iter() {
+            for metric in [Metric::Final(label.clone()), Metric::Auc(label.clone())] {
+                species.push(row(metric.header(units), &metric)?);
+            }
+        }
+        Ok(ComparisonReport {
+            dose: self.config.dose,
+            durations: [conventional.times[conventional.times.len()-1],
+                        flash.times[flash.times.len()-1]],
+            endpoints,
+            species,
+        })
+    }
+}
+
+// Metrics of both irradiations, in the output units
+#[derive(Debug, Clone)]
+pub struct ComparisonReport {
+    pub dose: f64, // [Gy]
+    // Simulated times, conventional then FLASH [s]
+    pub durations: [f64; 2],
+    pub endpoints: Vec<ComparisonRow>,
+    pub species: Vec<ComparisonRow>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ComparisonRow {
+    pub name: String,
+    pub conventional: f64,
+    pub flash: f64,
+}
+
+impl ComparisonRow {
+    // FLASH minus conventional
+    pub fn difference(&self) -> f64 { self.flash - self.conventional }
+    // FLASH over conventional, NaN when both are 0
+    pub fn ratio(&self) -> f64 { self.flash / self.conventional }
+}
+
+impl ComparisonReport {
+    pub fn iter_rows(&self) -> impl Iterator<Item=&ComparisonRow> {
+        self.endpoints.iter().chain(self.species.iter())
+    }
+
+    // CSV with one row per quantity, parent directories are created
+    pub fn write(&self, path:&Path) -> Result<()> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Cannot create {}", dir.display()))?;
+        }
+        let file = File::create(path)
+            .with_context(|| format!("Cannot create {}", path.display()))?;
+        let mut buf = BufWriter::new(file);
+        writeln!(buf, "quantity, conventional, flash, difference, ratio")?;
+        for row in self.iter_rows() {
+            writeln!(buf, "{}, {}, {}, {}, {}", row.name, row.conventional, row.flash,
+                     row.difference(), row.ratio())?;
+        }
+        buf.flush()?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for ComparisonReport {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} Gy, {:e} s simulated (conventional) and {:e} s (FLASH)",
+                 self.dose, self.durations[0], self.durations[1])?;
+        let width = self.iter_rows().map(|r| r.name.chars().count()).max().unwrap_or(0);
+        let line = |f:&mut fmt::Formatter, row:&ComparisonRow| writeln!(f,
+            "  {:<width$} {:>12.4e} {:>12.4e} {:>12.4e} {:>9.3}",
+            row.name, row.conventional, row.flash, row.difference(), row.ratio());
+        writeln!(f, "  {:<width$} {:>12} {:>12} {:>12} {:>9}",
+                 "", "conventional", "FLASH", "difference", "ratio")?;
+        for row in self.endpoints.iter() {
+            line(f, row)?;
+        }
+        writeln!(f, "Per species")?;
+        for row in self.species.iter() {
+            line(f, row)?;
+        }
+        Ok(())
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   TESTING                                  */
+/* -------------------------------------------------------------------------- */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::SolverConfig;
+
+    #[test]
+    fn irradiations_are_compared_at_equal_dose() {
+        assert!(is_radical("OH_r/O_r_minus") && is_radical("e_aq") && !is_radical("H2O2"));
+        assert!(is_peroxyl("HO2_r/O2_r_minus") && !is_peroxyl("O2"));
+
+        // 1 Gy: 10 s of conventional irradiation, with O2 and H2O2
+        let path = format!("{}/data/comparison.ron", env!("CARGO_MANIFEST_DIR"));
+        let mut comparison = Comparison::from_file(&path).unwrap();
+        assert_float_relative_eq!(comparison.conventional().unwrap().config.end_time,
+                                  10.0 + 1e-3, 1e-9);
+        let report = comparison.run().unwrap();
+        let names: Vec<&str> = report.endpoints.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["O2 consumed [µM]", "auc(OH_r/O_r_minus) [µM.µs]",
+                               "auc(HO2_r/O2_r_minus) [µM.µs]", "auc(e_aq) [µM.µs]",
+                               "auc(H_r) [µM.µs]", "peroxyl exposure [µM.µs]",
+                               "final(H2O2) [µM]"]);
+        for row in [&report.endpoints[0], &report.endpoints[6]] {
+            assert!(row.conventional > 0.0 && row.flash > 0.0, "{}", row.name);
+            assert!(row.ratio().is_finite(), "{}", row.name);
+        }
+        // The conventional run is long: its peroxyl exposure is larger
+        assert!(report.endpoints[5].ratio() < 1e-2);
+
+        // Explicit steps are refused before running
+        let dopri5 = SolverConfig::Dopri5 { rtol: 1e-8, atol: 1e-22, max_steps: 1_000_000 };
+        comparison.base.config.solver = dopri5;
+        let message = format!("{:#}", comparison.conventional().unwrap().check_steps().unwrap_err());
+        assert!(message.contains("more than max_steps (1000000)"), "{message}");
+
+        // Same FLASH outcome with Dopri5, over a short follow-up
+        comparison.config.follow_up = 2e-6;
+        let flash = comparison.flash().unwrap();
+        flash.check_steps().unwrap();
+        let dopri5 = flash.run().unwrap();
+        comparison.base.config.solver = SolverConfig::Rosenbrock {
+            rtol: 1e-6, atol: 1e-18, max_steps: 100_000,
+        };
+        let rosenbrock = comparison.flash().unwrap().run().unwrap();
+        let (a, b) = (rosenbrock.states.last().unwrap(), dopri5.states.last().unwrap());
+        for (sp, (x, y)) in rosenbrock.labels.iter().zip(a.iter().zip(b.iter())) {
+            assert_float_absolute_eq!(*x, *y, 1e-3 * y.abs() + 1e-9);
+            assert!(x.is_finite(), "{sp}");
+        }
+    }
+}
@@ -1,4 +1,7 @@
 
+/* --------------------------- Module declarations -------------------------- */
+pub mod compiled;
+
 /* ---------------------------- External imports ---------------------------- */
 use std::collections::HashMap;
 use anyhow::{Result, Context};
@@ -13,9 +16,17 @@ use super::reactions::traits::{
 };
 use super::reactions::reactions_parser::{
     BioParam,
+    ChemistryParameter,
+    Mechanism,
     map_all_species,
 };
 use super::reactions::acid_base::AcidBase;
+use super::reactions::compartments::{Compartment, same_namespace};
+use super::reactions::profiles::Bolus;
+use super::reactions::provenance::Provenance;
+use super::reactions::registry::SpeciesRegistry;
+use super::reactions::errors::RadioBioError;
+use super::units::{to_molar, to_state};
 
 /* -------------------------------------------------------------------------- */
 /*                         FUNCTION/STRUCT DEFINITIONS                        */
@@ -35,12 +46,20 @@ pub type State = na::DVector<f64>;
 pub type Time = f64;
 /* -------------------------------------------------------------------------- */
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Env {
     pub reactions: Vec<ChemicalReaction>,
     pub species: Vec<SimSpecies>,
     pub bio_param: BioParam,
     pub initial_cc: HashMap<String, f64>,
+    pub compartments: Vec<Compartment>,
+    pub boluses: Vec<Bolus>,
+    // File of origin of every entry of the mechanism
+    pub provenance: Provenance,
+    // Resolved reactions files, to write the simulated mechanism back
+    pub mechanism: Mechanism,
+    // Display names, aliases and properties of the species
+    pub registry: SpeciesRegistry,
 }
 
 impl Env {
@@ -97,7 +116,21 @@ impl Env {
         return out;
     }
 
-    pub fn mapped_cc_species(&self, y:&State) -> HashMap<String, f64> {
+    // Same order as species_label(), e.g. "O₂•⁻" for "O2_r_minus"
+    pub fn species_display_names(&self) -> Vec<String> {
+        self.species_label().iter()
+            .map(|label| self.registry.display_name(label))
+            .collect()
+    }
+
+    // Declared diffusion coefficients of the tracked species [m²/s]
+    pub fn diffusion_coefficients(&self) -> HashMap<String, f64> {
+        self.species_label().into_iter()
+            .filter_map(|label| self.registry.diffusion(&label).map(|d| (label, d)))
+            .collect()
+    }
+
+    pub fn mapped_cc_species(&self, t:Time, y:&State) -> HashMap<String, f64> {
         let mut out: HashMap<String, f64> = HashMap::new();
         let sp_idx = self.map_all_species();
 
@@ -106,15 +139,13 @@ impl Env {
             match y.get(*idx) {
                 // Force positive concentrations
                 Some(&value) if value>=0_f64 => {
-                    // Convert [µ-mol] / [l] to [mol] / [l]
-                    out.insert(species.clone(), value * 1e-6);
+                    out.insert(species.clone(), to_molar(value));
                 }
                 // Negative cc value case:
-                Some(&value) => {
-                    //println!("Negative value for {} ({:.2e})", species, value);
+                Some(_) => {
                     out.insert(species.clone(), 0_f64);
                 }
-                None => {();}
+                None => {}
             }
 
         }
@@ -122,7 +153,7 @@ impl Env {
         for sp_sim in self.species.iter() {
             match sp_sim {
                 SimSpecies::CstSpecies(sp) => {
-                    out.insert(sp.as_owned_str(), sp.cc_value());
+                    out.insert(sp.as_owned_str(), sp.cc_at(t));
                 },
                 _ => continue
             }
@@ -133,8 +164,10 @@ impl Env {
 
     #[allow(non_snake_case)]
     pub fn compute_acid_base(&self, cc:&mut HashMap<String, f64>) {
-        let cc_H_plus = *cc.get("H_plus").unwrap();
         for couple in self.iter_ABCouples() {
+            // H_plus of the compartment holding the couple
+            let cc_H_plus = *cc.get(&same_namespace(couple.acid_str(), "H_plus"))
+                               .unwrap();
             let label = couple.as_owned_str();
             let cc_tot = *cc.get(&label).unwrap();
             let partition = couple.compute_partition( cc_tot, cc_H_plus);
@@ -144,8 +177,8 @@ impl Env {
               .or_insert(partition.acid());
             // Update Base entry
             cc.entry(couple.base_str().clone())
-              .and_modify(|val| {*val=partition.acid();})
-              .or_insert(partition.acid());
+              .and_modify(|val| {*val=partition.base();})
+              .or_insert(partition.base());
         }
     }
 
@@ -166,33 +199,101 @@ impl Env {
                         .with_context(||format!("While computing reaction: {:?}", r))?;
                     out.push(val);
                 },
+                ChemicalReaction::Transfer(r) => {
+                    let val = r
+                        .compute_reaction(dose_rate, cc)
+                        .with_context(||format!("While computing transfer: {:?}", r))?;
+                    out.push(val);
+                },
             }
         }
         return Ok(out);
     }
 
-    // Create vector with cc's at t = 0
+    // Times where the forcing of the system is discontinuous: steps of the
+    // clamped concentration profiles and bolus additions.
+    pub fn breakpoints(&self) -> Vec<Time> {
+        let mut out: Vec<Time> = self.boluses.iter().map(|b| b.time).collect();
+        for sp in self.species.iter() {
+            if let SimSpecies::CstSpecies(cst) = sp {
+                out.extend(cst.profile().iter().flat_map(|p| p.breakpoints()));
+            }
+        }
+        out.sort_by(|a, b| a.total_cmp(b));
+        out.dedup();
+        return out;
+    }
+
+    // Add every bolus scheduled at time t to the state vector
+    pub fn apply_boluses(&self, t:Time, y:&mut State) {
+        let sp_idx = self.map_all_species();
+        for bolus in self.boluses.iter().filter(|b| b.time == t) {
+            if let Some(idx) = sp_idx.get(&bolus.species) {
+                y[*idx] += to_state(bolus.amount);
+            }
+        }
+    }
+
+    pub fn compartment(&self, name:&str) -> Option<&Compartment> {
+        self.compartments.iter().find(|c| c.name() == name)
+    }
+
+    // Create vector with cc's at t = 0, in the units of the state
     pub fn get_initial_values(&self) -> State {
         let mut out = State::zeros(self.number_of_tracked_species());
-        if self.initial_cc.len() <= 0 {
+        if self.initial_cc.is_empty() {
             return out;
         }
         let sp_idx = self.map_all_species();
         for (sp, value) in self.initial_cc.iter() {
             match sp_idx.get(sp) {
-                Some(idx) => out[*idx] = *value,
+                Some(idx) => out[*idx] = to_state(*value),
                 None => continue
             }
         }
         return out;
     }
+
+    // Mechanism actually simulated, with the origin of its entries
+    pub fn mechanism_to_ron(&self) -> Result<String, RadioBioError> {
+        self.mechanism.to_ron(&self.provenance)
+    }
+    pub fn mechanism_to_json(&self) -> Result<String, RadioBioError> {
+        self.mechanism.to_json(&self.provenance)
+    }
+    // RON or JSON depending on the extension
+    pub fn write_mechanism(&self, path:&str) -> Result<(), RadioBioError> {
+        self.mechanism.write(path, &self.provenance)
+    }
+    // Same mechanism with some k values, G-values or initial cc's changed
+    pub fn with_changes(&self, changes:&[(ChemistryParameter, f64)])
+    -> Result<Env, RadioBioError> {
+        self.mechanism.build_with(changes, &self.provenance)
+    }
 }
 
 
 /* -------------------------------------------------------------------------- */
 /*                                   TESTING                                  */
 /* -------------------------------------------------------------------------- */
-//#[cfg(test)]
-//mod tests {
-//    //use super::*;
-//}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reactions::parse_reactions_file;
+
+    #[test]
+    fn acid_base_partners_share_the_couple() {
+        let file = format!("{}/data/reactions.ron", env!("CARGO_MANIFEST_DIR"));
+        let env = parse_reactions_file(&file).unwrap();
+        let y = State::from_element(env.number_of_tracked_species(), 1.0);
+        let cc = env.mapped_cc_species(0.0, &y);
+        let h_plus = cc["H_plus"];
+        assert!(env.iter_ABCouples().count() > 0);
+        for couple in env.iter_ABCouples() {
+            let (acid, base) = (cc[couple.acid_str()], cc[couple.base_str()]);
+            // Both partners add up to the couple, in the ratio set by the pKa
+            assert_float_relative_eq!(acid + base, cc[&couple.as_owned_str()], 1e-12);
+            assert_float_relative_eq!(base / acid, couple.ka() / h_plus, 1e-12);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,305 @@
+//! Index based representation of an [`Env`] for fast evaluation.
+//!
+//! All species names are resolved once into slots of a flat concentration
+//! buffer and reactions become ranges of (slot, stoichiometry). Evaluating
+//! the right hand side then needs neither HashMap nor allocation and gives
+//! the same results as [`Env::mapped_cc_species`] followed by
+//! [`Env::compute_chemical_reactions`].
+
+/* ---------------------------- External imports ---------------------------- */
+use std::cell::RefCell;
+use std::collections::HashMap;
+use anyhow::{Result, bail, Context};
+
+/* ---------------------------- Internal imports ---------------------------- */
+use super::{Env, State, Time};
+use crate::reactions::SimSpecies;
+use crate::reactions::acid_base::AcidBase;
+use crate::reactions::compartments::same_namespace;
+use crate::reactions::errors::RadioBioError;
+use crate::reactions::k_reactions::{ChemicalReaction, ReactionRateIndex};
+use crate::reactions::species::CstSpecies;
+use crate::reactions::traits::{IsTrackedSpecies, RawSpecies};
+use crate::units::{to_molar, to_state};
+
+/* -------------------------------------------------------------------------- */
+/*                         FUNCTION/STRUCT DEFINITIONS                        */
+/* -------------------------------------------------------------------------- */
+#[derive(Debug, Clone)]
+enum CompiledRate {
+    // k . Π (cc / stoichio) over reactants[start..end]
+    MassAction { k: f64, start: usize, end: usize },
+    // kr . dose_rate
+    Radiolytic { kr: f64 },
+    // k . cc[from]
+    Transfer { k: f64, from: usize },
+    // Reaction involving a species that cannot be resolved
+    Unresolved { species: String },
+}
+
+#[derive(Debug, Clone)]
+struct CompiledCouple {
+    total: usize,
+    acid: usize,
+    base: usize,
+    h_plus: usize,
+    couple: AcidBase,
+}
+
+#[derive(Debug, Clone)]
+struct Term {
+    reaction: usize,
+    // -1 for consumption, yield factor for production
+    weight: f64,
+    consumption: bool,
+}
+
+#[derive(Debug)]
+pub struct CompiledNetwork {
+    n_tracked: usize,
+    // Constant (possibly clamped) species and their slot
+    cst: Vec<(usize, CstSpecies)>,
+    couples: Vec<CompiledCouple>,
+    rates: Vec<CompiledRate>,
+    // (slot, stoichiometry) of the reactants of mass action reactions
+    reactants: Vec<(usize, f64)>,
+    // Contributions to each tracked species: terms[offsets[i]..offsets[i+1]]
+    terms: Vec<Term>,
+    offsets: Vec<usize>,
+    // Debug form of each reaction for error messages
+    labels: Vec<String>,
+    scratch: RefCell<Scratch>,
+}
+
+#[derive(Debug)]
+struct Scratch {
+    cc: Vec<f64>,
+    rates: Vec<f64>,
+}
+
+impl CompiledNetwork {
+    pub fn new(env:&Env) -> Self {
+        let n_tracked = env.number_of_tracked_species();
+        let mut slots: HashMap<String, usize> = HashMap::new();
+
+        // Tracked species live in the first slots, same index as in y
+        for sp in env.iter_tracked_species() {
+            match sp {
+                SimSpecies::TrackedSpecies(s) => {slots.insert(s.as_owned_str(), s.index());},
+                SimSpecies::ABCouple(ab) => {slots.insert(ab.as_owned_str(), ab.index());},
+                _ => {},
+            }
+        }
+        // Then constant species and acid/base partners get their own slot
+        let mut n_slots = n_tracked;
+        let mut cst = vec![];
+        for sp in env.species.iter() {
+            match sp {
+                SimSpecies::CstSpecies(s) => {
+                    slots.insert(s.as_owned_str(), n_slots);
+                    cst.push((n_slots, s.clone()));
+                    n_slots += 1;
+                },
+                SimSpecies::ABPartner(p) => {
+                    slots.insert(p.as_owned_str(), n_slots);
+                    n_slots += 1;
+                },
+                _ => {},
+            }
+        }
+
+        let mut couples = vec![];
+        for couple in env.iter_ABCouples() {
+            let h_label = same_namespace(couple.acid_str(), "H_plus");
+            couples.push(CompiledCouple {
+                total: slots[&couple.as_owned_str()],
+                acid: slots[couple.acid_str()],
+                base: slots[couple.base_str()],
+                h_plus: *slots.get(&h_label)
+                    .unwrap_or_else(|| panic!("{h_label} is always defined with the pH")),
+                couple: couple.clone(),
+            });
+        }
+
+        let mut rates = vec![];
+        let mut reactants = vec![];
+        let mut labels = vec![];
+        for reaction in env.reactions.iter() {
+            labels.push(format!("{:?}", reaction));
+            let rate = match reaction {
+                ChemicalReaction::KReaction(r) => {
+                    let start = reactants.len();
+                    let mut unresolved = None;
+                    for (sp, stoi) in r.iter_reactants() {
+                        match slots.get(sp.as_str()) {
+                            Some(slot) => reactants.push((*slot, *stoi as f64)),
+                            None => {
+                                unresolved = Some(sp.as_owned_str());
+                                break;
+                            },
+                        }
+                    }
+                    match unresolved {
+                        Some(species) => CompiledRate::Unresolved { species },
+                        None => CompiledRate::MassAction {
+                            k: r.k_value(), start, end: reactants.len() },
+                    }
+                },
+                ChemicalReaction::Radiolytic(r) =>
+                    CompiledRate::Radiolytic { kr: r.kr() },
+                ChemicalReaction::Transfer(r) => match slots.get(r.from()) {
+                    Some(slot) => CompiledRate::Transfer { k: r.k_value(), from: *slot },
+                    None => CompiledRate::Unresolved { species: r.from().clone() },
+                },
+            };
+            rates.push(rate);
+        }
+
+        // Production/consumption terms in the order they were linked
+        let mut per_species: Vec<Vec<Term>> = vec![vec![]; n_tracked];
+        for sp in env.iter_tracked_species() {
+            let tracked = sp.unwrap_tracked().expect("Filtered on tracked species");
+            for rr_idx in tracked.iter_kreaction_indexes() {
+                let term = match rr_idx {
+                    ReactionRateIndex::Consumption(idx) =>
+                        Term { reaction: *idx, weight: -1.0, consumption: true },
+                    ReactionRateIndex::Production(idx) =>
+                        Term { reaction: *idx,
+                               weight: env.reactions[*idx].yield_factor(),
+                               consumption: false },
+                };
+                per_species[tracked.index()].push(term);
+            }
+        }
+        let mut offsets = vec![0];
+        let mut terms = vec![];
+        for list in per_species {
+            terms.extend(list);
+            offsets.push(terms.len());
+        }
+
+        let n_reactions = rates.len();
+        Self {
+            n_tracked,
+            cst,
+            couples,
+            rates,
+            reactants,
+            terms,
+            offsets,
+            labels,
+            scratch: RefCell::new(Scratch {
+                cc: vec![0_f64; n_slots],
+                rates: vec![0_f64; n_reactions],
+            }),
+        }
+    }
+
+    pub fn dimension(&self) -> usize { self.n_tracked }
+    pub fn number_of_reactions(&self) -> usize { self.rates.len() }
+
+    // dy += d[species]/dt for the state y (both in µmol/l)
+    pub fn evaluate(&self, t:Time, dose_rate:f64, y:&State, dy:&mut State)
+    -> Result<()> {
+        if y.len() != self.n_tracked || dy.len() != self.n_tracked {
+            bail!("State of size {} given to a network of {} species",
+                  y.len(), self.n_tracked);
+        }
+        let mut scratch = self.scratch.borrow_mut();
+        let Scratch { cc, rates } = &mut *scratch;
+
+        // Concentrations [mol/l], negative values are forced to 0
+        for (i, value) in y.iter().enumerate().take(self.n_tracked) {
+            cc[i] = if *value >= 0_f64 { to_molar(*value) } else { 0_f64 };
+        }
+        for (slot, sp) in self.cst.iter() {
+            cc[*slot] = sp.cc_at(t);
+        }
+        for c in self.couples.iter() {
+            let partition = c.couple.compute_partition(cc[c.total], cc[c.h_plus]);
+            cc[c.acid] = partition.acid();
+            cc[c.base] = partition.base();
+        }
+
+        // Reaction values [mol/l/s]
+        for (idx, rate) in self.rates.iter().enumerate() {
+            rates[idx] = match rate {
+                CompiledRate::MassAction { k, start, end } => {
+                    let mut res = *k;
+                    for (slot, stoi) in self.reactants[*start..*end].iter() {
+                        res *= cc[*slot] / stoi;
+                    }
+                    res
+                },
+                CompiledRate::Radiolytic { kr } => kr * dose_rate,
+                CompiledRate::Transfer { k, from } => k * cc[*from],
+                CompiledRate::Unresolved { species } => {
+                    return Err(RadioBioError::UnknownSpecies(species.clone()))
+                        .with_context(|| format!("While computing reaction: {}",
+                                                 self.labels[idx]));
+                },
+            };
+        }
+
+        // Accumulate contributions, converted back to [µmol/l/s]
+        for sp in 0..self.n_tracked {
+            let mut acc = 0_f64;
+            for term in self.terms[self.offsets[sp]..self.offsets[sp+1]].iter() {
+                if term.consumption {
+                    acc -= rates[term.reaction];
+                } else {
+                    acc += rates[term.reaction] * term.weight;
+                }
+            }
+            dy[sp] += to_state(acc);
+        }
+        Ok(())
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   TESTING                                  */
+/* -------------------------------------------------------------------------- */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reactions::parse_reactions_file;
+
+    // Same computation through the HashMap based path of Env
+    fn reference(env:&Env, t:Time, dose_rate:f64, y:&State) -> State {
+        let cc = env.mapped_cc_species(t, y);
+        let values = env.compute_chemical_reactions(&cc, dose_rate).unwrap();
+        let mut dy = State::zeros(y.len());
+        for sp in env.iter_tracked_species() {
+            let tracked = sp.unwrap_tracked().unwrap();
+            let idx = tracked.index();
+            for rr_idx in tracked.iter_kreaction_indexes() {
+                match rr_idx {
+                    ReactionRateIndex::Consumption(r) => dy[idx] -= values[*r],
+                    ReactionRateIndex::Production(r) =>
+                        dy[idx] += values[*r] * env.reactions[*r].yield_factor(),
+                }
+            }
+            dy[idx] = to_state(dy[idx]);
+        }
+        dy
+    }
+
+    #[test]
+    fn compiled_network_matches_env() {
+        for file in ["reactions.ron", "reactions_compartments.ron", "reactions_vial.ron"] {
+            let path = format!("{}/data/{}", env!("CARGO_MANIFEST_DIR"), file);
+            let env = parse_reactions_file(&path).unwrap();
+            let network = CompiledNetwork::new(&env);
+            let n = env.number_of_tracked_species();
+            let y = State::from_fn(n, |i, _| (i as f64 * 7.3).sin() * 10.0);
+
+            let mut dy = State::zeros(n);
+            network.evaluate(1e-6, 2.0, &y, &mut dy).unwrap();
+            assert_eq!(dy, reference(&env, 1e-6, 2.0, &y), "{file}");
+            // Added to what dy holds
+            network.evaluate(1e-6, 2.0, &y, &mut dy).unwrap();
+            assert_eq!(dy, 2.0 * reference(&env, 1e-6, 2.0, &y), "{file}");
+        }
+    }
+}
@@ -0,0 +1,439 @@
+//! Export of the simulated mechanism to other kinetics tools.
+//!
+//! The resolved [`Env`] is first flattened into a [`Network`] of species
+//! and mass action reactions shared by every format:
+//! - acid/base couples become a single species (their total). As the pH
+//!   is fixed, the acid and base fractions are constants folded into the
+//!   rate constants, which is exact.
+//! - transfers between compartments become first order reactions whose
+//!   product coefficient is the volume ratio.
+//! - radiolytic yields become sources proportional to the dose rate.
+//! - a k reaction is exported with the law of the simulation: rate
+//!   k . Π ([X] / n), each species changed once per reaction, whatever its
+//!   stoichiometry n. "2 X -> Y" is thus written "X -> Y" with k / 2.
+//!
+//! Everything a format cannot represent is listed in [`Export::warnings`].
+
+/* --------------------------- Module declarations -------------------------- */
+pub mod sbml;
+pub mod cantera;
+pub mod kpp;
+
+/* ---------------------------- External imports ---------------------------- */
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Result, Context, bail};
+
+/* ---------------------------- Internal imports ---------------------------- */
+use crate::Env;
+use crate::reactions::SimSpecies;
+use crate::reactions::Metadata;
+use crate::reactions::compartments::same_namespace;
+use crate::reactions::k_reactions::ChemicalReaction;
+use crate::reactions::traits::{IsChemicalReaction, RawSpecies};
+
+/* -------------------------------------------------------------------------- */
+/*                         FUNCTION/STRUCT DEFINITIONS                        */
+/* -------------------------------------------------------------------------- */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Sbml,    // SBML Level 3 (COPASI, libRoadRunner...)
+    Cantera, // Cantera YAML input file
+    Kpp,     // KPP .eqn and .spc files
+}
+
+impl ExportFormat {
+    pub fn from_name(name:&str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "sbml" | "xml" => Some(ExportFormat::Sbml),
+            "cantera" | "yaml" => Some(ExportFormat::Cantera),
+            "kpp" => Some(ExportFormat::Kpp),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportFile {
+    pub extension: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Export {
+    pub files: Vec<ExportFile>,
+    pub warnings: Vec<String>,
+}
+
+impl Export {
+    // Write every file as `stem.extension`, e.g. out/mechanism.eqn
+    pub fn write(&self, stem:&Path) -> Result<Vec<PathBuf>> {
+        let mut out = vec![];
+        for file in self.files.iter() {
+            let path = stem.with_extension(&file.extension);
+            fs::write(&path, &file.content)
+                .with_context(|| format!("Cannot write {}", path.display()))?;
+            out.push(path);
+        }
+        Ok(out)
+    }
+}
+
+pub fn export(env:&Env, format:ExportFormat) -> Result<Export> {
+    let network = Network::new(env)?;
+    let export = match format {
+        ExportFormat::Sbml => sbml::export(&network),
+        ExportFormat::Cantera => cantera::export(&network, env),
+        ExportFormat::Kpp => kpp::export(&network),
+    };
+    Ok(export)
+}
+
+#[derive(Debug, Clone)]
+pub struct NetSpecies {
+    pub id: String,    // Identifier valid in every format
+    pub label: String, // Label in the reactions file
+    pub name: String,  // Display name from the registry
+    pub fixed: bool,
+    // Initial value of tracked species, value of fixed ones [mol/l]
+    pub concentration: f64,
+    pub metadata: Metadata, // Of the pKa for acid/base couples
+}
+
+// rate = k . Π [reactant]^n
+#[derive(Debug, Clone)]
+pub struct NetReaction {
+    pub label: String,
+    pub reactants: Vec<(usize, usize)>,
+    pub products: Vec<(usize, f64)>,
+    pub k: f64,
+    pub metadata: Metadata,
+}
+
+// d[species]/dt += kr . dose_rate
+#[derive(Debug, Clone)]
+pub struct NetSource {
+    pub species: usize,
+    pub kr: f64,
+    pub metadata: Metadata, // Of the G-value
+}
+
+#[derive(Debug, Clone)]
+pub struct NetBolus {
+    pub time: f64,
+    pub species: usize,
+    pub amount: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Network {
+    pub species: Vec<NetSpecies>,
+    pub reactions: Vec<NetReaction>,
+    pub sources: Vec<NetSource>,
+    pub boluses: Vec<NetBolus>,
+    // Issues common to all formats
+    pub warnings: Vec<String>,
+}
+
+impl Network {
+    pub fn new(env:&Env) -> Result<Self> {
+        let mut species: Vec<NetSpecies> = vec![];
+        let mut warnings = vec![];
+        // Label -> (species index, constant factor of its concentration)
+        let mut resolve: HashMap<String, (usize, f64)> = HashMap::new();
+        let push = |species:&mut Vec<NetSpecies>, label:String, fixed:bool, cc:f64| {
+            species.push(NetSpecies {
+                id: String::new(),
+                name: env.registry.display_name(&label),
+                label: label,
+                fixed: fixed,
+                concentration: cc,
+                metadata: Metadata::default(),
+            });
+            species.len() - 1
+        };
+
+        let mut couples = vec![];
+        for sp in env.species.iter() {
+            match sp {
+                SimSpecies::TrackedSpecies(s) => {
+                    let idx = push(&mut species, s.as_owned_str(), false, 0.0);
+                    resolve.insert(s.as_owned_str(), (idx, 1.0));
+                },
+                SimSpecies::ABCouple(ab) => {
+                    let idx = push(&mut species, ab.as_owned_str(), false, 0.0);
+                    species[idx].metadata = ab.metadata().clone();
+                    resolve.insert(ab.as_owned_str(), (idx, 1.0));
+                    couples.push((idx, ab));
+                },
+                SimSpecies::CstSpecies(c) => {
+                    if c.profile().is_some() {
+                        warnings.push(format!(
+                            "{} follows a time profile, exported with its value at t = 0",
+                            c.name()));
+                    }
+                    let idx = push(&mut species, c.as_owned_str(), true, c.cc_at(0.0));
+                    resolve.insert(c.as_owned_str(), (idx, 1.0));
+                },
+                SimSpecies::ABPartner(_) => {},
+            }
+        }
+        // Acid and base are constant fractions of the couple total
+        for (idx, ab) in couples {
+            let h_label = same_namespace(ab.acid_str(), "H_plus");
+            let h_plus = match resolve.get(&h_label) {
+                Some((h_idx, _)) => species[*h_idx].concentration,
+                None => bail!("{h_label} is always defined with the pH"),
+            };
+            let partition = ab.compute_partition(1.0, h_plus);
+            resolve.insert(ab.acid_str().clone(), (idx, partition.acid()));
+            resolve.insert(ab.base_str().clone(), (idx, partition.base()));
+        }
+        let find = |resolve:&HashMap<String, (usize, f64)>, label:&str|
+            resolve.get(label).cloned().with_context(|| format!("Unknown species {label}"));
+
+        for (sp, cc) in env.initial_cc.iter() {
+            // Declared but in no reaction, the simulation ignores it too
+            let idx = match resolve.get(sp.as_str()) {
+                Some((idx, _)) => *idx,
+                None => {
+                    warnings.push(format!("{sp} takes part in no reaction, not exported"));
+                    continue
+                },
+            };
+            if !species[idx].fixed {
+                species[idx].concentration += cc;
+            }
+        }
+
+        let mut reactions = vec![];
+        let mut sources = vec![];
+        for reaction in env.reactions.iter() {
+            match reaction {
+                ChemicalReaction::KReaction(r) => {
+                    let mut k = r.k_value();
+                    let mut reactants: Vec<(usize, usize)> = vec![];
+                    let mut products: Vec<(usize, f64)> = vec![];
+                    // Both partners of a couple count for the couple
+                    for (sp, n) in r.iter_reactants() {
+                        let (idx, factor) = find(&resolve, sp.as_str())?;
+                        k *= factor / *n as f64;
+                        match reactants.iter_mut().find(|(i, _)| *i == idx) {
+                            Some((_, m)) => *m += 1,
+                            None => reactants.push((idx, 1)),
+                        }
+                    }
+                    for (sp, _) in r.iter_products() {
+                        let idx = product_index(&mut species, &mut resolve, sp.as_str(),
+                                                env.registry.display_name(sp.as_str()));
+                        match products.iter_mut().find(|(i, _)| *i == idx) {
+                            Some((_, m)) => *m += 1.0,
+                            None => products.push((idx, 1.0)),
+                        }
+                    }
+                    if r.iter_reactants().chain(r.iter_products()).any(|(_, n)| *n > 1) {
+                        warnings.push(format!(
+                            "{}: exported as simulated, with the rate k.[X]/n and \
+                             coefficients of 1", r));
+                    }
+                    reactions.push(NetReaction {
+                        label: format!("{}", r),
+                        reactants: reactants,
+                        products: products,
+                        k: k,
+                        metadata: r.metadata().clone(),
+                    });
+                },
+                ChemicalReaction::Transfer(r) => {
+                    let (from, factor) = find(&resolve, r.from())?;
+                    let (to, _) = find(&resolve, r.to())?;
+                    reactions.push(NetReaction {
+                        label: format!("{}", r),
+                        reactants: vec![(from, 1)],
+                        products: vec![(to, r.volume_ratio())],
+                        k: r.k_value() * factor,
+                        metadata: Metadata::default(),
+                    });
+                },
+                ChemicalReaction::Radiolytic(r) => {
+                    for sp in r.products() {
+                        let idx = product_index(&mut species, &mut resolve, sp,
+                                                env.registry.display_name(sp));
+                        sources.push(NetSource {
+                            species: idx,
+                            kr: r.kr(),
+                            metadata: r.metadata().clone(),
+                        });
+                    }
+                },
+            }
+        }
+
+        let mut boluses = vec![];
+        for bolus in env.boluses.iter() {
+            let (idx, _) = find(&resolve, &bolus.species)?;
+            boluses.push(NetBolus { time: bolus.time, species: idx, amount: bolus.amount });
+        }
+
+        assign_ids(&mut species);
+        Ok(Self { species, reactions, sources, boluses, warnings })
+    }
+
+    pub fn iter_tracked(&self) -> impl Iterator<Item=&NetSpecies> {
+        self.species.iter().filter(|sp| !sp.fixed)
+    }
+    pub fn iter_fixed(&self) -> impl Iterator<Item=&NetSpecies> {
+        self.species.iter().filter(|sp| sp.fixed)
+    }
+}
+
+// Species only produced are not tracked by the simulation, as they have no
+// effect. They are kept in the exported network.
+fn product_index(species:&mut Vec<NetSpecies>, resolve:&mut HashMap<String, (usize, f64)>,
+                 label:&str, name:String) -> usize {
+    if let Some((idx, _)) = resolve.get(label) {
+        return *idx;
+    }
+    species.push(NetSpecies {
+        id: String::new(),
+        name: name,
+        label: label.to_string(),
+        fixed: false,
+        concentration: 0.0,
+        metadata: Metadata::default(),
+    });
+    resolve.insert(label.to_string(), (species.len() - 1, 1.0));
+    return species.len() - 1;
+}
+
+// Letters, digits and '_' only, not starting with a digit, all distinct
+fn assign_ids(species:&mut [NetSpecies]) {
+    let mut used: Vec<String> = vec![];
+    for sp in species.iter_mut() {
+        let mut id: String = sp.label.chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        if id.starts_with(|c:char| c.is_ascii_digit()) {
+            id.insert_str(0, "s_");
+        }
+        let base = id.clone();
+        let mut n = 2;
+        while used.contains(&id) {
+            id = format!("{base}_{n}");
+            n += 1;
+        }
+        used.push(id.clone());
+        sp.id = id;
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   TESTING                                  */
+/* -------------------------------------------------------------------------- */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Beam, ODESolver, State};
+    use crate::ode_solver::traits::System;
+    use crate::reactions::parse_reactions_file;
+
+    #[test]
+    fn couples_are_folded_in_rate_constants() {
+        let path = format!("{}/data/reactions.ron", env!("CARGO_MANIFEST_DIR"));
+        let env = parse_reactions_file(&path).unwrap();
+        let network = Network::new(&env).unwrap();
+        // Plus the species only produced, e.g. O2_minus_minus
+        assert!(network.iter_tracked().any(|sp| sp.label == "O2_minus_minus"));
+        assert!(network.iter_tracked().count() > env.number_of_tracked_species());
+
+        // e_aq + O2_r_minus -> ... only sees the base part of HO2_r/O2_r_minus
+        let idx = network.species.iter().position(|sp| sp.label == "HO2_r/O2_r_minus").unwrap();
+        assert_eq!(network.species[idx].id, "HO2_r_O2_r_minus");
+        let r = network.reactions.iter()
+            .find(|r| r.label == "e_aq + O2_r_minus -> O2_minus_minus")
+            .unwrap();
+        let h_plus = 1e-7;
+        let ka = 10_f64.powf(-4.9);
+        assert_float_relative_eq!(r.k, 1.3e10 / (1.0 + h_plus / ka), 1e-12);
+        assert!(r.reactants.contains(&(idx, 1)));
+        assert_eq!(network.sources.len(), env.bio_param.radiolytic.len());
+    }
+
+    #[test]
+    fn every_format_is_written() {
+        let path = format!("{}/data/reactions_vial.ron", env!("CARGO_MANIFEST_DIR"));
+        let env = parse_reactions_file(&path).unwrap();
+        let network = Network::new(&env).unwrap();
+
+        let sbml = export(&env, ExportFormat::Sbml).unwrap();
+        let n = network.reactions.len() + network.sources.len();
+        assert_eq!(sbml.files[0].content.matches("<reaction ").count(), n);
+        assert!(sbml.warnings.iter().any(|w| w.contains("dose_rate")));
+
+        let kpp = export(&env, ExportFormat::Kpp).unwrap();
+        let eqn = &kpp.files.iter().find(|f| f.extension == "eqn").unwrap().content;
+        assert_eq!(eqn.matches(" : ").count(), n);
+
+        let cantera = export(&env, ExportFormat::Cantera).unwrap();
+        assert!(cantera.files[0].content.contains("kinetics: bulk"));
+        assert!(cantera.warnings.iter().any(|w| w.contains("Radiolytic")));
+    }
+
+    #[test]
+    fn kpp_files_are_read_back() {
+        let path = format!("{}/data/reactions_simple.ron", env!("CARGO_MANIFEST_DIR"));
+        let env = parse_reactions_file(&path).unwrap();
+        let kpp = export(&env, ExportFormat::Kpp).unwrap();
+        // Species and equations in one file
+        let out = crate::test_dir("kpp_export").join("mechanism.kpp");
+        fs::write(&out, kpp.files.iter().map(|f| f.content.as_str()).collect::<String>()).unwrap();
+        let again = parse_reactions_file(out.to_str().unwrap()).unwrap();
+
+        let (network, read) = (Network::new(&env).unwrap(), Network::new(&again).unwrap());
+        for (r, back) in network.reactions.iter().zip(read.reactions.iter()) {
+            assert_float_relative_eq!(back.k, r.k, 1e-12);
+        }
+        for sp in network.species.iter() {
+            let back = read.species.iter().find(|b| b.label == sp.label).unwrap();
+            assert_eq!(back.fixed, sp.fixed);
+            assert_float_relative_eq!(back.concentration, sp.concentration, 1e-12);
+        }
+    }
+
+    #[test]
+    fn network_follows_the_simulated_law() {
+        let path = format!("{}/data/reactions.ron", env!("CARGO_MANIFEST_DIR"));
+        let env = parse_reactions_file(&path).unwrap();
+        let network = Network::new(&env).unwrap();
+        let r = network.reactions.iter()
+            .find(|r| r.label == "2 e_aq -> H2 + 2 OH_minus")
+            .unwrap();
+        assert_eq!(r.reactants.len(), 1);
+        assert_eq!(r.reactants[0].1, 1);
+        assert_float_relative_eq!(r.k, 1.1e10 / 2.0, 1e-12);
+        assert!(r.products.iter().all(|(_, n)| *n == 1.0));
+
+        // Same derivatives as the solver, without beam
+        let labels = env.species_label();
+        let y = State::from_fn(labels.len(), |i, _| 1.0 + (i as f64 * 7.3).sin().abs());
+        let mut cc: Vec<f64> = network.species.iter().map(|sp| sp.concentration).collect();
+        for (i, label) in labels.iter().enumerate() {
+            let idx = network.species.iter().position(|sp| &sp.label == label).unwrap();
+            cc[idx] = y[i] * 1e-6;
+        }
+        let mut dcc = vec![0.0; cc.len()];
+        for r in network.reactions.iter() {
+            let rate = r.reactants.iter().fold(r.k, |acc, (i, n)| acc * cc[*i].powi(*n as i32));
+            r.reactants.iter().for_each(|(i, n)| dcc[*i] -= *n as f64 * rate);
+            r.products.iter().for_each(|(i, n)| dcc[*i] += n * rate);
+        }
+        let chem = ODESolver::new(env, Beam::new_constant(String::from("e"), 0.0).unwrap());
+        let mut dy = State::zeros(labels.len());
+        chem.system(0.0, &y, &mut dy);
+        for (i, label) in labels.iter().enumerate() {
+            let idx = network.species.iter().position(|sp| &sp.label == label).unwrap();
+            assert_float_relative_eq!(dcc[idx] * 1e6, dy[i], 1e-9);
+        }
+    }
+}
@@ -0,0 +1,153 @@
+/* ---------------------------- External imports ---------------------------- */
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+
+/* ---------------------------- Internal imports ---------------------------- */
+use super::{Export, ExportFile, Network};
+use crate::Env;
+use crate::reactions::composition::Composition;
+
+/* -------------------------------------------------------------------------- */
+/*                         FUNCTION/STRUCT DEFINITIONS                        */
+/* -------------------------------------------------------------------------- */
+// Cantera element of the electrons, charge = -E
+const ELECTRON: &str = "E";
+
+// Cantera YAML input with a single ideal-condensed phase in mol, dm (l)
+// and s. All species share the molar volume 1/Σ[X], so the mole fractions
+// of the initial state give back the initial concentrations.
+pub fn export(network:&Network, env:&Env) -> Export {
+    let mut warnings = network.warnings.clone();
+    // Couples are written with the composition of their acid
+    let acids: HashMap<String, String> = env.iter_ABCouples()
+        .map(|ab| (ab.as_owned_str(), ab.acid_str().clone()))
+        .collect();
+
+    let mut compositions = vec![];
+    let mut elements = BTreeSet::new();
+    for sp in network.species.iter() {
+        let label = acids.get(&sp.label).unwrap_or(&sp.label);
+        let comp = match env.registry.composition(label) {
+            Some(comp) => comp,
+            None => {
+                warnings.push(format!(
+                    "{}: unknown composition, written empty (declare it in `species`)",
+                    sp.label));
+                Composition::default()
+            },
+        };
+        elements.extend(comp.iter_elements().map(|(el, _)| el.clone()));
+        if comp.charge() != 0 {
+            elements.insert(String::from(ELECTRON));
+        }
+        compositions.push(comp);
+    }
+    if !acids.is_empty() {
+        warnings.push(String::from(
+            "Acid/base couples are single species with the composition of their acid"));
+    }
+
+    let total: f64 = network.species.iter().map(|sp| sp.concentration).sum();
+    let molar_volume = match total > 0.0 {
+        true => 1.0 / total,
+        false => 1.0,
+    };
+
+    let mut yaml = String::from("description: Resolved radiobio mechanism\n\n");
+    yaml.push_str("units: {length: dm, quantity: mol, time: s, activation-energy: J/mol}\n\n");
+    yaml.push_str("phases:\n");
+    yaml.push_str("- name: solution\n");
+    yaml.push_str("  thermo: ideal-condensed\n");
+    yaml.push_str("  standard-concentration-basis: unity\n");
+    let _ = writeln!(yaml, "  elements: [{}]",
+                     elements.iter().cloned().collect::<Vec<_>>().join(", "));
+    let _ = writeln!(yaml, "  species: [{}]",
+                     network.species.iter().map(|sp| sp.id.as_str()).collect::<Vec<_>>().join(", "));
+    yaml.push_str("  kinetics: bulk\n");
+    yaml.push_str("  reactions: all\n");
+    let fractions: Vec<String> = network.species.iter()
+        .filter(|sp| sp.concentration > 0.0)
+        .map(|sp| format!("{}: {:e}", sp.id, sp.concentration / total))
+        .collect();
+    let _ = writeln!(yaml, "  state: {{T: 298.15, P: 1 atm, X: {{{}}}}}", fractions.join(", "));
+
+    yaml.push_str("\nspecies:\n");
+    for (sp, comp) in network.species.iter().zip(compositions.iter()) {
+        let mut parts: Vec<String> = comp.iter_elements()
+            .filter(|(_, n)| **n != 0)
+            .map(|(el, n)| format!("{el}: {n}"))
+            .collect();
+        if comp.charge() != 0 {
+            parts.push(format!("{ELECTRON}: {}", -comp.charge()));
+        }
+        let _ = writeln!(yaml, "- name: {}", sp.id);
+        let note = match sp.metadata.is_empty() {
+            true => sp.label.clone(),
+            false => format!("{}, {}", sp.label, sp.metadata),
+        };
+        let _ = writeln!(yaml, "  note: {}", quoted(&note));
+        let _ = writeln!(yaml, "  composition: {{{}}}", parts.join(", "));
+        yaml.push_str("  thermo: {model: constant-cp, T0: 298.15, h0: 0, s0: 0, cp0: 0}\n");
+        let _ = writeln!(yaml, "  equation-of-state: {{model: constant-volume, molar-volume: {:e}}}",
+                         molar_volume);
+    }
+
+    yaml.push_str("\nreactions:\n");
+    for r in network.reactions.iter() {
+        let reactants: Vec<String> = r.reactants.iter()
+            .map(|(i, n)| term(*n as f64, &network.species[*i].id))
+            .collect();
+        let products: Vec<String> = r.products.iter()
+            .map(|(i, n)| term(*n, &network.species[*i].id))
+            .collect();
+        let balance = r.reactants.iter().map(|(i, n)| (*i, -(*n as f64)))
+            .chain(r.products.iter().cloned())
+            .fold(HashMap::new(), |mut acc:HashMap<String, f64>, (i, n)| {
+                let comp = &compositions[i];
+                for (el, count) in comp.iter_elements() {
+                    *acc.entry(el.clone()).or_insert(0.0) += n * *count as f64;
+                }
+                *acc.entry(String::from(ELECTRON)).or_insert(0.0) -= n * comp.charge() as f64;
+                acc
+            });
+        if balance.values().any(|n| n.abs() > 1e-9) {
+            warnings.push(format!(
+                "{}: not balanced, Cantera will reject it", r.label));
+        }
+        let _ = writeln!(yaml, "- equation: {} => {}", reactants.join(" + "), products.join(" + "));
+        let _ = writeln!(yaml, "  rate-constant: {{A: {:e}, b: 0, Ea: 0}}", r.k);
+        if !r.metadata.is_empty() {
+            let _ = writeln!(yaml, "  note: {}", quoted(&r.metadata.to_string()));
+        }
+    }
+
+    let fixed: Vec<&str> = network.iter_fixed().map(|sp| sp.label.as_str()).collect();
+    if !fixed.is_empty() {
+        warnings.push(format!(
+            "Cantera has no constant species, {} evolve freely", fixed.join(", ")));
+    }
+    if !network.sources.is_empty() {
+        warnings.push(String::from(
+            "Radiolytic yields are not exported, Cantera has no dose rate sources"));
+    }
+    for bolus in network.boluses.iter() {
+        warnings.push(format!(
+            "Bolus of {} at t = {} s not exported, Cantera has no events",
+            network.species[bolus.species].label, bolus.time));
+    }
+    Export {
+        files: vec![ExportFile { extension: String::from("yaml"), content: yaml }],
+        warnings: warnings,
+    }
+}
+
+fn quoted(text:&str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn term(n:f64, id:&str) -> String {
+    match n == 1.0 {
+        true => id.to_string(),
+        false => format!("{n} {id}"),
+    }
+}
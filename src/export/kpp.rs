@@ -0,0 +1,91 @@
+/* ---------------------------- External imports ---------------------------- */
+use std::fmt::Write;
+
+/* ---------------------------- Internal imports ---------------------------- */
+use super::{Export, ExportFile, Network};
+use crate::units::MOLECULES_PER_CM3;
+
+/* -------------------------------------------------------------------------- */
+/*                         FUNCTION/STRUCT DEFINITIONS                        */
+/* -------------------------------------------------------------------------- */
+// Fixed species standing for the dose rate: G + DOSE = DOSE + X : kr
+const DOSE: &str = "DOSE";
+
+// KPP species (.spc) and equations (.eqn) files, to include from a .def
+// file. Units are those of KPP mechanisms, molecule/cm3 and s: the
+// concentrations are written in mol/l with CFACTOR = N_A/1000, and a rate
+// constant with m reactants is divided by (N_A/1000)^(m-1). DOSE is scaled
+// by CFACTOR too, so the radiolytic yields keep their value.
+pub fn export(network:&Network) -> Export {
+    let mut warnings = network.warnings.clone();
+
+    let mut spc = String::from(
+        "{ Species of the radiobio mechanism, in mol/l: CFACTOR converts to molecule/cm3 }\n");
+    spc.push_str("#DEFVAR\n");
+    for sp in network.iter_tracked() {
+        let _ = writeln!(spc, "  {} = IGNORE; {{ {} }}", sp.id, sp.label);
+    }
+    spc.push_str("#DEFFIX\n");
+    for sp in network.iter_fixed() {
+        let _ = writeln!(spc, "  {} = IGNORE; {{ {} }}", sp.id, sp.label);
+    }
+    if !network.sources.is_empty() {
+        warnings.push(format!("{DOSE} is 0, set it to the dose rate of the beam [Gy/s]"));
+        let _ = writeln!(spc, "  {DOSE} = IGNORE; {{ dose rate [Gy/s] }}");
+    }
+    spc.push_str("#INITVALUES\n");
+    let _ = writeln!(spc, "  CFACTOR = {MOLECULES_PER_CM3:e};");
+    for sp in network.species.iter().filter(|sp| sp.concentration != 0.0) {
+        let _ = writeln!(spc, "  {} = {:e};", sp.id, sp.concentration);
+    }
+    if !network.sources.is_empty() {
+        let _ = writeln!(spc, "  {DOSE} = 0;");
+    }
+
+    let mut eqn = String::from(
+        "{ Reactions of the radiobio mechanism, k in (cm3/molecule)^(m-1)/s for m reactants }\n");
+    eqn.push_str("#EQUATIONS\n");
+    for (idx, r) in network.reactions.iter().enumerate() {
+        // Reactants are repeated, products may have fractional coefficients
+        let reactants: Vec<&str> = r.reactants.iter()
+            .flat_map(|(i, n)| std::iter::repeat_n(network.species[*i].id.as_str(), *n))
+            .collect();
+        let products: Vec<String> = r.products.iter()
+            .map(|(i, n)| match *n == 1.0 {
+                true => network.species[*i].id.clone(),
+                false => format!("{} {}", n, network.species[*i].id),
+            })
+            .collect();
+        let k = r.k / MOLECULES_PER_CM3.powi(reactants.len() as i32 - 1);
+        let comment = match r.metadata.is_empty() {
+            true => r.label.clone(),
+            false => format!("{}; {}", r.label, r.metadata),
+        };
+        let _ = writeln!(eqn, "  <R{}> {} = {} : {:e}; {{ {} }}",
+                         idx+1, reactants.join(" + "), products.join(" + "), k,
+                         comment.replace(['{', '}'], ""));
+    }
+    for (idx, source) in network.sources.iter().enumerate() {
+        let _ = write!(eqn, "  <G{}> {DOSE} = {DOSE} + {} : {:e};",
+                       idx+1, network.species[source.species].id, source.kr);
+        match source.metadata.is_empty() {
+            true => eqn.push('\n'),
+            false => {
+                let _ = writeln!(eqn, " {{ {} }}", source.metadata.to_string().replace(['{', '}'], ""));
+            },
+        }
+    }
+
+    for bolus in network.boluses.iter() {
+        warnings.push(format!(
+            "Bolus of {} at t = {} s not exported, KPP has no events",
+            network.species[bolus.species].label, bolus.time));
+    }
+    Export {
+        files: vec![
+            ExportFile { extension: String::from("spc"), content: spc },
+            ExportFile { extension: String::from("eqn"), content: eqn },
+        ],
+        warnings: warnings,
+    }
+}
@@ -0,0 +1,167 @@
+/* ---------------------------- External imports ---------------------------- */
+use std::fmt::Write;
+
+/* ---------------------------- Internal imports ---------------------------- */
+use super::{Export, ExportFile, Network};
+use crate::reactions::Metadata;
+
+/* -------------------------------------------------------------------------- */
+/*                         FUNCTION/STRUCT DEFINITIONS                        */
+/* -------------------------------------------------------------------------- */
+const COMPARTMENT: &str = "solution";
+const DOSE_RATE: &str = "dose_rate";
+
+// SBML Level 3 Version 2 model in mol, l and s. Fixed species are boundary
+// species and boluses are events.
+pub fn export(network:&Network) -> Export {
+    let mut warnings = network.warnings.clone();
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<sbml xmlns=\"http://www.sbml.org/sbml/level3/version2/core\" \
+                  level=\"3\" version=\"2\">\n");
+    xml.push_str("  <model id=\"radiobio\" substanceUnits=\"mole\" timeUnits=\"second\" \
+                  volumeUnits=\"litre\" extentUnits=\"mole\">\n");
+
+    xml.push_str("    <listOfCompartments>\n");
+    let _ = writeln!(xml, "      <compartment id=\"{COMPARTMENT}\" spatialDimensions=\"3\" \
+                           size=\"1\" constant=\"true\"/>");
+    xml.push_str("    </listOfCompartments>\n");
+
+    xml.push_str("    <listOfSpecies>\n");
+    for sp in network.species.iter() {
+        let _ = write!(xml, "      <species id=\"{}\" name=\"{}\" compartment=\"{COMPARTMENT}\" \
+                              initialConcentration=\"{:e}\" hasOnlySubstanceUnits=\"false\" \
+                              boundaryCondition=\"{}\" constant=\"{}\"",
+                       sp.id, escape(&sp.name), sp.concentration, sp.fixed, sp.fixed);
+        match sp.metadata.is_empty() {
+            true => xml.push_str("/>\n"),
+            false => {
+                xml.push_str(">\n");
+                write_notes(&mut xml, &sp.metadata);
+                xml.push_str("      </species>\n");
+            },
+        }
+    }
+    xml.push_str("    </listOfSpecies>\n");
+
+    // Radiolytic sources are proportional to the dose rate [Gy/s]
+    if !network.sources.is_empty() {
+        warnings.push(format!(
+            "The {DOSE_RATE} parameter is 0, set it to the dose rate of the beam [Gy/s]"));
+        xml.push_str("    <listOfParameters>\n");
+        let _ = writeln!(xml, "      <parameter id=\"{DOSE_RATE}\" value=\"0\" constant=\"false\"/>");
+        xml.push_str("    </listOfParameters>\n");
+    }
+
+    xml.push_str("    <listOfReactions>\n");
+    for (idx, r) in network.reactions.iter().enumerate() {
+        let name = match r.metadata.label.is_empty() {
+            true => &r.label,
+            false => &r.metadata.label,
+        };
+        let _ = writeln!(xml, "      <reaction id=\"R{}\" name=\"{}\" reversible=\"false\">",
+                         idx+1, escape(name));
+        write_notes(&mut xml, &r.metadata);
+        let reactants: Vec<(&str, f64)> = r.reactants.iter()
+            .map(|(i, n)| (network.species[*i].id.as_str(), *n as f64))
+            .collect();
+        let products: Vec<(&str, f64)> = r.products.iter()
+            .map(|(i, n)| (network.species[*i].id.as_str(), *n))
+            .collect();
+        write_references(&mut xml, "listOfReactants", &reactants);
+        write_references(&mut xml, "listOfProducts", &products);
+
+        let mut factors = vec![ci(COMPARTMENT), ci("k")];
+        for (i, n) in r.reactants.iter() {
+            let id = &network.species[*i].id;
+            factors.push(match n {
+                1 => ci(id),
+                _ => format!("<apply><power/>{}<cn type=\"integer\"> {} </cn></apply>", ci(id), n),
+            });
+        }
+        write_kinetic_law(&mut xml, &factors, ("k", r.k));
+        xml.push_str("      </reaction>\n");
+    }
+    for (idx, source) in network.sources.iter().enumerate() {
+        let sp = &network.species[source.species];
+        let _ = writeln!(xml, "      <reaction id=\"G{}\" name=\"radiolysis -> {}\" \
+                               reversible=\"false\">", idx+1, escape(&sp.label));
+        write_notes(&mut xml, &source.metadata);
+        write_references(&mut xml, "listOfProducts", &[(sp.id.as_str(), 1.0)]);
+        write_kinetic_law(&mut xml, &[ci(COMPARTMENT), ci("kr"), ci(DOSE_RATE)],
+                          ("kr", source.kr));
+        xml.push_str("      </reaction>\n");
+    }
+    xml.push_str("    </listOfReactions>\n");
+
+    // Boluses add an amount to the concentration at a given time
+    if !network.boluses.is_empty() {
+        xml.push_str("    <listOfEvents>\n");
+        for (idx, bolus) in network.boluses.iter().enumerate() {
+            let id = &network.species[bolus.species].id;
+            let _ = writeln!(xml, "      <event id=\"bolus{}\" useValuesFromTriggerTime=\"true\">",
+                             idx+1);
+            let _ = writeln!(xml, "        <trigger initialValue=\"false\" persistent=\"true\">");
+            let _ = writeln!(xml, "          <math xmlns=\"http://www.w3.org/1998/Math/MathML\">\
+                                   <apply><geq/><csymbol encoding=\"text\" \
+                                   definitionURL=\"http://www.sbml.org/sbml/symbols/time\"> t \
+                                   </csymbol><cn> {} </cn></apply></math>", bolus.time);
+            let _ = writeln!(xml, "        </trigger>");
+            let _ = writeln!(xml, "        <listOfEventAssignments>");
+            let _ = writeln!(xml, "          <eventAssignment variable=\"{id}\">");
+            let _ = writeln!(xml, "            <math xmlns=\"http://www.w3.org/1998/Math/MathML\">\
+                                   <apply><plus/>{}<cn> {} </cn></apply></math>",
+                             ci(id), bolus.amount);
+            let _ = writeln!(xml, "          </eventAssignment>");
+            let _ = writeln!(xml, "        </listOfEventAssignments>");
+            let _ = writeln!(xml, "      </event>");
+        }
+        xml.push_str("    </listOfEvents>\n");
+    }
+
+    xml.push_str("  </model>\n");
+    xml.push_str("</sbml>\n");
+    Export {
+        files: vec![ExportFile { extension: String::from("xml"), content: xml }],
+        warnings: warnings,
+    }
+}
+
+// Reference, uncertainty and notes as XHTML notes
+fn write_notes(xml:&mut String, metadata:&Metadata) {
+    if metadata.is_empty() { return; }
+    let _ = writeln!(xml, "        <notes><body xmlns=\"http://www.w3.org/1999/xhtml\">\
+                           <p>{}</p></body></notes>", escape(&metadata.to_string()));
+}
+
+fn write_references(xml:&mut String, list:&str, species:&[(&str, f64)]) {
+    if species.is_empty() { return; }
+    let _ = writeln!(xml, "        <{list}>");
+    for (id, n) in species.iter() {
+        let _ = writeln!(xml, "          <speciesReference species=\"{id}\" \
+                               stoichiometry=\"{n}\" constant=\"true\"/>");
+    }
+    let _ = writeln!(xml, "        </{list}>");
+}
+
+// Product of the factors, with the rate constant as a local parameter
+fn write_kinetic_law(xml:&mut String, factors:&[String], (name, value):(&str, f64)) {
+    let _ = writeln!(xml, "        <kineticLaw>");
+    let _ = writeln!(xml, "          <listOfLocalParameters>");
+    let _ = writeln!(xml, "            <localParameter id=\"{name}\" value=\"{value:e}\"/>");
+    let _ = writeln!(xml, "          </listOfLocalParameters>");
+    let _ = writeln!(xml, "          <math xmlns=\"http://www.w3.org/1998/Math/MathML\">\
+                           <apply><times/>{}</apply></math>", factors.concat());
+    let _ = writeln!(xml, "        </kineticLaw>");
+}
+
+fn ci(id:&str) -> String {
+    format!("<ci> {id} </ci>")
+}
+
+fn escape(text:&str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -1,3 +1,5 @@
+// Explicit `return` and `field: field` initialisations are the house style.
+#![allow(clippy::needless_return, clippy::redundant_field_names)]
 /* -------------------------------------------------------------------------- */
 /*                             MODULE DEFINITIONS                             */
 /* -------------------------------------------------------------------------- */
@@ -5,100 +7,125 @@ pub mod reactions;
 pub mod physics;
 pub mod env;
 pub mod ode_solver;
+pub mod spatial;
+pub mod export;
+pub mod units;
+pub mod simulation;
+pub mod analysis;
+pub mod sweep;
+pub mod comparison;
 
 /* -------------------------------------------------------------------------- */
 /* ---------------------------- External imports ---------------------------- */
+#[cfg(test)]
 #[macro_use]
 extern crate assert_float_eq;
 
 
-use anyhow::Context;
+use anyhow::{Context, Result, bail};
 
 /* ---------------------------- Internal imports ---------------------------- */
-use reactions::{traits::{IsTrackedSpecies}, SimSpecies};
-use reactions::k_reactions::ReactionRateIndex;
 use ode_solver::traits::{System};
+use env::compiled::CompiledNetwork;
 
 /* ------------------------------- Re-exports ------------------------------- */
 
 pub use env::{Env, State, Time};
 pub use physics::beam::{Beam, IsTimed};
+pub use physics::schedule::{Fraction, TreatmentSchedule};
+pub use spatial::{SpatialSolver, Domain, Boundary, Geometry};
+pub use simulation::{Simulation, SimulationConfig, Trajectory};
+pub use sweep::{Sweep, SweepConfig};
+pub use comparison::{Comparison, ComparisonConfig};
 
 /* -------------------------- Type/func definitions ------------------------- */
 
 
+// The Env is compiled at construction and cannot be changed afterwards
 pub struct ODESolver {
-    pub sim_env: Env,
-    pub beam: Beam,
+    sim_env: Env,
+    pub schedule: TreatmentSchedule,
+    network: CompiledNetwork,
     dim: usize,
+    // Pulses are breakpoints up to this time
+    end_time: Time,
 }
 
 impl ODESolver {
+    // Beam ON for the whole simulation
     pub fn new(env:Env, beam:Beam) -> Self {
+        ODESolver::new_with_schedule(env, TreatmentSchedule::single(beam))
+    }
+    pub fn new_with_schedule(env:Env, schedule:TreatmentSchedule) -> Self {
         let dim = env.number_of_tracked_species();
-        Self { sim_env: env,
-               beam: beam,
+        Self { network: CompiledNetwork::new(&env),
+               sim_env: env,
+               schedule: schedule,
                dim: dim,
+               end_time: Time::NEG_INFINITY,
              }
     }
     pub fn dimension(&self) -> usize { self.dim }
+    pub fn env(&self) -> &Env { &self.sim_env }
+    // Needed for the solvers to stop at the edges of the beam pulses
+    pub fn set_end_time(&mut self, end_time:Time) {
+        self.end_time = end_time;
+    }
 }
 
 impl System<State> for ODESolver {
     fn system(&self, t: Time, y: &State, dy: &mut State) {
-
-        // Get the dose_rate for the time t:
-        let dr = self.beam.at(t).dose_rate();
-
-        // Some print for debug only
-        //println!("System call at {t:.2e} ==> Dose Rate: {dr}");
-        //println!("Species labels: {:?}", self.sim_env.species_label());
-        //println!("\ty\t=> {:?}", y);
-        // Create a HashMap<species,f64> with the current cc + /!\ Acid/Base
-        let sp_cc = self.sim_env.mapped_cc_species(y);
-        // First compute production rate values from reaction list
-        let reaction_values: Vec<f64> = self.sim_env
-            .compute_chemical_reactions(&sp_cc, dr)
-            .with_context(||format!("Failure occurs at t = {t}"))
+        self.try_system(t, y, dy)
             .expect("Oupsy, something went wrong with reaction values");
+    }
+
+    fn try_system(&self, t: Time, y: &State, dy: &mut State) -> Result<()> {
 
-        // Compute new concentration values:
-        for sim_sp in self.sim_env.iter_tracked_species() {
-            let mut kreaction_idx = vec![];
-            #[allow(unused_assignments)]
-            let mut sp_idx:usize = 0;
-            match sim_sp {
-                SimSpecies::TrackedSpecies(sp) => {
-                    kreaction_idx.extend(sp.iter_kreaction_indexes());
-                    sp_idx = sp.index();
-                },
-                SimSpecies::ABCouple(ab) => {
-                    kreaction_idx.extend(ab.iter_kreaction_indexes());
-                    sp_idx = ab.index();
-                },
-                _ => continue,
-            }
-
-            //dy[sp_idx] = 0_f64;
-            //println!("Species: {}", sim_sp.as_owned_str());
-            for rr_idx in kreaction_idx {
-                match rr_idx {
-                    ReactionRateIndex::Consumption(idx) => {
-                        dy[sp_idx] -= reaction_values[*idx];
-                        //println!(" - Reaction: {:?} ==> {:?}", self.sim_env.reactions[*idx], reaction_values[*idx]);
-                    },
-                    ReactionRateIndex::Production(idx) => {
-                        dy[sp_idx] += reaction_values[*idx];
-                        //println!(" + Reaction: {:?} ==> {:?}", self.sim_env.reactions[*idx], reaction_values[*idx]);
-                    },
-                }
-            }
-            // Convert [mol] / [l] to [µ-mol] / [l]
-            dy[sp_idx] *= 1e6;
-            //println!("\n");
+        // Get the dose_rate for the time t:
+        let dr = self.schedule.at(t).dose_rate();
+
+        // Reactions, acid/base partition and unit conversion are all
+        // handled by the compiled network
+        self.network
+            .evaluate(t, dr, y, dy)
+            .with_context(||format!("Failure occurs at t = {t}"))?;
+
+        if let Some(idx) = dy.iter().position(|val| !val.is_finite()) {
+            bail!("Non finite derivative ({}) for {} at t = {t}",
+                  dy[idx],
+                  self.sim_env.species_label()[idx]);
         }
-        //println!("\tdy/dt\t=> {:?}\n\n", dy);
-        //println!("\n\n\n");
+        Ok(())
+    }
+
+    fn breakpoints(&self) -> Vec<f64> {
+        let mut out = self.sim_env.breakpoints();
+        out.extend(self.schedule.breakpoints());
+        out.extend(self.schedule.pulse_edges(self.end_time));
+        return out;
+    }
+
+    fn apply_event(&self, t: Time, y: &mut State) {
+        self.sim_env.apply_boluses(t, y);
     }
 
-}
\ No newline at end of file
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   TESTING                                  */
+/* -------------------------------------------------------------------------- */
+// Empty directory for the files written by one test, unique to this test
+// and this process so that concurrent runs do not collide
+#[cfg(test)]
+pub(crate) fn test_dir(name:&str) -> std::path::PathBuf {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "radiobio-{}-{}-{name}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
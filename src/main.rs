@@ -1,100 +1,373 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use anyhow::{Context, Result, bail};
 
-#[allow(unused_imports)]
-use std::{fs::File, io::BufWriter, io::Write, path::Path};
-
-use radiobio::ode_solver::rk4::Rk4;
-use radiobio::reactions::parse_reactions_file;
-use radiobio::physics::beam::Beam;
-use radiobio::{ODESolver, Time, State};
-
-
-fn main() {
-    let reaction_file = format!(
-        "{}/data/reactions_simple.ron",
-        env!("CARGO_MANIFEST_DIR")
-    );
-
-    // Env is {reactions -> {acid_base   -> vec<AcidBase>,
-    //                      k_reactions -> vec<KReaction>
-    //                     },
-    //          species -> HashMap
-    //       }
-    let sim_env = parse_reactions_file(&reaction_file).unwrap();
-
-    let beam = Beam::new_constant(String::from("e"), 2.0).expect("");
-    //let beam = Beam::new_pulsed(String::from("e"), 1e6, 250e-6, 1e-6).expect("");
-
-    let sim = ODESolver::new( sim_env, beam );
-    let labels = sim.sim_env.species_label();
-    let y0 = sim.sim_env.get_initial_values();
-
-    // Debug of Sim:
-    for elt in sim.sim_env.species.iter() {
-        println!("{:?}", elt);
-    }
-
-    //std::process::exit(0);
-    /* ---------------------------------------------------------------------- */
-    //let mut stepper = Dopri5::new(sim, 0.0, 1e-3, 1e-10, y0, 1e-6, 1e-8);
-    //let mut stepper = Dop853::new(sim, 1e-9, 10e-6, 0.0, y0, 1e-16, 1e-16);
-    let mut stepper = Rk4::new(sim, 1e-9, y0, 100e-6, 0.5e-7);
-    let res = stepper.integrate();
-
-    // Handle result
-    match res {
-        Ok(stats) => {
-            println!("{}", stats);
-            let file = format!(
-                "{}/output/rk4.dat",
-                env!("CARGO_MANIFEST_DIR")
-            );
-            let path = Path::new(&file);
-            save(labels,
-            stepper.x_out(),
-            stepper.y_out(),
-            path);
-            println!("Results saved in: {:?}", path);
-        }
-        Err(e) => println!("An error occured: {}", e),
-    }
+use radiobio::{Comparison, Env, Simulation, Sweep};
+use radiobio::analysis::write_metrics;
+use radiobio::export::{export, ExportFormat};
+use radiobio::reactions::{parse_reactions_file, validate_reactions_file, SimSpecies};
+use radiobio::reactions::k_reactions::{ChemicalReaction, ReactionRateIndex};
+use radiobio::reactions::traits::IsChemicalReaction;
+use radiobio::units::ConcentrationUnit;
+
+
+const USAGE: &str = "\
+Usage: radiobio <command> [options]
+
+Commands:
+  run <config> [--output <path>]
+        Run the simulation described by a configuration file
+  sweep <config> [--output <path>]
+        Run a simulation over a grid of parameters, exits with 1 if a run failed
+  compare <config> [--output <path>]
+        Deliver the same dose with a conventional and a FLASH beam, compare the outcomes
+  validate <reactions>
+        Check a reactions file, exits with 1 if it has errors
+  info <reactions>
+        Print the species, the reactions and the stoichiometric summary
+  export <reactions> --format <sbml|cantera|kpp> [--output <stem>]
+        Write the resolved mechanism for another kinetics tool
+  help
+        Print this message
+
+Exit codes: 0 on success, 1 if the command fails, 2 for invalid arguments";
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Run { config: String, output: Option<String> },
+    Sweep { config: String, output: Option<String> },
+    Compare { config: String, output: Option<String> },
+    Validate { reactions: String },
+    Info { reactions: String },
+    Export { reactions: String, format: ExportFormat, output: Option<String> },
+    Help,
 }
 
+// Wrong command line, reported with the usage
+#[derive(Debug, PartialEq)]
+struct UsageError(String);
 
-pub fn save(labels: Vec<String>, times: &Vec<Time>, states: &Vec<State>, filename: &Path) {
-    // Create or open file
-    let file = match File::create(filename) {
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match parse_args(&args) {
+        Ok(command) => command,
+        Err(UsageError(message)) => {
+            eprintln!("error: {message}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    match execute(command) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
         Err(e) => {
-            println!("Could not open file. Error: {:?}", e);
-            return;
+            eprintln!("error: {:#}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                              ARGUMENT PARSING                              */
+/* -------------------------------------------------------------------------- */
+fn parse_args(args:&[String]) -> Result<Command, UsageError> {
+    let (name, rest) = match args.split_first() {
+        Some((name, rest)) => (name.as_str(), rest),
+        None => return Err(UsageError(String::from("no command given"))),
+    };
+    let mut positional = vec![];
+    let mut format = None;
+    let mut output = None;
+    let mut iter = rest.iter();
+    while let Some(arg) = iter.next() {
+        // --option value, --option=value or -o value
+        let (option, inline) = match arg.split_once('=') {
+            Some((option, value)) if arg.starts_with("--") => (option, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = |option:&str| match inline.clone().or_else(|| iter.next().cloned()) {
+            Some(value) => Ok(value),
+            None => Err(UsageError(format!("{option} needs a value"))),
+        };
+        match option {
+            "-f" | "--format" => format = Some(value(option)?),
+            "-o" | "--output" => output = Some(value(option)?),
+            "-h" | "--help" => return Ok(Command::Help),
+            _ if option.starts_with('-') && option.len() > 1 =>
+                return Err(UsageError(format!("unknown option {option}"))),
+            _ => positional.push(arg.clone()),
         }
-        Ok(buf) => buf,
+    }
+
+    let file = |what:&str| match positional.as_slice() {
+        [file] => Ok(file.clone()),
+        [] => Err(UsageError(format!("{name} needs a {what} file"))),
+        _ => Err(UsageError(format!("{name} takes a single {what} file"))),
+    };
+    let no_option = |option:&str, given:&Option<String>| match given {
+        Some(_) => Err(UsageError(format!("{name} has no {option} option"))),
+        None => Ok(()),
+    };
+    match name {
+        "run" | "sweep" | "compare" => {
+            no_option("--format", &format)?;
+            let config = file("configuration")?;
+            match name {
+                "run" => Ok(Command::Run { config, output }),
+                "sweep" => Ok(Command::Sweep { config, output }),
+                _ => Ok(Command::Compare { config, output }),
+            }
+        },
+        "validate" | "info" => {
+            no_option("--format", &format)?;
+            no_option("--output", &output)?;
+            let reactions = file("reactions")?;
+            match name {
+                "validate" => Ok(Command::Validate { reactions }),
+                _ => Ok(Command::Info { reactions }),
+            }
+        },
+        "export" => {
+            let format = match format {
+                Some(format) => ExportFormat::from_name(&format).ok_or_else(|| UsageError(
+                    format!("unknown format {format}, expected sbml, cantera or kpp")))?,
+                None => return Err(UsageError(String::from("export needs a --format"))),
+            };
+            Ok(Command::Export { reactions: file("reactions")?, format, output })
+        },
+        "help" | "-h" | "--help" => Ok(Command::Help),
+        _ => Err(UsageError(format!("unknown command {name}"))),
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                  COMMANDS                                  */
+/* -------------------------------------------------------------------------- */
+// Ok(false) when the command ran but its result is a failure
+fn execute(command:Command) -> Result<bool> {
+    match command {
+        Command::Run { config, output } => run(&config, output),
+        Command::Sweep { config, output } => sweep(&config, output),
+        Command::Compare { config, output } => compare(&config, output),
+        Command::Validate { reactions } => validate(&reactions),
+        Command::Info { reactions } => info(&reactions),
+        Command::Export { reactions, format, output } => export_mechanism(&reactions, format, output),
+        Command::Help => {
+            println!("{USAGE}");
+            Ok(true)
+        },
+    }
+}
+
+fn run(config:&str, output:Option<String>) -> Result<bool> {
+    let sim = Simulation::from_file(config)?;
+    let trajectory = sim.run()?;
+    println!("{}", trajectory.stats);
+    let path = match output {
+        Some(path) => PathBuf::from(path),
+        None => sim.output_path(),
+    };
+    sim.write(&trajectory, &path)?;
+    println!("Results saved in: {}", path.display());
+    let metrics_path = path.with_extension("metrics.csv");
+    write_metrics(&sim.analyse(&trajectory)?, &metrics_path, sim.config.output.units)?;
+    println!("Metrics saved in: {}", metrics_path.display());
+    // Mechanism used, next to the results
+    let mechanism_path = path.with_extension("mechanism.ron");
+    sim.env.write_mechanism(&mechanism_path.to_string_lossy())?;
+    println!("Mechanism saved in: {}", mechanism_path.display());
+    Ok(true)
+}
+
+fn sweep(config:&str, output:Option<String>) -> Result<bool> {
+    let sweep = Sweep::from_file(config)?;
+    let points = sweep.points()?;
+    println!("{} runs on {} threads", points.len(), sweep.threads(points.len()));
+    let table = sweep.run()?;
+    for (idx, error) in table.iter_failures() {
+        eprintln!("error: run #{} ({}): {error}", idx+1, table.describe(&table.rows[idx]));
+    }
+    let path = match output {
+        Some(path) => PathBuf::from(path),
+        None => sweep.output_path(),
     };
-    let mut buf = BufWriter::new(file);
+    table.write(&path)?;
+    println!("Results saved in: {}", path.display());
+    Ok(table.iter_failures().count() == 0)
+}
 
-    // Write labels
-    write!(&mut buf, "{}", &labels[0]).unwrap();
-    if let Err(e) = buf.flush() {
-        println!("Could not write to file. Error: {:?}", e);
+fn compare(config:&str, output:Option<String>) -> Result<bool> {
+    let comparison = Comparison::from_file(config)?;
+    let report = comparison.run()?;
+    print!("{report}");
+    let path = match output {
+        Some(path) => PathBuf::from(path),
+        None => comparison.output_path(),
+    };
+    report.write(&path)?;
+    println!("Results saved in: {}", path.display());
+    Ok(true)
+}
+
+fn validate(reactions:&str) -> Result<bool> {
+    let report = validate_reactions_file(reactions)?;
+    if !report.is_empty() {
+        println!("{report}");
     }
+    let errors = report.iter_errors().count();
+    let warnings = report.iter_warnings().count();
+    println!("{reactions}: {errors} error(s), {warnings} warning(s)");
+    Ok(errors == 0)
+}
+
+fn info(reactions:&str) -> Result<bool> {
+    let env = parse_reactions_file(reactions)?;
+    let unit = ConcentrationUnit::Micromolar;
+    let labels = env.species_label();
+    let y0 = env.get_initial_values();
+    let fixed: Vec<&SimSpecies> = env.species.iter()
+        .filter(|sp| matches!(sp, SimSpecies::CstSpecies(_)))
+        .collect();
+    let count = |f:fn(&ChemicalReaction) -> bool| env.reactions.iter().filter(|r| f(r)).count();
 
-    for label in labels[1..].iter() {
-        write!(&mut buf, ", {}", label).unwrap();
+    println!("{reactions}");
+    println!("  {} tracked species ({} acid/base couples), {} fixed",
+             labels.len(), env.iter_ABCouples().count(), fixed.len());
+    println!("  {} k reactions, {} radiolytic yields, {} transfers",
+             count(|r| matches!(r, ChemicalReaction::KReaction(_))),
+             count(|r| matches!(r, ChemicalReaction::Radiolytic(_))),
+             count(|r| matches!(r, ChemicalReaction::Transfer(_))));
+
+    println!("\nTracked species     initial [{}]  consumed  produced  G-value", unit.symbol());
+    for sp in env.iter_tracked_species() {
+        let tracked = sp.unwrap_tracked()?;
+        let (mut consumed, mut produced, mut radiolytic) = (0, 0, false);
+        for rr_idx in tracked.iter_kreaction_indexes() {
+            match rr_idx {
+                ReactionRateIndex::Consumption(_) => consumed += 1,
+                ReactionRateIndex::Production(r) => match env.reactions[*r] {
+                    ChemicalReaction::Radiolytic(_) => radiolytic = true,
+                    _ => produced += 1,
+                },
+            }
+        }
+        let idx = tracked.index();
+        let note = match (produced, radiolytic, y0[idx] > 0.0) {
+            (0, false, false) => "  (never present)",
+            _ => "",
+        };
+        println!("  {:<18} {:>12.4e} {:>9} {:>9}  {:<7}{}",
+                 sp.as_owned_str(), unit.from_state(y0[idx]), consumed, produced,
+                 if radiolytic { "yes" } else { "no" }, note);
+    }
+    if !fixed.is_empty() {
+        println!("\nFixed species       value [{}]", unit.symbol());
+        for sp in fixed {
+            if let SimSpecies::CstSpecies(cst) = sp {
+                let profile = if cst.profile().is_some() { "  (time profile)" } else { "" };
+                println!("  {:<18} {:>12.4e}{}",
+                         cst.name(), unit.from_molar(cst.cc_at(0.0)), profile);
+            }
+        }
+    }
+    // Products never consumed are not simulated
+    let known: Vec<String> = env.species.iter().map(|sp| sp.as_owned_str()).collect();
+    let end_products: Vec<String> = env.list_all_products().into_iter()
+        .filter(|sp| !known.contains(sp))
+        .collect();
+    if !end_products.is_empty() {
+        println!("\nEnd products (not tracked): {}", end_products.join(", "));
     }
-    write!(&mut buf, "\n").unwrap();
 
-    if let Err(e) = buf.flush() {
-        println!("Could not write to file. Error: {:?}", e);
+    println!("\nReactions");
+    for (idx, reaction) in env.reactions.iter().enumerate() {
+        let text = match reaction {
+            ChemicalReaction::KReaction(r) => {
+                let metadata = match r.metadata().is_empty() {
+                    true => String::new(),
+                    false => format!("  [{}]", r.metadata()),
+                };
+                format!("{r}  k = {:e}{metadata}", r.k_value())
+            },
+            ChemicalReaction::Radiolytic(r) => {
+                let products: Vec<&str> = r.products().map(|s| s.as_str()).collect();
+                format!("radiolysis -> {}  kr = {:e}", products.join(" + "), r.kr())
+            },
+            ChemicalReaction::Transfer(r) => format!("{r}  k = {:e}", r.k_value()),
+        };
+        println!("  {:>3}. {}", idx+1, text);
     }
+    Ok(true)
+}
 
-    // Write time and state vector in a csv format
-    for (i, state) in states.iter().enumerate() {
-        buf.write_fmt(format_args!("{}", times[i])).unwrap();
-        for val in state.iter() {
-            buf.write_fmt(format_args!(", {}", val)).unwrap();
+fn export_mechanism(reactions:&str, format:ExportFormat, output:Option<String>)
+-> Result<bool> {
+    let env: Env = parse_reactions_file(reactions)?;
+    let exported = export(&env, format)?;
+    // Next to the working directory by default, never over the input
+    let stem = match output {
+        Some(stem) => PathBuf::from(stem),
+        None => PathBuf::from(Path::new(reactions).file_stem().unwrap_or_default()),
+    };
+    for file in exported.files.iter() {
+        let path = stem.with_extension(&file.extension);
+        if path.exists() && same_file(&path, Path::new(reactions)) {
+            bail!("{} would overwrite the reactions file, give another --output",
+                  path.display());
         }
-        buf.write_fmt(format_args!("\n")).unwrap();
     }
-    if let Err(e) = buf.flush() {
-        println!("Could not write to file. Error: {:?}", e);
+    for warning in exported.warnings.iter() {
+        eprintln!("warning: {warning}");
     }
-}
\ No newline at end of file
+    if let Some(dir) = stem.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Cannot create {}", dir.display()))?;
+    }
+    for path in exported.write(&stem)? {
+        println!("Written: {}", path.display());
+    }
+    Ok(true)
+}
+
+fn same_file(a:&Path, b:&Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   TESTING                                  */
+/* -------------------------------------------------------------------------- */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line:&str) -> Result<Command, UsageError> {
+        let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+        parse_args(&args)
+    }
+
+    #[test]
+    fn arguments_are_parsed() {
+        assert_eq!(parse("run sim.ron"),
+                   Ok(Command::Run { config: String::from("sim.ron"), output: None }));
+        assert_eq!(parse("run sim.ron --output=out.dat"),
+                   Ok(Command::Run { config: String::from("sim.ron"),
+                                     output: Some(String::from("out.dat")) }));
+        assert_eq!(parse("export r.ron -f KPP -o out/r"),
+                   Ok(Command::Export { reactions: String::from("r.ron"),
+                                        format: ExportFormat::Kpp,
+                                        output: Some(String::from("out/r")) }));
+        assert_eq!(parse("sweep grid.ron"),
+                   Ok(Command::Sweep { config: String::from("grid.ron"), output: None }));
+        assert_eq!(parse("compare -o out.csv flash.ron"),
+                   Ok(Command::Compare { config: String::from("flash.ron"),
+                                         output: Some(String::from("out.csv")) }));
+        assert_eq!(parse("info --help"), Ok(Command::Help));
+        for wrong in ["", "simulate sim.ron", "run", "run a.ron b.ron", "validate r.ron -o x",
+                      "export r.ron", "export r.ron --format pdf", "run sim.ron --output",
+                      "run sim.ron --verbose"] {
+            assert!(parse(wrong).is_err(), "{wrong}");
+        }
+    }
+}
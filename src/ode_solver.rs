@@ -1,2 +1,4 @@
+pub mod dopri5;
 pub mod rk4;
+pub mod rosenbrock;
 pub mod traits;
\ No newline at end of file
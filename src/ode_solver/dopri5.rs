@@ -0,0 +1,435 @@
+//! Explicit Runge-Kutta method of order 5(4) of Dormand & Prince with adaptive step size and dense output of order 4.
+
+use super::traits::{integrate_adaptive, AdaptiveStep, Driver, IntegrationError, OutputType, Stats, System};
+
+use nalgebra::{allocator::Allocator, DefaultAllocator, Dim, OVector, Scalar};
+use num_traits::{One, Zero};
+use simba::scalar::{ClosedAdd, ClosedMul, ClosedNeg, ClosedSub, SubsetOf};
+
+// Butcher tableau
+const C2: f64 = 1. / 5.;
+const C3: f64 = 3. / 10.;
+const C4: f64 = 4. / 5.;
+const C5: f64 = 8. / 9.;
+const A21: f64 = 1. / 5.;
+const A31: f64 = 3. / 40.;
+const A32: f64 = 9. / 40.;
+const A41: f64 = 44. / 45.;
+const A42: f64 = -56. / 15.;
+const A43: f64 = 32. / 9.;
+const A51: f64 = 19372. / 6561.;
+const A52: f64 = -25360. / 2187.;
+const A53: f64 = 64448. / 6561.;
+const A54: f64 = -212. / 729.;
+const A61: f64 = 9017. / 3168.;
+const A62: f64 = -355. / 33.;
+const A63: f64 = 46732. / 5247.;
+const A64: f64 = 49. / 176.;
+const A65: f64 = -5103. / 18656.;
+const A71: f64 = 35. / 384.;
+const A73: f64 = 500. / 1113.;
+const A74: f64 = 125. / 192.;
+const A75: f64 = -2187. / 6784.;
+const A76: f64 = 11. / 84.;
+// Error estimation: difference between the orders 5 and 4
+const E1: f64 = 71. / 57600.;
+const E3: f64 = -71. / 16695.;
+const E4: f64 = 71. / 1920.;
+const E5: f64 = -17253. / 339200.;
+const E6: f64 = 22. / 525.;
+const E7: f64 = -1. / 40.;
+// Dense output
+const D1: f64 = -12715105075. / 11282082432.;
+const D3: f64 = 87487479700. / 32700410799.;
+const D4: f64 = -10690763975. / 1880347072.;
+const D5: f64 = 701980252875. / 199316789632.;
+const D6: f64 = -1453857185. / 822651844.;
+const D7: f64 = 69997945. / 29380423.;
+
+// Step size control
+const SAFETY: f64 = 0.9;
+const FAC_MIN: f64 = 0.2; // Largest decrease of the step size is 1/FAC_MIN
+const FAC_MAX: f64 = 10.0;
+const BETA: f64 = 0.04;
+
+/// Structure containing the parameters for the numerical integration.
+pub struct Dopri5<V, F>
+where
+    F: System<V>,
+{
+    f: F,
+    x: f64,
+    y: V,
+    rtol: f64,
+    atol: f64,
+    h: f64,
+    h_last: f64,
+    fac_old: f64,
+    rejected: bool,
+    driver: Driver<V>,
+    // Workspaces reused by every step
+    k: [V; 7],
+    y_next: V,
+    y_stage: V,
+    rcont: [V; 5],
+}
+
+impl<T, D: Dim, F> Dopri5<OVector<T, D>, F>
+where
+    f64: From<T>,
+    T: Copy + SubsetOf<f64> + Scalar + ClosedAdd + ClosedMul + ClosedSub + ClosedNeg + Zero + One,
+    F: System<OVector<T, D>>,
+    OVector<T, D>: std::ops::Mul<f64, Output = OVector<T, D>>,
+    DefaultAllocator: Allocator<T, D>,
+{
+    /// Default initializer for the structure
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Structure implementing the System<V> trait
+    /// * `x`       - Initial value of the independent variable (usually time)
+    /// * `x_end`   - Final value of the independent variable
+    /// * `dx`      - Increment in the dense output. Every accepted step is stored if dx = 0
+    /// * `y`       - Initial value of the dependent variable(s)
+    /// * `rtol`    - Relative tolerance used in the computation of the adaptive step size
+    /// * `atol`    - Absolute tolerance used in the computation of the adaptive step size
+    ///
+    pub fn new(f: F, x: f64, x_end: f64, dx: f64, y: OVector<T, D>, rtol: f64, atol: f64) -> Self {
+        let (rows, cols) = y.shape_generic();
+        let zeros = || OVector::zeros_generic(rows, cols);
+        Dopri5 {
+            k: std::array::from_fn(|_| zeros()),
+            y_next: zeros(),
+            y_stage: zeros(),
+            rcont: std::array::from_fn(|_| zeros()),
+            f,
+            x,
+            y,
+            rtol,
+            atol,
+            h: 0.0,
+            h_last: 0.0,
+            fac_old: 1e-4,
+            rejected: false,
+            driver: Driver::new(x_end, dx),
+        }
+    }
+
+    /// Largest number of steps, accepted or rejected, before the integration fails.
+    pub fn set_max_steps(&mut self, max_steps: u32) {
+        self.driver.max_steps = max_steps;
+    }
+
+    /// Core integration method, see `integrate_adaptive`.
+    pub fn integrate(&mut self) -> Result<Stats, IntegrationError> {
+        integrate_adaptive(self)
+    }
+
+    /// Evaluates the system at (x, y) in k[idx].
+    fn evaluate(&mut self, x: f64, idx: usize) -> Result<(), IntegrationError> {
+        self.k[idx].fill(T::zero());
+        self.driver.stats.num_eval += 1;
+        self.f
+            .try_system(x, &self.y, &mut self.k[idx])
+            .map_err(|error| IntegrationError::SystemFailure { x, error })
+    }
+
+    /// Evaluates the system at (x, y_stage) in k[idx].
+    fn evaluate_stage(&mut self, x: f64, idx: usize) -> Result<(), IntegrationError> {
+        self.k[idx].fill(T::zero());
+        self.driver.stats.num_eval += 1;
+        self.f
+            .try_system(x, &self.y_stage, &mut self.k[idx])
+            .map_err(|error| IntegrationError::SystemFailure { x, error })
+    }
+
+    /// y_stage = y + h * Σ a_i k_i
+    fn stage(&mut self, h: f64, coefs: &[(usize, f64)]) {
+        self.y_stage.copy_from(&self.y);
+        for (idx, a) in coefs {
+            self.y_stage.axpy(T::from_superset_unchecked(&(h * a)), &self.k[*idx], T::one());
+        }
+    }
+
+    /// Root mean square of v / (atol + rtol * max(|y|, |y_next|)).
+    fn norm(&self, v: &OVector<T, D>, y_next: Option<&OVector<T, D>>) -> f64 {
+        let n = v.len().max(1) as f64;
+        let sum: f64 = v
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let y = f64::from(self.y[i]).abs();
+                let scale = match y_next {
+                    Some(y_next) => y.max(f64::from(y_next[i]).abs()),
+                    None => y,
+                };
+                (f64::from(*value) / (self.atol + self.rtol * scale)).powi(2)
+            })
+            .sum();
+        (sum / n).sqrt()
+    }
+
+    /// Initial step size, following Hairer & Wanner. k[0] holds the derivative at x.
+    fn initial_step(&mut self, stop: f64) -> Result<f64, IntegrationError> {
+        let h_max = stop - self.x;
+        let dnf = self.norm(&self.k[0], None);
+        let dny = self.norm(&self.y, None);
+        let mut h = match dnf <= 1e-5 || dny <= 1e-5 {
+            true => 1e-6,
+            false => 0.01 * dny / dnf,
+        };
+        h = h.min(h_max);
+        // Explicit Euler step to estimate the second derivative
+        self.stage(h, &[(0, 1.0)]);
+        self.evaluate_stage(self.x + h, 1)?;
+        let mut diff = self.k[1].clone();
+        diff.axpy(-T::one(), &self.k[0], T::one());
+        let der2 = self.norm(&diff, None) / h;
+        let der12 = der2.max(dnf);
+        let h1 = match der12 <= 1e-15 {
+            true => (h * 1e-3).max(1e-6),
+            false => (0.01 / der12).powf(0.2),
+        };
+        Ok((100.0 * h).min(h1).min(h_max))
+    }
+
+    /// Computes a step of size h from (x, y) into y_next and returns the error estimate. k[0] holds the derivative at x.
+    fn step(&mut self, h: f64) -> Result<f64, IntegrationError> {
+        let x = self.x;
+        self.stage(h, &[(0, A21)]);
+        self.evaluate_stage(x + C2 * h, 1)?;
+        self.stage(h, &[(0, A31), (1, A32)]);
+        self.evaluate_stage(x + C3 * h, 2)?;
+        self.stage(h, &[(0, A41), (1, A42), (2, A43)]);
+        self.evaluate_stage(x + C4 * h, 3)?;
+        self.stage(h, &[(0, A51), (1, A52), (2, A53), (3, A54)]);
+        self.evaluate_stage(x + C5 * h, 4)?;
+        self.stage(h, &[(0, A61), (1, A62), (2, A63), (3, A64), (4, A65)]);
+        self.evaluate_stage(x + h, 5)?;
+        self.stage(h, &[(0, A71), (2, A73), (3, A74), (4, A75), (5, A76)]);
+        self.y_next.copy_from(&self.y_stage);
+        self.evaluate_stage(x + h, 6)?;
+
+        // Error estimate, reusing y_stage
+        self.y_stage.fill(T::zero());
+        for (idx, e) in [(0, E1), (2, E3), (3, E4), (4, E5), (5, E6), (6, E7)] {
+            self.y_stage.axpy(T::from_superset_unchecked(&(h * e)), &self.k[idx], T::one());
+        }
+        let err = self.norm(&self.y_stage, Some(&self.y_next));
+        match err.is_finite() {
+            true => Ok(err),
+            false => Ok(f64::MAX),
+        }
+    }
+
+    /// Moves to the end of an accepted step, preparing the dense output and the derivative of the next step.
+    fn accept(&mut self, h: f64, last: bool, stop: f64) -> Result<(), IntegrationError> {
+        let one = T::one();
+        let h_t = |v: f64| T::from_superset_unchecked(&(h * v));
+        if self.driver.out_type == OutputType::Dense {
+            // rcont[0] = y, rcont[1] = y_next - y
+            self.rcont[0].copy_from(&self.y);
+            self.rcont[1].copy_from(&self.y_next);
+            self.rcont[1].axpy(-one, &self.y, one);
+            // rcont[2] = h k1 - (y_next - y)
+            self.rcont[2].copy_from(&self.y);
+            self.rcont[2].axpy(-one, &self.y_next, one);
+            self.rcont[2].axpy(h_t(1.0), &self.k[0], one);
+            // rcont[3] = (y_next - y) - h k7 - rcont[2]
+            let two = T::from_superset_unchecked(&2.0);
+            self.rcont[3].fill(T::zero());
+            self.rcont[3].axpy(two, &self.y_next, one);
+            self.rcont[3].axpy(-two, &self.y, one);
+            self.rcont[3].axpy(h_t(-1.0), &self.k[0], one);
+            self.rcont[3].axpy(h_t(-1.0), &self.k[6], one);
+            // rcont[4] = h Σ d_i k_i
+            self.rcont[4].fill(T::zero());
+            for (idx, d) in [(0, D1), (2, D3), (3, D4), (4, D5), (5, D6), (6, D7)] {
+                self.rcont[4].axpy(h_t(d), &self.k[idx], one);
+            }
+        }
+        self.y.copy_from(&self.y_next);
+        self.h_last = h;
+        self.x = match last {
+            true => stop,
+            false => self.x + h,
+        };
+        // Negative concentrations are not physical
+        let mut clamped = false;
+        self.y.apply(|v| {
+            if f64::from(*v) < 0_f64 {
+                *v = T::zero();
+                clamped = true;
+            }
+        });
+        // First Same As Last, unless y was modified
+        match clamped {
+            true => self.evaluate(self.x, 0),
+            false => {
+                self.k.swap(0, 6);
+                Ok(())
+            }
+        }
+    }
+
+    /// Getter for the independent variable's output.
+    pub fn x_out(&self) -> &Vec<f64> {
+        &self.driver.x_out
+    }
+
+    /// Getter for the dependent variables' output.
+    pub fn y_out(&self) -> &Vec<OVector<T, D>> {
+        &self.driver.y_out
+    }
+}
+
+impl<T, D: Dim, F> AdaptiveStep<OVector<T, D>> for Dopri5<OVector<T, D>, F>
+where
+    f64: From<T>,
+    T: Copy + SubsetOf<f64> + Scalar + ClosedAdd + ClosedMul + ClosedSub + ClosedNeg + Zero + One,
+    F: System<OVector<T, D>>,
+    OVector<T, D>: std::ops::Mul<f64, Output = OVector<T, D>>,
+    DefaultAllocator: Allocator<T, D>,
+{
+    type F = F;
+
+    fn driver(&self) -> &Driver<OVector<T, D>> {
+        &self.driver
+    }
+
+    fn driver_mut(&mut self) -> &mut Driver<OVector<T, D>> {
+        &mut self.driver
+    }
+
+    fn system(&self) -> &F {
+        &self.f
+    }
+
+    fn x(&self) -> f64 {
+        self.x
+    }
+
+    fn y(&self) -> &OVector<T, D> {
+        &self.y
+    }
+
+    fn set_y(&mut self, y: OVector<T, D>) {
+        self.y = y;
+    }
+
+    fn h(&self) -> f64 {
+        self.h
+    }
+
+    fn start_segment(&mut self, stop: f64) -> Result<(), IntegrationError> {
+        self.evaluate(self.x, 0)?;
+        if self.h == 0.0 {
+            self.h = self.initial_step(stop)?;
+        }
+        Ok(())
+    }
+
+    fn try_step(&mut self, h: f64, last: bool, stop: f64) -> Result<bool, IntegrationError> {
+        let err = self.step(h)?;
+        let fac11 = err.powf(0.2 - BETA * 0.75);
+        let fac = (fac11 / self.fac_old.powf(BETA) / SAFETY).clamp(1.0 / FAC_MAX, 1.0 / FAC_MIN);
+        if err > 1.0 {
+            self.h = h / (1.0 / FAC_MIN).min(fac11 / SAFETY);
+            self.rejected = true;
+            return Ok(false);
+        }
+        self.accept(h, last, stop)?;
+        self.fac_old = err.max(1e-4);
+        // The step after a rejection is not allowed to increase.
+        // A step shortened to land on a stop keeps the proposal.
+        let h_new = match self.rejected {
+            true => (h / fac).min(h),
+            false => h / fac,
+        };
+        if !last || h_new > self.h {
+            self.h = h_new;
+        }
+        self.rejected = false;
+        Ok(true)
+    }
+
+    /// Dense output at x in the last accepted step.
+    fn interpolate(&self, x: f64) -> OVector<T, D> {
+        let x_old = self.x - self.h_last;
+        let theta = match self.h_last > 0.0 {
+            true => (x - x_old) / self.h_last,
+            false => 1.0,
+        };
+        let theta1 = 1.0 - theta;
+        let mut out = self.rcont[4].clone() * theta1;
+        out += &self.rcont[3];
+        out = out * theta;
+        out += &self.rcont[2];
+        out = out * theta1;
+        out += &self.rcont[1];
+        out = out * theta;
+        out += &self.rcont[0];
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::DVector;
+
+    // dy/dx = -y, with y doubled at x = 1
+    struct Decay;
+
+    impl System<DVector<f64>> for Decay {
+        fn system(&self, _x: f64, y: &DVector<f64>, dy: &mut DVector<f64>) {
+            dy[0] = -y[0];
+        }
+        fn breakpoints(&self) -> Vec<f64> {
+            vec![1.0]
+        }
+        fn apply_event(&self, _x: f64, y: &mut DVector<f64>) {
+            y[0] *= 2.0;
+        }
+    }
+
+    #[test]
+    fn adaptive_steps_follow_the_solution() {
+        let exact = |x: f64| match x <= 1.0 {
+            true => (-x).exp(),
+            false => 2.0 * (-x).exp(),
+        };
+        let y0 = DVector::from_element(1, 1.0);
+        let mut stepper = Dopri5::new(Decay, 0.0, 3.0, 0.25, y0.clone(), 1e-8, 1e-10);
+        let stats = stepper.integrate().unwrap();
+        assert!(stats.accepted_steps < 200);
+        assert_eq!(stepper.x_out().len(), 13);
+        for (x, y) in stepper.x_out().iter().zip(stepper.y_out().iter()) {
+            assert_float_relative_eq!(*x, (x / 0.25).round() * 0.25, 1e-12);
+            assert_float_relative_eq!(y[0], exact(*x), 1e-6);
+        }
+
+        // Every accepted step, with the event stored at x = 1
+        let mut stepper = Dopri5::new(Decay, 0.0, 3.0, 0.0, y0, 1e-8, 1e-10);
+        stepper.integrate().unwrap();
+        let at_event = stepper.x_out().iter().filter(|x| **x == 1.0).count();
+        assert_eq!(at_event, 2);
+        assert_float_relative_eq!(stepper.y_out().last().unwrap()[0], exact(3.0), 1e-6);
+
+        // Event at x_end, after the last dense sample
+        let y0 = DVector::from_element(1, 1.0);
+        let mut stepper = Dopri5::new(Decay, 0.0, 1.0, 0.5, y0, 1e-8, 1e-10);
+        stepper.integrate().unwrap();
+        assert_eq!(stepper.x_out(), &vec![0.0, 0.5, 1.0, 1.0]);
+        assert_float_relative_eq!(stepper.y_out()[3][0], 2.0 * exact(1.0), 1e-6);
+    }
+
+    #[test]
+    fn step_limit_is_configurable() {
+        let y0 = DVector::from_element(1, 1.0);
+        let mut stepper = Dopri5::new(Decay, 0.0, 3.0, 0.0, y0, 1e-8, 1e-10);
+        stepper.set_max_steps(5);
+        assert!(matches!(stepper.integrate(),
+                         Err(IntegrationError::MaxNumStepReached { n_step: 5, .. })));
+    }
+}
@@ -3,8 +3,7 @@
 use super::traits::{IntegrationError, Stats, System};
 
 use nalgebra::{allocator::Allocator, DefaultAllocator, Dim, OVector, Scalar};
-use nalgebra as na;
-use num_traits::Zero;
+use num_traits::{One, Zero};
 use simba::scalar::{ClosedAdd, ClosedMul, ClosedNeg, ClosedSub, SubsetOf};
 
 /// Structure containing the parameters for the numerical integration.
@@ -17,16 +16,18 @@ where
     y: V,
     x_end: f64,
     step_size: f64,
-    half_step: f64,
     x_out: Vec<f64>,
     y_out: Vec<V>,
     stats: Stats,
+    // Workspaces reused by every step
+    k: [V; 4],
+    y_stage: V,
 }
 
 impl<T, D: Dim, F> Rk4<OVector<T, D>, F>
 where
     f64: From<T>,
-    T: Copy + SubsetOf<f64> + Scalar + ClosedAdd + ClosedMul + ClosedSub + ClosedNeg + Zero,
+    T: Copy + SubsetOf<f64> + Scalar + ClosedAdd + ClosedMul + ClosedSub + ClosedNeg + Zero + One,
     F: System<OVector<T, D>>,
     OVector<T, D>: std::ops::Mul<f64, Output = OVector<T, D>>,
     DefaultAllocator: Allocator<T, D>,
@@ -42,13 +43,15 @@ where
     /// * `step_size`   - Step size used in the method
     ///
     pub fn new(f: F, x: f64, y: OVector<T, D>, x_end: f64, step_size: f64) -> Self {
+        let (rows, cols) = y.shape_generic();
         Rk4 {
+            k: std::array::from_fn(|_| OVector::zeros_generic(rows, cols)),
+            y_stage: OVector::zeros_generic(rows, cols),
             f,
             x,
             y,
             x_end,
             step_size,
-            half_step: step_size / 2.,
             x_out: Vec::new(),
             y_out: Vec::new(),
             stats: Stats::new(),
@@ -56,55 +59,117 @@ where
     }
 
     /// Core integration method.
+    ///
+    /// The integration stops exactly at the breakpoints of the system, applies
+    /// the corresponding event and restarts from there. When the event changes
+    /// the state, the point is stored twice (before and after the event). An
+    /// event at `x_end` is applied to the last point.
     pub fn integrate(&mut self) -> Result<Stats, IntegrationError> {
         // Save initial values
         self.x_out.push(self.x);
         self.y_out.push(self.y.clone());
 
-        let num_steps = ((self.x_end - self.x) / self.step_size).ceil() as usize;
-        for _ in 0..num_steps {
-            let (x_new, y_new) = self.step();
+        let mut stops: Vec<f64> = self
+            .f
+            .breakpoints()
+            .into_iter()
+            .filter(|x| *x >= self.x && *x <= self.x_end)
+            .collect();
+        stops.sort_by(|a, b| a.total_cmp(b));
+        stops.dedup();
+        let events = stops.len();
+        if stops.last() != Some(&self.x_end) {
+            stops.push(self.x_end);
+        }
 
-            self.x_out.push(x_new);
-            self.y_out.push(y_new.clone());
+        for (i, stop) in stops.iter().enumerate() {
+            // Ratios within rounding errors of an integer do not add a step
+            let ratio = (stop - self.x) / self.step_size;
+            let num_steps = match (ratio - 1e-9).ceil() {
+                n if n >= 1.0 => n as usize,
+                _ if ratio > 0.0 => 1,
+                _ => 0,
+            };
+            for n in 0..num_steps {
+                // Last step of the segment lands exactly on the stop
+                if n + 1 == num_steps {
+                    self.step(stop - self.x)?;
+                    self.x = *stop;
+                } else {
+                    self.step(self.step_size)?;
+                }
 
-            self.x = x_new;
-            self.y = y_new;
+                self.x_out.push(self.x);
+                self.y_out.push(self.y.clone());
 
-            self.stats.num_eval += 4;
-            self.stats.accepted_steps += 1;
+                self.stats.num_eval += 4;
+                self.stats.accepted_steps += 1;
+            }
+            if i < events {
+                self.restart_at(*stop);
+            }
         }
         Ok(self.stats)
     }
 
-    /// Performs one step of the Runge-Kutta 4 method.
-    fn step(&self) -> (f64, OVector<T, D>) {
-        let (rows, cols) = self.y.shape_generic();
-        let mut k = vec![OVector::zeros_generic(rows, cols); 12];
-
-        self.f.system(self.x, &self.y, &mut k[0]);
-        self.f.system(
-            self.x + self.half_step,
-            &(self.y.clone() + k[0].clone() * self.half_step),
-            &mut k[1],
-        );
-        self.f.system(
-            self.x + self.half_step,
-            &(self.y.clone() + k[1].clone() * self.half_step),
-            &mut k[2],
-        );
-        self.f.system(
-            self.x + self.step_size,
-            &(self.y.clone() + k[2].clone() * self.step_size),
-            &mut k[3],
-        );
-
-        let x_new = self.x + self.step_size;
-        let y_new = &self.y
-            + (k[0].clone() + k[1].clone() * 2.0 + k[2].clone() * 2.0 + k[3].clone())
-                * (self.step_size / 6.0);
-        let y_new = y_new.map(|x| if f64::from(x)<0_f64 {T::zero()} else {x});
-        (x_new, y_new)
+    /// Applies the event of the system at a breakpoint.
+    fn restart_at(&mut self, x: f64) {
+        let mut y = self.y.clone();
+        self.f.apply_event(x, &mut y);
+        if y != self.y {
+            self.y = y;
+            self.x_out.push(x);
+            self.y_out.push(self.y.clone());
+        }
+    }
+
+    /// Performs one step of the Runge-Kutta 4 method, updating x and y in place.
+    fn step(&mut self, step_size: f64) -> Result<(), IntegrationError> {
+        let h = T::from_superset_unchecked(&step_size);
+        let half = T::from_superset_unchecked(&(step_size / 2.));
+        let one = T::one();
+        let two = T::from_superset_unchecked(&2.);
+
+        // Systems only add their contribution to dy
+        for k in self.k.iter_mut() {
+            k.fill(T::zero());
+        }
+
+        let failure = |x: f64| move |error| IntegrationError::SystemFailure { x, error };
+
+        self.f
+            .try_system(self.x, &self.y, &mut self.k[0])
+            .map_err(failure(self.x))?;
+        self.y_stage.copy_from(&self.y);
+        self.y_stage.axpy(half, &self.k[0], one);
+        self.f
+            .try_system(self.x + step_size / 2., &self.y_stage, &mut self.k[1])
+            .map_err(failure(self.x + step_size / 2.))?;
+
+        self.y_stage.copy_from(&self.y);
+        self.y_stage.axpy(half, &self.k[1], one);
+        self.f
+            .try_system(self.x + step_size / 2., &self.y_stage, &mut self.k[2])
+            .map_err(failure(self.x + step_size / 2.))?;
+
+        self.y_stage.copy_from(&self.y);
+        self.y_stage.axpy(h, &self.k[2], one);
+        self.f
+            .try_system(self.x + step_size, &self.y_stage, &mut self.k[3])
+            .map_err(failure(self.x + step_size))?;
+
+        // y += (k0 + 2 k1 + 2 k2 + k3) * h / 6
+        self.y_stage.copy_from(&self.k[0]);
+        self.y_stage.axpy(two, &self.k[1], one);
+        self.y_stage.axpy(two, &self.k[2], one);
+        self.y_stage.axpy(one, &self.k[3], one);
+        self.y
+            .axpy(T::from_superset_unchecked(&(step_size / 6.0)), &self.y_stage, one);
+        self.y
+            .apply(|x| if f64::from(*x) < 0_f64 { *x = T::zero() });
+
+        self.x += step_size;
+        Ok(())
     }
 
     /// Getter for the independent variable's output.
@@ -116,4 +181,53 @@ where
     pub fn y_out(&self) -> &Vec<OVector<T, D>> {
         &self.y_out
     }
+
+    /// Getter for the integrated system.
+    pub fn system(&self) -> &F {
+        &self.f
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{bail, Context};
+    use nalgebra::DVector;
+
+    // dy/dx = -y, failing past x = 0.5
+    struct Decay;
+
+    impl System<DVector<f64>> for Decay {
+        fn system(&self, x: f64, y: &DVector<f64>, dy: &mut DVector<f64>) {
+            self.try_system(x, y, dy).unwrap();
+        }
+        fn try_system(&self, x: f64, y: &DVector<f64>, dy: &mut DVector<f64>) -> anyhow::Result<()> {
+            if x > 0.5 {
+                return Err(anyhow::anyhow!("Unknown species encountered (X)"))
+                    .context("While computing reaction");
+            }
+            if y[0] < 0.0 {
+                bail!("Negative value");
+            }
+            dy[0] = -y[0];
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn system_errors_stop_the_integration() {
+        let mut stepper = Rk4::new(Decay, 0.0, DVector::from_element(1, 1.0), 1.0, 0.1);
+        match stepper.integrate() {
+            Err(IntegrationError::SystemFailure { x, error }) => {
+                assert!(x > 0.5 && x <= 0.6);
+                let msg = format!("{:#}", error);
+                assert!(msg.contains("While computing reaction"));
+                assert!(msg.contains("Unknown species encountered (X)"));
+            }
+            _ => panic!("Expected a SystemFailure"),
+        }
+        // Output stops at the last successful step
+        assert_float_relative_eq!(*stepper.x_out().last().unwrap(), 0.5, 1e-12);
+        assert_float_relative_eq!(stepper.y_out()[5][0], (-0.5_f64).exp(), 1e-5);
+    }
 }
@@ -0,0 +1,366 @@
+//! Linearly implicit Rosenbrock method of order 2(3) of Shampine & Reichelt (ode23s) with adaptive step size, for stiff systems.
+
+use super::traits::{integrate_adaptive, AdaptiveStep, Driver, IntegrationError, Stats, System};
+
+use nalgebra::{DMatrix, DVector};
+
+// Coefficients, L-stable
+const D: f64 = 1. / (2. + std::f64::consts::SQRT_2);
+const E32: f64 = 6. + std::f64::consts::SQRT_2;
+
+// Step size control
+const SAFETY: f64 = 0.8;
+const FAC_MAX: f64 = 5.0;
+const FAC_MIN: f64 = 0.1;
+
+/// Structure containing the parameters for the numerical integration.
+///
+/// Each step solves linear systems with W = I - h d J, J being the Jacobian of the system computed by finite differences, as is its derivative with respect to x. The step size is then limited by the accuracy only, not by the fastest reactions as for the explicit methods.
+pub struct Rosenbrock<F>
+where
+    F: System<DVector<f64>>,
+{
+    f: F,
+    x: f64,
+    y: DVector<f64>,
+    rtol: f64,
+    atol: f64,
+    h: f64,
+    driver: Driver<DVector<f64>>,
+    // Derivative at (x, y)
+    f0: DVector<f64>,
+    // Jacobian and ∂f/∂x at (x, y), kept when a step is rejected
+    jacobian: Option<(DMatrix<f64>, DVector<f64>)>,
+    // Start and stages of the last accepted step, for the dense output
+    y_last: DVector<f64>,
+    k1: DVector<f64>,
+    k2: DVector<f64>,
+    h_last: f64,
+}
+
+impl<F> Rosenbrock<F>
+where
+    F: System<DVector<f64>>,
+{
+    /// Default initializer for the structure
+    ///
+    /// # Arguments
+    ///
+    /// * `f`       - Structure implementing the System<V> trait
+    /// * `x`       - Initial value of the independent variable (usually time)
+    /// * `x_end`   - Final value of the independent variable
+    /// * `dx`      - Increment in the dense output. Every accepted step is stored if dx = 0
+    /// * `y`       - Initial value of the dependent variable(s)
+    /// * `rtol`    - Relative tolerance used in the computation of the adaptive step size
+    /// * `atol`    - Absolute tolerance used in the computation of the adaptive step size
+    ///
+    pub fn new(f: F, x: f64, x_end: f64, dx: f64, y: DVector<f64>, rtol: f64, atol: f64) -> Self {
+        let n = y.len();
+        Rosenbrock {
+            f,
+            x,
+            y_last: y.clone(),
+            y,
+            rtol,
+            atol,
+            h: 0.0,
+            driver: Driver::new(x_end, dx),
+            f0: DVector::zeros(n),
+            jacobian: None,
+            k1: DVector::zeros(n),
+            k2: DVector::zeros(n),
+            h_last: 0.0,
+        }
+    }
+
+    /// Largest number of steps, accepted or rejected, before the integration fails.
+    pub fn set_max_steps(&mut self, max_steps: u32) {
+        self.driver.max_steps = max_steps;
+    }
+
+    /// Core integration method, see `integrate_adaptive`.
+    pub fn integrate(&mut self) -> Result<Stats, IntegrationError> {
+        integrate_adaptive(self)
+    }
+
+    /// Evaluates the system at (x, y).
+    fn evaluate(&mut self, x: f64, y: &DVector<f64>) -> Result<DVector<f64>, IntegrationError> {
+        let mut dy = DVector::zeros(y.len());
+        self.driver.stats.num_eval += 1;
+        self.f
+            .try_system(x, y, &mut dy)
+            .map_err(|error| IntegrationError::SystemFailure { x, error })?;
+        Ok(dy)
+    }
+
+    /// Jacobian and ∂f/∂x at (x, y) by forward differences, f0 holding the derivative at (x, y). The increment in x follows ode23s.
+    fn jacobian(&mut self, h: f64) -> Result<(DMatrix<f64>, DVector<f64>), IntegrationError> {
+        let n = self.y.len();
+        let mut j = DMatrix::zeros(n, n);
+        let mut y = self.y.clone();
+        for col in 0..n {
+            let delta = f64::EPSILON.sqrt() * self.y[col].abs().max(self.atol.max(1e-10));
+            y[col] = self.y[col] + delta;
+            let f = self.evaluate(self.x, &y)?;
+            j.set_column(col, &((f - &self.f0) / delta));
+            y[col] = self.y[col];
+        }
+        let delta = f64::EPSILON.sqrt() * self.x.abs().max((self.x + h).abs());
+        let f = self.evaluate(self.x + delta, &y)?;
+        Ok((j, (f - &self.f0) / delta))
+    }
+
+    /// Root mean square of v / (atol + rtol * max(|y|, |y_next|)).
+    fn norm(&self, v: &DVector<f64>, y_next: &DVector<f64>) -> f64 {
+        let n = v.len().max(1) as f64;
+        let sum: f64 = v
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let scale = self.y[i].abs().max(y_next[i].abs());
+                (value / (self.atol + self.rtol * scale)).powi(2)
+            })
+            .sum();
+        (sum / n).sqrt()
+    }
+
+    /// Initial step size from the derivative at x, as in ode23s.
+    fn initial_step(&self, stop: f64) -> f64 {
+        let h_max = stop - self.x;
+        let rate = self
+            .f0
+            .iter()
+            .zip(self.y.iter())
+            .map(|(f, y)| f.abs() / (self.atol + self.rtol * y.abs()))
+            .fold(0.0, f64::max);
+        match rate > 0.0 {
+            true => (0.8 * self.rtol.powf(1. / 3.) / rate).min(h_max),
+            false => h_max,
+        }
+    }
+
+    /// Computes a step of size h from (x, y). Returns the new state, its derivative and the error estimate, None when W is singular.
+    #[allow(clippy::type_complexity)]
+    fn step(
+        &mut self,
+        h: f64,
+        j: &DMatrix<f64>,
+        dfdx: &DVector<f64>,
+    ) -> Result<Option<(DVector<f64>, DVector<f64>, f64)>, IntegrationError> {
+        let n = self.y.len();
+        let w = (DMatrix::identity(n, n) - j * (h * D)).lu();
+        let solve = |b: DVector<f64>| w.solve(&b);
+        let x = self.x;
+        let time_term = dfdx * (h * D);
+
+        let k1 = match solve(&self.f0 + &time_term) {
+            Some(k1) => k1,
+            None => return Ok(None),
+        };
+        let y_half = &self.y + &k1 * (0.5 * h);
+        let f1 = self.evaluate(x + 0.5 * h, &y_half)?;
+        let k2 = match solve(&f1 - &k1) {
+            Some(k2) => k2 + &k1,
+            None => return Ok(None),
+        };
+        let y_next = &self.y + &k2 * h;
+        let f2 = self.evaluate(x + h, &y_next)?;
+        let rhs = &f2 - (&k2 - &f1) * E32 - (&k1 - &self.f0) * 2.0 + time_term;
+        let k3 = match solve(rhs) {
+            Some(k3) => k3,
+            None => return Ok(None),
+        };
+
+        let err_vec = (&k1 - &k2 * 2.0 + k3) * (h / 6.0);
+        let err = self.norm(&err_vec, &y_next);
+        self.k1 = k1;
+        self.k2 = k2;
+        match err.is_finite() {
+            true => Ok(Some((y_next, f2, err))),
+            false => Ok(Some((y_next, f2, f64::MAX))),
+        }
+    }
+
+    /// Getter for the independent variable's output.
+    pub fn x_out(&self) -> &Vec<f64> {
+        &self.driver.x_out
+    }
+
+    /// Getter for the dependent variables' output.
+    pub fn y_out(&self) -> &Vec<DVector<f64>> {
+        &self.driver.y_out
+    }
+}
+
+impl<F> AdaptiveStep<DVector<f64>> for Rosenbrock<F>
+where
+    F: System<DVector<f64>>,
+{
+    type F = F;
+
+    fn driver(&self) -> &Driver<DVector<f64>> {
+        &self.driver
+    }
+
+    fn driver_mut(&mut self) -> &mut Driver<DVector<f64>> {
+        &mut self.driver
+    }
+
+    fn system(&self) -> &F {
+        &self.f
+    }
+
+    fn x(&self) -> f64 {
+        self.x
+    }
+
+    fn y(&self) -> &DVector<f64> {
+        &self.y
+    }
+
+    fn set_y(&mut self, y: DVector<f64>) {
+        self.y = y;
+    }
+
+    fn h(&self) -> f64 {
+        self.h
+    }
+
+    fn start_segment(&mut self, stop: f64) -> Result<(), IntegrationError> {
+        self.f0 = self.evaluate(self.x, &self.y.clone())?;
+        self.jacobian = None;
+        if self.h == 0.0 {
+            self.h = self.initial_step(stop);
+        }
+        Ok(())
+    }
+
+    fn try_step(&mut self, h: f64, last: bool, stop: f64) -> Result<bool, IntegrationError> {
+        let (j, dfdx) = match self.jacobian.take() {
+            Some(jacobian) => jacobian,
+            None => self.jacobian(h)?,
+        };
+        // A singular W counts as a failed step
+        let (next, err) = match self.step(h, &j, &dfdx)? {
+            Some((y_next, f_next, err)) => (Some((y_next, f_next)), err),
+            None => (None, f64::MAX),
+        };
+        let fac = match err > 0.0 {
+            true => (SAFETY * err.powf(-1. / 3.)).clamp(FAC_MIN, FAC_MAX),
+            false => FAC_MAX,
+        };
+        let Some((y_next, f_next)) = next.filter(|_| err <= 1.0) else {
+            self.h = h * fac.min(0.5);
+            self.jacobian = Some((j, dfdx));
+            return Ok(false);
+        };
+        self.h_last = h;
+        self.x = match last {
+            true => stop,
+            false => self.x + h,
+        };
+        self.y_last = std::mem::replace(&mut self.y, y_next);
+        self.f0 = f_next;
+        // Negative concentrations are not physical
+        if self.y.iter().any(|v| *v < 0.0) {
+            self.y.apply(|v| *v = v.max(0.0));
+            self.f0 = self.evaluate(self.x, &self.y.clone())?;
+        }
+        // A step shortened to land on a stop keeps the proposal
+        if !last || h * fac > self.h {
+            self.h = h * fac;
+        }
+        Ok(true)
+    }
+
+    /// Dense output at x in the last accepted step.
+    fn interpolate(&self, x: f64) -> DVector<f64> {
+        let h = self.h_last;
+        let theta = match h > 0.0 {
+            true => (x - (self.x - h)) / h,
+            false => 1.0,
+        };
+        let c1 = theta * (1.0 - theta) / (1.0 - 2.0 * D);
+        let c2 = theta * (theta - 2.0 * D) / (1.0 - 2.0 * D);
+        &self.y_last + (&self.k1 * c1 + &self.k2 * c2) * h
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Robertson's problem: rates over nine orders of magnitude
+    struct Robertson;
+
+    impl System<DVector<f64>> for Robertson {
+        fn system(&self, _x: f64, y: &DVector<f64>, dy: &mut DVector<f64>) {
+            dy[0] = -0.04 * y[0] + 1e4 * y[1] * y[2];
+            dy[1] = 0.04 * y[0] - 1e4 * y[1] * y[2] - 3e7 * y[1] * y[1];
+            dy[2] = 3e7 * y[1] * y[1];
+        }
+    }
+
+    // dy/dx = -y, with y doubled at x = 1
+    struct Decay;
+
+    impl System<DVector<f64>> for Decay {
+        fn system(&self, _x: f64, y: &DVector<f64>, dy: &mut DVector<f64>) {
+            dy[0] = -y[0];
+        }
+        fn breakpoints(&self) -> Vec<f64> {
+            vec![1.0]
+        }
+        fn apply_event(&self, _x: f64, y: &mut DVector<f64>) {
+            y[0] *= 2.0;
+        }
+    }
+
+    // Fast relaxation towards a value growing linearly with x, as with a
+    // clamped species following a table
+    struct Tracking;
+
+    impl System<DVector<f64>> for Tracking {
+        fn system(&self, x: f64, y: &DVector<f64>, dy: &mut DVector<f64>) {
+            dy[0] = 1e3 * (x - y[0]);
+        }
+    }
+
+    #[test]
+    fn explicit_dependence_on_x_is_followed() {
+        let exact = |x: f64| x - 1e-3 * (1.0 - (-1e3 * x).exp());
+        let y0 = DVector::from_element(1, 0.0);
+        let mut stepper = Rosenbrock::new(Tracking, 0.0, 10.0, 0.5, y0, 1e-6, 1e-12);
+        let stats = stepper.integrate().unwrap();
+        // Without the ∂f/∂x term: 30 000 steps
+        assert!(stats.accepted_steps < 1000);
+        for (x, y) in stepper.x_out().iter().zip(stepper.y_out().iter()).skip(1) {
+            assert_float_relative_eq!(y[0], exact(*x), 1e-7);
+        }
+    }
+
+    #[test]
+    fn stiff_systems_take_few_steps() {
+        let y0 = DVector::from_vec(vec![1.0, 0.0, 0.0]);
+        let mut stepper = Rosenbrock::new(Robertson, 0.0, 40.0, 0.0, y0, 1e-4, 1e-10);
+        let stats = stepper.integrate().unwrap();
+        assert!(stats.accepted_steps < 500);
+        let y = stepper.y_out().last().unwrap();
+        // Reference values at x = 40 (Hairer & Wanner)
+        assert_float_relative_eq!(y[0], 0.7158271, 1e-3);
+        assert_float_relative_eq!(y[2], 0.2841637, 1e-3);
+        assert_float_relative_eq!(y.sum(), 1.0, 1e-6);
+
+        let exact = |x: f64| match x <= 1.0 {
+            true => (-x).exp(),
+            false => 2.0 * (-x).exp(),
+        };
+        let y0 = DVector::from_element(1, 1.0);
+        let mut stepper = Rosenbrock::new(Decay, 0.0, 3.0, 0.25, y0, 1e-6, 1e-10);
+        stepper.integrate().unwrap();
+        assert_eq!(stepper.x_out().len(), 13);
+        for (x, y) in stepper.x_out().iter().zip(stepper.y_out().iter()) {
+            assert_float_relative_eq!(y[0], exact(*x), 1e-4);
+        }
+    }
+}
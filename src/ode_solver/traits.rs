@@ -3,10 +3,23 @@
 use std::fmt;
 use thiserror::Error;
 
+const MAX_STEPS: u32 = 100_000; // Default, see Driver::max_steps
+
 /// Trait needed to be implemented by the user.
 pub trait System<V> {
     /// System of ordinary differential equations.
     fn system(&self, x: f64, y: &V, dy: &mut V);
+    /// Fallible version of `system` called by the integrators. An error stops the integration and is returned as `IntegrationError::SystemFailure`.
+    fn try_system(&self, x: f64, y: &V, dy: &mut V) -> anyhow::Result<()> {
+        self.system(x, y, dy);
+        Ok(())
+    }
+    /// Values of the independent variable where the system is discontinuous. Integrators stop exactly at these points, call `apply_event` and restart from there.
+    fn breakpoints(&self) -> Vec<f64> {
+        Vec::new()
+    }
+    /// Discrete change of the dependent variables at a breakpoint.
+    fn apply_event(&self, _x: f64, _y: &mut V) {}
     /// Stop function called at every successful integration step. The integration is stopped when this function returns true.
     fn solout(&mut self, _x: f64, _y: &V, _dy: &V) -> bool {
         false
@@ -20,6 +33,162 @@ pub enum OutputType {
     Sparse,
 }
 
+/// Output, step limit and statistics of an adaptive integrator, see `integrate_adaptive`.
+pub struct Driver<V> {
+    pub x_end: f64,
+    /// Increment in the dense output, every accepted step is stored if dx = 0
+    pub dx: f64,
+    /// Largest number of steps, accepted or rejected, before the integration fails
+    pub max_steps: u32,
+    pub out_type: OutputType,
+    pub x_out: Vec<f64>,
+    pub y_out: Vec<V>,
+    pub stats: Stats,
+}
+
+impl<V> Driver<V> {
+    pub(crate) fn new(x_end: f64, dx: f64) -> Self {
+        Driver {
+            x_end,
+            dx,
+            max_steps: MAX_STEPS,
+            out_type: match dx > 0.0 {
+                true => OutputType::Dense,
+                false => OutputType::Sparse,
+            },
+            x_out: Vec::new(),
+            y_out: Vec::new(),
+            stats: Stats::new(),
+        }
+    }
+}
+
+/// Steps of an adaptive integrator. The segments between breakpoints, the
+/// events, the step limit and the output are handled by `integrate_adaptive`.
+pub(crate) trait AdaptiveStep<V> {
+    type F: System<V>;
+    fn driver(&self) -> &Driver<V>;
+    fn driver_mut(&mut self) -> &mut Driver<V>;
+    fn system(&self) -> &Self::F;
+    fn x(&self) -> f64;
+    fn y(&self) -> &V;
+    fn set_y(&mut self, y: V);
+    /// Proposed size of the next step.
+    fn h(&self) -> f64;
+    /// Prepares the first step from x towards `stop`, after a possible event.
+    fn start_segment(&mut self, stop: f64) -> Result<(), IntegrationError>;
+    /// Tries a step of size h. When accepted, moves to x + h (exactly `stop`
+    /// if `last`). Proposes the next step size in both cases.
+    fn try_step(&mut self, h: f64, last: bool, stop: f64) -> Result<bool, IntegrationError>;
+    /// Dense output at x in the last accepted step.
+    fn interpolate(&self, x: f64) -> V;
+}
+
+/// Integrates from x to x_end. As for `Rk4`, the integration stops exactly
+/// at the breakpoints of the system, applies the corresponding event and
+/// restarts from there. An event at `x_end` is applied to the last point.
+pub(crate) fn integrate_adaptive<V, S>(s: &mut S) -> Result<Stats, IntegrationError>
+where
+    V: Clone + PartialEq,
+    S: AdaptiveStep<V>,
+{
+    let (x_start, x_end) = (s.x(), s.driver().x_end);
+    let y_start = s.y().clone();
+    let driver = s.driver_mut();
+    driver.x_out.push(x_start);
+    driver.y_out.push(y_start);
+    let mut next_out = 1;
+
+    let mut stops: Vec<f64> = s
+        .system()
+        .breakpoints()
+        .into_iter()
+        .filter(|x| *x > x_start && *x <= x_end)
+        .collect();
+    stops.sort_by(|a, b| a.total_cmp(b));
+    stops.dedup();
+    let events = stops.len();
+    if stops.last() != Some(&x_end) {
+        stops.push(x_end);
+    }
+
+    for (i, stop) in stops.iter().enumerate() {
+        s.start_segment(*stop)?;
+        while s.x() < *stop {
+            let (x, h) = (s.x(), s.h());
+            let driver = s.driver();
+            if driver.stats.accepted_steps + driver.stats.rejected_steps >= driver.max_steps {
+                return Err(IntegrationError::MaxNumStepReached {
+                    x,
+                    n_step: driver.max_steps,
+                });
+            }
+            if h.abs() <= x.abs() * f64::EPSILON {
+                return Err(IntegrationError::StepSizeUnderflow { x });
+            }
+            // Last step of the segment lands exactly on the stop
+            let last = x + 1.01 * h >= *stop;
+            let h = match last {
+                true => stop - x,
+                false => h,
+            };
+            if !s.try_step(h, last, *stop)? {
+                s.driver_mut().stats.rejected_steps += 1;
+                continue;
+            }
+            s.driver_mut().stats.accepted_steps += 1;
+            match s.driver().out_type {
+                OutputType::Dense => {
+                    let dx = s.driver().dx;
+                    while next_out as f64 * dx + x_start <= s.x().min(x_end) {
+                        let x_out = next_out as f64 * dx + x_start;
+                        let y_out = s.interpolate(x_out);
+                        let driver = s.driver_mut();
+                        driver.x_out.push(x_out);
+                        driver.y_out.push(y_out);
+                        next_out += 1;
+                    }
+                }
+                OutputType::Sparse => {
+                    let (x, y) = (s.x(), s.y().clone());
+                    let driver = s.driver_mut();
+                    driver.x_out.push(x);
+                    driver.y_out.push(y);
+                }
+            }
+        }
+        if i < events {
+            restart_at(s, *stop);
+        }
+    }
+    let y = s.y().clone();
+    let driver = s.driver_mut();
+    if driver.out_type == OutputType::Dense && *driver.x_out.last().unwrap() < x_end {
+        driver.x_out.push(x_end);
+        driver.y_out.push(y);
+    }
+    Ok(driver.stats)
+}
+
+/// Applies the event of the system at a breakpoint. The dense output only
+/// shows the jump at `x_end`, after the last sample.
+fn restart_at<V, S>(s: &mut S, x: f64)
+where
+    V: Clone + PartialEq,
+    S: AdaptiveStep<V>,
+{
+    let mut y = s.y().clone();
+    s.system().apply_event(x, &mut y);
+    if y != *s.y() {
+        s.set_y(y.clone());
+        let driver = s.driver_mut();
+        if driver.out_type == OutputType::Sparse || x == driver.x_end {
+            driver.x_out.push(x);
+            driver.y_out.push(y);
+        }
+    }
+}
+
 /// Enumeration of the errors that may arise during integration.
 #[derive(Debug, Error)]
 pub enum IntegrationError {
@@ -29,6 +198,8 @@ pub enum IntegrationError {
     StepSizeUnderflow { x: f64 },
     #[error("The problem seems to become stiff at x = {x}.")]
     StiffnessDetected { x: f64 },
+    #[error("Stopped at x = {x}. System evaluation failed: {error:#}")]
+    SystemFailure { x: f64, error: anyhow::Error },
 }
 
 /// Contains some statistics of the integration.
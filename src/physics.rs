@@ -1,6 +1,8 @@
 /* --------------------------- Module declarations -------------------------- */
 pub mod utils;
 pub mod beam;
+pub mod gas;
+pub mod schedule;
 
 /* ------------------------- Re-Exports useful items ------------------------ */
 pub use utils::ge_to_kr;
\ No newline at end of file
@@ -58,14 +58,39 @@ impl Beam {
         self.as_particle_beam().peak_dose_rate()
     }
 
+    // Dose delivered in the first `duration` seconds [Gy], pulses are
+    // counted exactly
+    pub fn dose_in(&self, duration:f64) -> f64 {
+        match self {
+            Beam::Constant(beam) => beam.average_dose_rate() * duration,
+            Beam::Pulsed(beam) => {
+                let ts = beam.get_structure();
+                let pulses = (duration / ts.period()).floor();
+                let last = (duration - pulses * ts.period()).min(ts.on_time());
+                beam.peak_dose_rate() * (pulses * ts.on_time() + last)
+            },
+        }
+    }
+
+    // Time to deliver `dose` [s], until the end of the last pulse needed
+    pub fn time_to_deliver(&self, dose:f64) -> f64 {
+        match self {
+            Beam::Constant(beam) => dose / beam.average_dose_rate(),
+            Beam::Pulsed(beam) => {
+                let ts = beam.get_structure();
+                let per_pulse = beam.peak_dose_rate() * ts.on_time();
+                // Full pulses before the last one, not counting rounding errors
+                let pulses = ((dose / per_pulse - 1e-9).ceil() - 1.0).max(0.0);
+                pulses * ts.period() + (dose - pulses * per_pulse) / beam.peak_dose_rate()
+            },
+        }
+    }
+
 }
 
 impl IsTimed for Beam {
     fn at(&self, time:f64) -> TimeMessage {
-        TimeMessage {
-            time: time,
-            current_dose_rate: self.as_particle_beam().peak_dose_rate(),
-        }
+        self.as_particle_beam().at(time)
     }
 
     fn set_structure(&mut self, ts:TimeStructure) {
@@ -101,6 +126,8 @@ impl TimeStructure {
             false => TimeState::IsOFF,
         }
     }
+    pub fn period(&self) -> f64 { self.period }
+    pub fn on_time(&self) -> f64 { self.on_time }
     pub fn duty_cycle(&self) -> f64 {
         self.on_time / self.period // return 'inf' if period = 0 (no error)
     }
@@ -133,10 +160,11 @@ impl ParticleBeam {
 }
 impl IsTimed for ParticleBeam {
     fn at(&self, time:f64) -> TimeMessage {
-        TimeMessage {
-            time: time,
-            current_dose_rate: self.peak_dose_rate(),
-        }
+        let dose_rate = match self.time_struct.state_at(time) {
+            TimeState::IsON => self.peak_dose_rate(),
+            TimeState::IsOFF => 0_f64,
+        };
+        TimeMessage::new(time, dose_rate)
     }
 
     fn set_structure(&mut self, ts:TimeStructure) {
@@ -154,6 +182,9 @@ pub struct TimeMessage {
 
 #[allow(non_snake_case)]
 impl TimeMessage {
+    pub fn new(time:f64, current_dose_rate:f64) -> Self {
+        Self { time, current_dose_rate }
+    }
     pub fn is_ON(&self) -> bool { self.current_dose_rate > 0_f64 }
     pub fn dose_rate(&self) -> f64 { self.current_dose_rate }
     pub fn time(&self) -> f64 { self.time }
@@ -0,0 +1,176 @@
+/* ---------------------------- External imports ---------------------------- */
+use physical_constants as CST;
+use serde::{Deserialize, Serialize};
+
+/* ---------------------------- Internal imports ---------------------------- */
+use crate::reactions::compartments::namespaced;
+
+/* -------------------------------------------------------------------------- */
+/*                         FUNCTION/STRUCT DEFINITIONS                        */
+/* -------------------------------------------------------------------------- */
+// Henry's law solubility of O2 in water at 25°C [mol/l/atm]
+pub const HENRY_O2: f64 = 1.3e-3;
+pub const MMHG_PER_ATM: f64 = 760.0;
+pub const ROOM_TEMPERATURE: f64 = 298.15; // [K]
+// Namespace of the gas phase species ("gas:O2")
+pub const GAS_PHASE: &str = "gas";
+
+// Ideal gas constant in [l.atm/mol/K]
+pub fn gas_constant() -> f64 {
+    CST::MOLAR_GAS_CONSTANT * 1e3 / CST::STANDARD_ATMOSPHERE
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub enum PartialPressure {
+    Atm(f64),
+    MmHg(f64),
+    Percent(f64), // % of a 1 atm gas phase (e.g. 21% for air)
+}
+
+impl PartialPressure {
+    pub fn atm(&self) -> f64 {
+        match self {
+            PartialPressure::Atm(p) => *p,
+            PartialPressure::MmHg(p) => p / MMHG_PER_ATM,
+            PartialPressure::Percent(p) => p / 100.0,
+        }
+    }
+    pub fn mmhg(&self) -> f64 { self.atm() * MMHG_PER_ATM }
+
+    // Dissolved concentration at equilibrium [mol/l]
+    pub fn to_concentration(&self, henry:f64) -> f64 {
+        henry * self.atm()
+    }
+}
+
+// Gas phase above the liquid of an in-vitro vial. The exchange follows
+// d[liquid]/dt = kla.(henry.p_gas - [liquid]). For sealed vials the gas
+// phase is depleted by the transfer, open vials keep p_gas constant.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Headspace {
+    pub species: String,
+    pub pressure: PartialPressure,
+    pub kla: f64,           // Mass transfer coefficient [1/s]
+    pub liquid_volume: f64, // [l]
+    pub gas_volume: f64,    // [l]
+    #[serde(default)]
+    pub sealed: bool,
+    #[serde(default = "default_henry")]
+    pub henry: f64,         // [mol/l/atm]
+    #[serde(default = "default_temperature")]
+    pub temperature: f64,   // [K]
+}
+
+fn default_henry() -> f64 { HENRY_O2 }
+fn default_temperature() -> f64 { ROOM_TEMPERATURE }
+
+impl Headspace {
+    // Label of the gas phase reservoir
+    pub fn gas_label(&self) -> String {
+        namespaced(GAS_PHASE, &self.species)
+    }
+
+    // Liquid concentration in equilibrium with the initial gas [mol/l]
+    pub fn equilibrium_concentration(&self) -> f64 {
+        self.pressure.to_concentration(self.henry)
+    }
+
+    // The gas reservoir is tracked as the liquid concentration it would
+    // be in equilibrium with (henry.p_gas). In these units the gas phase
+    // behaves as a compartment of volume V_gas / (henry.R.T).
+    pub fn equivalent_gas_volume(&self) -> f64 {
+        self.gas_volume / (self.henry * gas_constant() * self.temperature)
+    }
+
+    // V_liquid / V_gas,equivalent as used by the transfer reactions
+    pub fn volume_ratio(&self) -> f64 {
+        self.liquid_volume / self.equivalent_gas_volume()
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   TESTING                                  */
+/* -------------------------------------------------------------------------- */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::{Beam, ODESolver, State};
+    use crate::ode_solver::rk4::Rk4;
+    use crate::reactions::parse_reactions_file;
+
+    // Headspace of data/reactions_vial.ron
+    fn vial(sealed:bool) -> Headspace {
+        Headspace {
+            species: String::from("O2"),
+            pressure: PartialPressure::Percent(5.0),
+            kla: 5e-3,
+            liquid_volume: 1e-3,
+            gas_volume: 1e-3,
+            sealed,
+            henry: HENRY_O2,
+            temperature: ROOM_TEMPERATURE,
+        }
+    }
+
+    // Liquid and gas O2 [µmol/l] over 3000 s without beam, starting from
+    // deoxygenated medium
+    fn exchange(path:&str) -> Vec<(f64, Option<f64>)> {
+        let env = parse_reactions_file(path).unwrap();
+        let idx = env.map_all_species();
+        let (liquid, gas) = (idx["O2"], idx.get("gas:O2").cloned());
+        let mut y0 = env.get_initial_values();
+        y0[liquid] = 0.0;
+        let chem = ODESolver::new(env, Beam::new_constant(String::from("e"), 0.0).unwrap());
+        let tracked = gas.filter(|g| *g < chem.dimension());
+        let mut rk4 = Rk4::new(chem, 0.0, y0, 3000.0, 1.0);
+        rk4.integrate().unwrap();
+        rk4.y_out().iter()
+           .map(|y:&State| (y[liquid], tracked.map(|g| y[g])))
+           .collect()
+    }
+
+    #[test]
+    fn test_partial_pressure_conversions() {
+        // Air saturated water at 25°C: ~270 µmol/l
+        let air = PartialPressure::Percent(21.0);
+        assert_float_relative_eq!(air.mmhg(), 159.6, 1e-6);
+        assert_float_relative_eq!(air.to_concentration(HENRY_O2), 2.73e-4, 1e-6);
+        assert_float_relative_eq!(
+            PartialPressure::MmHg(38.0).atm(), PartialPressure::Percent(5.0).atm(), 1e-12);
+    }
+
+    #[test]
+    fn sealed_vial_conserves_oxygen() {
+        let file = format!("{}/data/reactions_vial.ron", env!("CARGO_MANIFEST_DIR"));
+        let hs = vial(true);
+        let states = exchange(&file);
+        let gas0 = states[0].1.unwrap();
+        // Total O2 in liquid-equivalent units
+        let total = |(liquid, gas):&(f64, Option<f64>)| liquid * hs.volume_ratio() + gas.unwrap();
+        for state in states.iter() {
+            assert_float_relative_eq!(total(state), gas0, 1e-9);
+        }
+        // Henry equilibrium with the depleted gas phase
+        let (liquid, gas) = states[states.len()-1];
+        let expected = gas0 / (1.0 + hs.volume_ratio());
+        assert_float_relative_eq!(liquid, expected, 1e-4);
+        assert_float_relative_eq!(gas.unwrap(), expected, 1e-4);
+    }
+
+    #[test]
+    fn open_vial_relaxes_to_the_gas_pressure() {
+        let file = format!("{}/data/reactions_vial.ron", env!("CARGO_MANIFEST_DIR"));
+        let open = fs::read_to_string(file).unwrap().replace("sealed: true", "sealed: false");
+        let path = crate::test_dir("open_vial").join("reactions_vial.ron");
+        fs::write(&path, open).unwrap();
+
+        let states = exchange(path.to_str().unwrap());
+        assert!(states.iter().all(|(_, gas)| gas.is_none()));
+        // Relaxation at kLa towards the constant gas pressure
+        let cc_eq = vial(false).equilibrium_concentration() * 1e6;
+        let (liquid, _) = states[200];
+        assert_float_relative_eq!(liquid, cc_eq * (1.0 - (-5e-3 * 200.0_f64).exp()), 1e-4);
+        assert_float_relative_eq!(states[states.len()-1].0, cc_eq, 1e-4);
+    }
+}
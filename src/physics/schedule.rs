@@ -0,0 +1,215 @@
+/* ---------------------------- External imports ---------------------------- */
+use anyhow::Result;
+use anyhow::bail;
+
+/* ---------------------------- Internal imports ---------------------------- */
+use super::beam::{Beam, IsTimed, TimeMessage};
+
+/* -------------------------------------------------------------------------- */
+/*                         FUNCTION/STRUCT DEFINITIONS                        */
+/* -------------------------------------------------------------------------- */
+// One irradiation of a treatment: the beam (with its own time structure)
+// is ON for `duration` seconds, then a `gap` follows before the next one.
+#[derive(Debug)]
+pub struct Fraction {
+    beam: Beam,
+    duration: f64,
+    gap: f64,
+}
+
+impl Fraction {
+    pub fn new(beam:Beam, duration:f64, gap:f64) -> Result<Self> {
+        if duration <= 0.0 || gap < 0.0 {
+            bail!("Invalid fraction: duration ({}) must be > 0 and gap ({}) >= 0",
+                  duration,
+                  gap
+            );
+        }
+        Ok(Self { beam, duration, gap })
+    }
+
+    // Duration deduced from the dose to deliver [Gy]
+    pub fn new_from_dose(beam:Beam, dose:f64, gap:f64) -> Result<Self> {
+        let dose_rate = beam.average_dose_rate();
+        if dose_rate <= 0.0 {
+            bail!("Cannot deliver {} Gy with a dose rate of {} Gy/s", dose, dose_rate);
+        }
+        let duration = beam.time_to_deliver(dose);
+        Fraction::new(beam, duration, gap)
+    }
+
+    pub fn beam(&self) -> &Beam { &self.beam }
+    pub fn duration(&self) -> f64 { self.duration }
+    pub fn gap(&self) -> f64 { self.gap }
+    pub fn dose(&self) -> f64 {
+        self.beam.dose_in(self.duration)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkKind {
+    Start,
+    End,
+}
+
+// Fraction boundary, to be reported along the trajectory
+#[derive(Debug, Clone, Copy)]
+pub struct FractionMark {
+    pub time: f64,
+    pub fraction: usize,
+    pub kind: MarkKind,
+}
+
+// Sequence of fractions starting at t = 0. Dose rate is zero during gaps
+// and after the last fraction.
+#[derive(Debug)]
+pub struct TreatmentSchedule {
+    fractions: Vec<Fraction>,
+    starts: Vec<f64>,
+}
+
+impl TreatmentSchedule {
+    pub fn new(fractions:Vec<Fraction>) -> Result<Self> {
+        if fractions.is_empty() {
+            bail!("A treatment schedule needs at least one fraction");
+        }
+        let mut starts = vec![];
+        let mut time = 0_f64;
+        for fraction in fractions.iter() {
+            starts.push(time);
+            time += fraction.duration + fraction.gap;
+        }
+        Ok(Self { fractions, starts })
+    }
+
+    // Beam never switched off (single open ended fraction)
+    pub fn single(beam:Beam) -> Self {
+        Self {
+            fractions: vec![Fraction { beam, duration: f64::INFINITY, gap: 0.0 }],
+            starts: vec![0.0],
+        }
+    }
+
+    pub fn iter_fractions(&self) -> impl Iterator<Item=&Fraction> {
+        self.fractions.iter()
+    }
+    pub fn number_of_fractions(&self) -> usize { self.fractions.len() }
+    pub fn fraction_start(&self, idx:usize) -> f64 { self.starts[idx] }
+    pub fn fraction_end(&self, idx:usize) -> f64 {
+        self.starts[idx] + self.fractions[idx].duration
+    }
+    pub fn total_dose(&self) -> f64 {
+        self.fractions.iter().map(|f| f.dose()).sum()
+    }
+    // End of the last gap
+    pub fn total_duration(&self) -> f64 {
+        self.fractions.iter().map(|f| f.duration + f.gap).sum()
+    }
+
+    // Index of the fraction being delivered at time t (None during gaps)
+    pub fn fraction_at(&self, time:f64) -> Option<usize> {
+        let idx = self.starts.partition_point(|start| *start <= time);
+        if idx == 0 { return None; }
+        match time < self.fraction_end(idx-1) {
+            true => Some(idx-1),
+            false => None,
+        }
+    }
+
+    pub fn marks(&self) -> Vec<FractionMark> {
+        let mut out = vec![];
+        for idx in 0..self.fractions.len() {
+            out.push(FractionMark {
+                time: self.fraction_start(idx), fraction: idx, kind: MarkKind::Start});
+            if self.fraction_end(idx).is_finite() {
+                out.push(FractionMark {
+                    time: self.fraction_end(idx), fraction: idx, kind: MarkKind::End});
+            }
+        }
+        return out;
+    }
+
+    // Times where the beam is switched on or off between fractions
+    pub fn breakpoints(&self) -> Vec<f64> {
+        self.marks().iter().map(|m| m.time).collect()
+    }
+
+    // Beginning and end of every pulse delivered before `until`. Adaptive
+    // solvers must stop there not to step over a pulse.
+    pub fn pulse_edges(&self, until:f64) -> Vec<f64> {
+        let mut out = vec![];
+        for (idx, fraction) in self.fractions.iter().enumerate() {
+            let structure = match fraction.beam() {
+                Beam::Pulsed(beam) => beam.get_structure(),
+                Beam::Constant(_) => continue,
+            };
+            let end = self.fraction_end(idx).min(until);
+            let mut start = self.starts[idx];
+            while start < end {
+                out.push(start);
+                out.push(start + structure.on_time());
+                start += structure.period();
+            }
+        }
+        out.retain(|t| *t < until);
+        return out;
+    }
+
+    // Fraction delivered at each time of a trajectory (None during gaps)
+    pub fn label_times(&self, times:&[f64]) -> Vec<Option<usize>> {
+        times.iter().map(|t| self.fraction_at(*t)).collect()
+    }
+
+    // Time structure of each fraction restarts at the fraction start
+    pub fn at(&self, time:f64) -> TimeMessage {
+        match self.fraction_at(time) {
+            Some(idx) => {
+                let local = self.fractions[idx].beam.at(time - self.starts[idx]);
+                TimeMessage::new(time, local.dose_rate())
+            },
+            None => TimeMessage::new(time, 0_f64),
+        }
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   TESTING                                  */
+/* -------------------------------------------------------------------------- */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fractionated_schedule() {
+        let conv = Beam::new_constant(String::from("e"), 0.1).unwrap();
+        let flash = Beam::new_pulsed(String::from("e"), 100.0, 1e-2, 1e-3).unwrap();
+        let schedule = TreatmentSchedule::new(vec![
+            Fraction::new_from_dose(conv, 1.0, 30.0).unwrap(),
+            Fraction::new_from_dose(flash, 1.0, 0.0).unwrap(),
+        ]).unwrap();
+
+        assert_float_relative_eq!(schedule.total_dose(), 2.0, 1e-12);
+        assert_float_relative_eq!(schedule.fraction_start(1), 40.0, 1e-12);
+        assert_eq!(schedule.fraction_at(5.0), Some(0));
+        assert_eq!(schedule.fraction_at(20.0), None);
+        assert_eq!(schedule.at(20.0).dose_rate(), 0.0);
+        // Pulses of the second fraction start with the fraction
+        assert_float_relative_eq!(schedule.at(40.0005).dose_rate(), 1000.0, 1e-12);
+        assert_eq!(schedule.at(40.005).dose_rate(), 0.0);
+        assert_eq!(schedule.breakpoints().len(), 4);
+        assert_eq!(schedule.label_times(&[5.0, 20.0, 40.0]), vec![Some(0), None, Some(1)]);
+
+        // Partial pulses are counted at the peak dose rate
+        let flash = Beam::new_pulsed(String::from("e"), 100.0, 1e-2, 1e-3).unwrap();
+        let fraction = Fraction::new_from_dose(flash, 2.5, 0.0).unwrap();
+        assert_float_relative_eq!(fraction.duration(), 2.05e-2, 1e-12);
+        assert_float_relative_eq!(fraction.dose(), 2.5, 1e-12);
+        assert_float_relative_eq!(fraction.beam().dose_in(1.5e-2), 2.0, 1e-12);
+        assert_float_relative_eq!(fraction.beam().time_to_deliver(1e-3), 1e-6, 1e-12);
+        // 10 ms of flash: a single pulse of 1 ms
+        let edges = schedule.pulse_edges(f64::INFINITY);
+        assert_eq!(edges.len(), 2);
+        assert_float_relative_eq!(edges[1], 40.001, 1e-12);
+        assert_eq!(schedule.pulse_edges(40.0005), vec![40.0]);
+    }
+}
@@ -5,12 +5,26 @@ pub mod reactions_parser;
 pub mod traits;
 pub mod species;
 pub mod errors;
+pub mod compartments;
+pub mod profiles;
+pub mod validation;
+pub mod composition;
+pub mod equation;
+pub mod provenance;
+pub mod metadata;
+pub mod registry;
 
 /* ------------------------- Re-Exports useful items ------------------------ */
 pub use acid_base::AcidBase;
 pub use k_reactions::KReaction;
 pub use species::SimSpecies;
+pub use compartments::Compartment;
+pub use provenance::Provenance;
+pub use metadata::{Metadata, Uncertainty};
+pub use registry::{SpeciesEntry, SpeciesRegistry};
 
 pub use reactions_parser::{
     parse_reactions_file,
+    validate_reactions_file,
+    ChemistryParameter,
 };
@@ -6,6 +6,7 @@ use super::traits::{
     IsTrackedSpecies
 };
 use super::k_reactions::ReactionRateIndex;
+use super::metadata::Metadata;
 
 /* -------------------------------------------------------------------------- */
 /*                         FUNCTION/STRUCT DEFINITIONS                        */
@@ -32,7 +33,7 @@ impl Chemical {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ABPartner {
     label: Chemical,
     // Index of the related Acid/Base Reaction. Better than a Rc cell to
@@ -71,10 +72,11 @@ pub struct AcidBase {
     ka: f64,
     index: usize,
     kreaction: Vec<ReactionRateIndex>,
+    metadata: Metadata,
 }
 impl IsTrackedSpecies for AcidBase {
     fn index(&self) -> usize { self.index }
-    fn iter_kreaction_indexes(&self) -> std::slice::Iter<ReactionRateIndex> {
+    fn iter_kreaction_indexes(&self) -> std::slice::Iter<'_, ReactionRateIndex> {
         self.kreaction.iter()
     }
     fn link_kreaction(&mut self, index:ReactionRateIndex) {
@@ -91,8 +93,13 @@ impl AcidBase {
                ka   : f64::powf(10.0, -pKa),
                index: index,
                kreaction: vec![],
+               metadata: Metadata::default(),
             }
     }
+    pub fn metadata(&self) -> &Metadata { &self.metadata }
+    pub fn set_metadata(&mut self, metadata:Metadata) {
+        self.metadata = metadata;
+    }
     pub fn pKa(&self) -> f64 {self.pKa}
     pub fn ka(&self)  -> f64 {self.ka}
     pub fn iter(&self) -> impl Iterator<Item=&Chemical> {
@@ -0,0 +1,110 @@
+/* ---------------------------- External imports ---------------------------- */
+use serde::{Deserialize, Serialize};
+
+/* ---------------------------- Internal imports ---------------------------- */
+
+
+/* -------------------------------------------------------------------------- */
+/*                         FUNCTION/STRUCT DEFINITIONS                        */
+/* -------------------------------------------------------------------------- */
+// Separator between compartment name and species label ("intra:O2")
+pub const NAMESPACE_SEPARATOR: char = ':';
+
+// A well-mixed volume holding its own copy of every species.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[allow(non_snake_case)]
+pub struct Compartment {
+    pub name: String,
+    pub volume: f64, // Only volume ratios matter, any unit is fine
+    #[serde(default = "default_irradiated")]
+    pub irradiated: bool, // Receives radiolytic production or not
+    #[serde(default)]
+    pub pH: Option<f64>, // Falls back on bio_param.pH
+}
+
+fn default_irradiated() -> bool { true }
+
+impl Compartment {
+    pub fn name(&self) -> &str { &self.name }
+    pub fn volume(&self) -> f64 { self.volume }
+    pub fn is_irradiated(&self) -> bool { self.irradiated }
+    pub fn label(&self, species:&str) -> String {
+        namespaced(&self.name, species)
+    }
+}
+
+pub fn namespaced(compartment:&str, species:&str) -> String {
+    format!("{compartment}{NAMESPACE_SEPARATOR}{species}")
+}
+
+// "intra:O2" -> (Some("intra"), "O2") ; "O2" -> (None, "O2")
+pub fn split_namespace(label:&str) -> (Option<&str>, &str) {
+    match label.split_once(NAMESPACE_SEPARATOR) {
+        Some((comp, sp)) => (Some(comp), sp),
+        None => (None, label),
+    }
+}
+
+pub fn is_namespaced(label:&str) -> bool {
+    label.contains(NAMESPACE_SEPARATOR)
+}
+
+// Label of a species living in the same compartment as `other`
+pub fn same_namespace(other:&str, species:&str) -> String {
+    match split_namespace(other) {
+        (Some(comp), _) => namespaced(comp, species),
+        (None, _) => species.to_string(),
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   TESTING                                  */
+/* -------------------------------------------------------------------------- */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reactions::parse_reactions_file;
+    use crate::reactions::k_reactions::ChemicalReaction;
+    use crate::reactions::traits::IsChemicalReaction;
+    use crate::ode_solver::traits::System;
+    use crate::{Beam, ODESolver};
+
+    #[test]
+    fn compartments_are_expanded_and_transfers_conserve_matter() {
+        let file = format!("{}/data/reactions_compartments.ron",
+                           env!("CARGO_MANIFEST_DIR"));
+        let env = parse_reactions_file(&file).unwrap();
+        let labels = env.species_label();
+        assert!(labels.contains(&String::from("extra:O2")));
+        assert!(labels.contains(&String::from("intra:O2")));
+        assert_eq!(env.initial_cc["intra:O2"], 20e-6);
+        assert_eq!(env.initial_cc["extra:O2"], 50e-6);
+
+        // No radiolytic production in the shielded compartment
+        let radiolytic: Vec<&ChemicalReaction> = env.reactions.iter()
+            .filter(|r| matches!(r, ChemicalReaction::Radiolytic(_)))
+            .collect();
+        assert_eq!(radiolytic.len(), 3);
+        for r in radiolytic {
+            assert!(r.species().all(|sp| sp.as_str().starts_with("extra:")));
+        }
+
+        // Amount leaving one compartment enters the other
+        for r in env.reactions.iter() {
+            if let ChemicalReaction::Transfer(t) = r {
+                let v_from = env.compartment(split_namespace(t.from()).0.unwrap())
+                                .unwrap().volume();
+                let v_to = env.compartment(split_namespace(t.to()).0.unwrap())
+                              .unwrap().volume();
+                assert_float_relative_eq!(v_from, t.volume_ratio() * v_to, 1e-12);
+            }
+        }
+
+        // Every namespaced species is resolved when evaluating the system
+        let sim = ODESolver::new(env, Beam::new_constant(String::from("e"), 1.0).unwrap());
+        let y0 = sim.env().get_initial_values();
+        let mut dy = y0.clone() * 0.0;
+        sim.system(0.0, &y0, &mut dy);
+        assert!(dy.iter().all(|x| x.is_finite()));
+    }
+}
@@ -0,0 +1,157 @@
+/* ---------------------------- External imports ---------------------------- */
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/* ---------------------------- Internal imports ---------------------------- */
+use super::compartments::split_namespace;
+use super::errors::RadioBioError;
+use super::k_reactions::KReaction;
+
+/* -------------------------------------------------------------------------- */
+/*                         FUNCTION/STRUCT DEFINITIONS                        */
+/* -------------------------------------------------------------------------- */
+// Elemental composition and charge of a species
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Composition {
+    elements: BTreeMap<String, i32>,
+    charge: i32,
+}
+
+impl Composition {
+    // "HO2" -> {H: 1, O: 2}. Elements are an upper case letter followed
+    // by lower case letters, then an optional count.
+    pub fn from_formula(formula:&str, charge:i32) -> Result<Self, RadioBioError> {
+        let invalid = || RadioBioError::InvalidFormula(formula.to_string());
+        let mut elements = BTreeMap::new();
+        let chars: Vec<char> = formula.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            if !chars[i].is_ascii_uppercase() { return Err(invalid()); }
+            let start = i;
+            i += 1;
+            while i < chars.len() && chars[i].is_ascii_lowercase() { i += 1; }
+            let element: String = chars[start..i].iter().collect();
+            let digits = i;
+            while i < chars.len() && chars[i].is_ascii_digit() { i += 1; }
+            let count = match digits == i {
+                true => 1,
+                false => chars[digits..i].iter().collect::<String>()
+                                          .parse::<i32>()
+                                          .map_err(|_| invalid())?,
+            };
+            *elements.entry(element).or_insert(0) += count;
+        }
+        Ok(Self { elements, charge })
+    }
+
+    // Read the composition from the naming convention of the reactions
+    // files: formula followed by "_r" (radical), "_minus" or "_plus" tags,
+    // e.g. "O2_r_minus" or "O2_minus_minus". "e_aq" is the solvated electron.
+    // The compartment of namespaced labels is ignored.
+    pub fn from_label(label:&str) -> Option<Self> {
+        let (_, species) = split_namespace(label);
+        if species == "e_aq" {
+            return Some(Self { elements: BTreeMap::new(), charge: -1 });
+        }
+        let mut parts = species.split('_');
+        let formula = parts.next()?;
+        let mut charge = 0;
+        for tag in parts {
+            match tag {
+                "r" => {},
+                "minus" => charge -= 1,
+                "plus" => charge += 1,
+                _ => return None,
+            }
+        }
+        Self::from_formula(formula, charge).ok()
+    }
+
+    pub fn charge(&self) -> i32 { self.charge }
+    pub fn count(&self, element:&str) -> i32 {
+        self.elements.get(element).cloned().unwrap_or(0)
+    }
+    pub fn iter_elements(&self) -> impl Iterator<Item=(&String, &i32)> {
+        self.elements.iter()
+    }
+    pub fn is_zero(&self) -> bool {
+        self.charge == 0 && self.elements.values().all(|n| *n == 0)
+    }
+
+    // self += n . other
+    fn add_scaled(&mut self, other:&Composition, n:i32) {
+        for (element, count) in other.elements.iter() {
+            *self.elements.entry(element.clone()).or_insert(0) += n * count;
+        }
+        self.charge += n * other.charge;
+    }
+}
+
+// Non zero elements then charge, e.g. "H: -1, O: -1, charge: +1"
+impl fmt::Display for Composition {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        let mut parts: Vec<String> = self.elements.iter()
+            .filter(|(_, n)| **n != 0)
+            .map(|(element, n)| format!("{element}: {n:+}"))
+            .collect();
+        if self.charge != 0 {
+            parts.push(format!("charge: {:+}", self.charge));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+// Declared compositions win over the ones read from the labels
+pub fn composition_of(label:&str, declared:&HashMap<String, Composition>)
+-> Option<Composition> {
+    let (_, species) = split_namespace(label);
+    declared.get(label)
+            .or_else(|| declared.get(species))
+            .cloned()
+            .or_else(|| Composition::from_label(label))
+}
+
+// Products minus reactants. Ok(zero) for a balanced reaction, Err with the
+// first species of unknown composition.
+pub fn reaction_balance(reaction:&KReaction, declared:&HashMap<String, Composition>)
+-> Result<Composition, String> {
+    let mut balance = Composition::default();
+    let sides = reaction.iter_reactants().map(|(sp, n)| (sp, -(*n as i32)))
+        .chain(reaction.iter_products().map(|(sp, n)| (sp, *n as i32)));
+    for (sp, n) in sides {
+        match composition_of(sp.as_str(), declared) {
+            Some(comp) => balance.add_scaled(&comp, n),
+            None => return Err(sp.as_str().to_string()),
+        }
+    }
+    Ok(balance)
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   TESTING                                  */
+/* -------------------------------------------------------------------------- */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compositions() {
+        let sp = Composition::from_label("intra:O2_r_minus").unwrap();
+        assert_eq!((sp.count("O"), sp.count("H"), sp.charge()), (2, 0, -1));
+        let sp = Composition::from_label("HO2_minus").unwrap();
+        assert_eq!((sp.count("O"), sp.count("H"), sp.charge()), (2, 1, -1));
+        assert_eq!(Composition::from_label("O2_minus_minus").unwrap().charge(), -2);
+        assert_eq!(Composition::from_formula("C2H6OS", 0).unwrap().count("C"), 2);
+        assert!(Composition::from_label("dmso").is_none());
+
+        let mut kr = KReaction::new_empty(Some(1.9e1));
+        kr.add_reactant("e_aq");
+        kr.add_reactant("H2O");
+        kr.add_product("H_r");
+        let declared = HashMap::new();
+        let balance = reaction_balance(&kr, &declared).unwrap();
+        assert_eq!(format!("{balance}"), "H: -1, O: -1, charge: +1");
+        kr.add_product("OH_minus");
+        assert!(reaction_balance(&kr, &declared).unwrap().is_zero());
+    }
+}
@@ -0,0 +1,99 @@
+/* ---------------------------- External imports ---------------------------- */
+
+
+/* ---------------------------- Internal imports ---------------------------- */
+use super::compartments::NAMESPACE_SEPARATOR;
+
+/* -------------------------------------------------------------------------- */
+/*                         FUNCTION/STRUCT DEFINITIONS                        */
+/* -------------------------------------------------------------------------- */
+// Accepted arrows between both sides of an equation ("=" as in KPP files)
+const ARROWS: [&str; 2] = ["->", "="];
+
+// Written form of a reaction: "2 e_aq -> H2 + 2 OH_minus"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Equation {
+    pub reactants: Vec<(usize, String)>,
+    pub products: Vec<(usize, String)>,
+}
+
+impl Equation {
+    // Errors are plain messages, the caller knows which reaction it is
+    pub fn parse(equation:&str) -> Result<Self, String> {
+        let (lhs, rhs) = ARROWS.iter()
+            .find_map(|arrow| equation.split_once(arrow))
+            .ok_or_else(|| format!("missing arrow, expected one of {:?}", ARROWS))?;
+        if ARROWS.iter().any(|arrow| rhs.contains(arrow)) {
+            return Err(String::from("more than one arrow"));
+        }
+        Ok(Self {
+            reactants: parse_side(lhs).map_err(|e| format!("reactants: {e}"))?,
+            products: parse_side(rhs).map_err(|e| format!("products: {e}"))?,
+        })
+    }
+
+    // Labels repeated as many times as their coefficient
+    pub fn expanded_reactants(&self) -> Vec<String> { expand(&self.reactants) }
+    pub fn expanded_products(&self) -> Vec<String> { expand(&self.products) }
+}
+
+fn expand(side:&[(usize, String)]) -> Vec<String> {
+    side.iter()
+        .flat_map(|(n, sp)| std::iter::repeat_n(sp.clone(), *n))
+        .collect()
+}
+
+// "2 e_aq + H2O" -> [(2, "e_aq"), (1, "H2O")]
+fn parse_side(side:&str) -> Result<Vec<(usize, String)>, String> {
+    if side.trim().is_empty() {
+        return Err(String::from("no species given"));
+    }
+    let mut out = vec![];
+    for term in side.split('+') {
+        let term = term.trim();
+        if term.is_empty() {
+            return Err(String::from("empty term around '+'"));
+        }
+        let digits = term.find(|c:char| !c.is_ascii_digit()).unwrap_or(term.len());
+        let coef = match digits {
+            0 => 1,
+            _ => term[..digits].parse::<usize>()
+                               .map_err(|_| format!("invalid coefficient in '{term}'"))?,
+        };
+        let label = term[digits..].trim();
+        if coef == 0 {
+            return Err(format!("null coefficient in '{term}'"));
+        }
+        if label.is_empty() {
+            return Err(format!("missing species after '{}'", &term[..digits]));
+        }
+        if let Some(c) = label.chars()
+            .find(|c| !(c.is_ascii_alphanumeric() || *c == '_' || *c == NAMESPACE_SEPARATOR)) {
+            return Err(format!("unexpected character '{c}' in '{label}'"));
+        }
+        out.push((coef, label.to_string()));
+    }
+    Ok(out)
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   TESTING                                  */
+/* -------------------------------------------------------------------------- */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_equations() {
+        let eq = Equation::parse("2 e_aq -> H2 + 2OH_minus").unwrap();
+        assert_eq!(eq.reactants, vec![(2, String::from("e_aq"))]);
+        assert_eq!(eq.expanded_products(), vec!["H2", "OH_minus", "OH_minus"]);
+        assert_eq!(Equation::parse("intra:O2 = extra:O2").unwrap().products,
+                   vec![(1, String::from("extra:O2"))]);
+
+        assert!(Equation::parse("e_aq + H2O").unwrap_err().contains("arrow"));
+        assert!(Equation::parse("e_aq + -> H_r").unwrap_err().contains("reactants"));
+        assert!(Equation::parse("e_aq -> 0 H_r").unwrap_err().contains("null"));
+        assert!(Equation::parse("e_aq -> H_r, OH").unwrap_err().contains("','"));
+    }
+}
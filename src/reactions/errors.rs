@@ -1,4 +1,6 @@
 use thiserror::Error;
+use std::io;
+use super::validation::ValidationReport;
 
 
 #[derive(Error, Debug)]
@@ -26,4 +28,47 @@ pub enum RadioBioError {
 
   #[error("Index: {0} of vec<SimSpecies> does not correspond to a Tracked Species")]
   NotATrackedSpeciesIndex(usize),
-}
\ No newline at end of file
+
+  #[error("Unknown compartment ({0})")]
+  UnknownCompartment(String),
+
+  #[error("Invalid transfer of {0}: {1}")]
+  InvalidTransfer(String, String),
+
+  #[error("Invalid concentration profile for {0}: {1}")]
+  InvalidProfile(String, String),
+
+  #[error("Invalid equation for reaction #{index} ({equation}): {message}")]
+  InvalidEquation { index: usize, equation: String, message: String },
+
+  #[error("{0} includes itself")]
+  IncludeCycle(String),
+
+  #[error("Unknown reaction id {0} in {1}")]
+  UnknownReactionId(String, String),
+
+  #[error("Cannot write the mechanism: {0}")]
+  Serialization(String),
+
+  #[error("Invalid alias: {0}")]
+  InvalidAlias(String),
+
+  #[error("Invalid quantity: {0}")]
+  InvalidQuantity(String),
+
+  #[error("Invalid chemical formula ({0})")]
+  InvalidFormula(String),
+
+  #[error("Cannot import {path}:\n{}", .issues.join("\n"))]
+  KppImport { path: String, issues: Vec<String> },
+
+  // The I/O error is the source, printed after this message by {:#}
+  #[error("Cannot read {path}")]
+  FileAccess { path: String, source: io::Error },
+
+  #[error("{path}:{line}:{column}: {message}")]
+  Parse { path: String, line: usize, column: usize, message: String },
+
+  #[error("Invalid reactions file:\n{0}")]
+  Validation(ValidationReport),
+}
@@ -7,6 +7,7 @@ use std::collections::HashMap;
 use super::traits::{IsChemicalReaction};
 use super::errors::RadioBioError;
 use super::species::ReactionSpecies;
+use super::metadata::Metadata;
 use crate::physics::utils::ge_to_kr;
 
 /* -------------------------------------------------------------------------- */
@@ -16,7 +17,19 @@ use crate::physics::utils::ge_to_kr;
 #[derive(Debug, Clone)]
 pub enum ChemicalReaction {
     KReaction(KReaction),
-    Radiolytic(RadiolyticReaction)
+    Radiolytic(RadiolyticReaction),
+    Transfer(TransferReaction),
+}
+
+impl ChemicalReaction {
+    // Factor applied to the reaction value for the products. Only differs
+    // from 1 for transfers between compartments of different volumes.
+    pub fn yield_factor(&self) -> f64 {
+        match self {
+            ChemicalReaction::Transfer(r) => r.volume_ratio(),
+            _ => 1.0,
+        }
+    }
 }
 
 impl IsChemicalReaction for ChemicalReaction {
@@ -27,13 +40,16 @@ impl IsChemicalReaction for ChemicalReaction {
                 r.compute_reaction(current_dose_rate, sp),
             ChemicalReaction::Radiolytic(r) =>
                 r.compute_reaction(current_dose_rate, sp),
+            ChemicalReaction::Transfer(r) =>
+                r.compute_reaction(current_dose_rate, sp),
         }
     }
 
-    fn species(&self) -> std::slice::Iter<ReactionSpecies> {
+    fn species(&self) -> std::slice::Iter<'_, ReactionSpecies> {
         match self {
             ChemicalReaction::KReaction(r) => r.species(),
             ChemicalReaction::Radiolytic(r) => r.species(),
+            ChemicalReaction::Transfer(r) => r.species(),
         }
     }
 }
@@ -51,6 +67,7 @@ pub struct KReaction{
     species: Vec<ReactionSpecies>,
     k_value: f64,
     stoichio: Vec<usize>,
+    metadata: Metadata,
 }
 
 impl IsChemicalReaction for KReaction {
@@ -69,7 +86,7 @@ impl IsChemicalReaction for KReaction {
         }
         Ok(res)
     }
-    fn species(&self) -> std::slice::Iter<ReactionSpecies> {
+    fn species(&self) -> std::slice::Iter<'_, ReactionSpecies> {
         self.species.iter()
     }
 
@@ -86,6 +103,7 @@ impl KReaction {
         Self {species,
               k_value,
               stoichio,
+              metadata: Metadata::default(),
             }
     }
 
@@ -94,9 +112,15 @@ impl KReaction {
             species: vec![],
             k_value: k_val.unwrap_or(0.0),
             stoichio: vec![],
+            metadata: Metadata::default(),
         }
     }
 
+    pub fn metadata(&self) -> &Metadata { &self.metadata }
+    pub fn set_metadata(&mut self, metadata:Metadata) {
+        self.metadata = metadata;
+    }
+
     pub fn number_of_reactants(&self) -> usize {
         self.species.iter()
                     .filter(|sp| sp.is_reactant())
@@ -114,12 +138,12 @@ impl KReaction {
     pub fn iter_reactants(&self) -> impl Iterator<Item=(&ReactionSpecies, &usize)> {
         self.species.iter()
                     .zip(&self.stoichio)
-                    .filter(|(sp, stoi)| sp.is_reactant())
+                    .filter(|(sp, _)| sp.is_reactant())
     }
     pub fn iter_products(&self) -> impl Iterator<Item=(&ReactionSpecies, &usize)> {
         self.species.iter()
                     .zip(&self.stoichio)
-                    .filter(|(sp, stoi)| !sp.is_reactant())
+                    .filter(|(sp, _)| !sp.is_reactant())
     }
 
     pub fn iter_reactants_indexed(&self)
@@ -217,8 +241,8 @@ impl fmt::Display for KReaction {
             }
         }
         out.push_str(" -> ");
-        for (idx, sp) in self.iter_products_indexed(){
-            if idx > 0 {
+        for (n, (idx, sp)) in self.iter_products_indexed().enumerate(){
+            if n > 0 {
                 out.push_str(" + ");
             }
             let stoichio = self.stoichio[idx];
@@ -246,14 +270,20 @@ impl fmt::Display for KReaction {
 pub struct RadiolyticReaction {
     species: Vec<ReactionSpecies>,
     reaction_cst : f64, //Kr => concentration yield (mol/l/Gy)
+    metadata: Metadata, // Of the G-value
 }
 
 impl RadiolyticReaction {
     pub fn new_from_ge(species:String, ge: f64) -> Self {
         Self { species: vec![ReactionSpecies::Product(species),],
-               reaction_cst: ge_to_kr(ge).unwrap() }
+               reaction_cst: ge_to_kr(ge).unwrap(),
+               metadata: Metadata::default() }
     }
     pub fn kr(&self) -> f64 {self.reaction_cst}
+    pub fn metadata(&self) -> &Metadata { &self.metadata }
+    pub fn set_metadata(&mut self, metadata:Metadata) {
+        self.metadata = metadata;
+    }
 }
 
 impl IsChemicalReaction for RadiolyticReaction {
@@ -263,10 +293,58 @@ impl IsChemicalReaction for RadiolyticReaction {
 
     }
 
-    fn species(&self) -> std::slice::Iter<ReactionSpecies> {
+    fn species(&self) -> std::slice::Iter<'_, ReactionSpecies> {
+        self.species.iter()
+    }
+
+}
+
+/* -------------------------------------------------------------------------- */
+/*                 TRANSFER BETWEEN COMPARTMENTS DEFINITION                   */
+/* -------------------------------------------------------------------------- */
+// First order transfer of one species from a compartment to another:
+// rate = k.[from], expressed in the concentration units of `from`. The
+// production in `to` is scaled by volume_ratio = V_from / V_to to
+// conserve matter.
+#[derive(Debug, Clone)]
+pub struct TransferReaction {
+    species: Vec<ReactionSpecies>,
+    k_value: f64,
+    volume_ratio: f64,
+}
+
+impl TransferReaction {
+    pub fn new(from:String, to:String, k_value:f64, volume_ratio:f64) -> Self {
+        Self { species: vec![ReactionSpecies::Reactant(from),
+                             ReactionSpecies::Product(to)],
+               k_value,
+               volume_ratio,
+        }
+    }
+    pub fn k_value(&self) -> f64 {self.k_value}
+    pub fn volume_ratio(&self) -> f64 {self.volume_ratio}
+    pub fn from(&self) -> &String {self.species[0].as_str()}
+    pub fn to(&self) -> &String {self.species[1].as_str()}
+}
+
+impl IsChemicalReaction for TransferReaction {
+    fn compute_reaction(&self, _:f64, sp:&HashMap<String, f64>)
+    -> Result<f64> {
+        match sp.get(self.from()) {
+            Some(cc) => Ok(self.k_value * cc),
+            None => bail!(RadioBioError::UnknownSpecies(self.from().to_string())),
+        }
+    }
+
+    fn species(&self) -> std::slice::Iter<'_, ReactionSpecies> {
         self.species.iter()
     }
+}
 
+impl fmt::Display for TransferReaction {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} => {}", self.from(), self.to())
+    }
 }
 
 //Obsolete implementations
@@ -0,0 +1,76 @@
+/* ---------------------------- External imports ---------------------------- */
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+/* ---------------------------- Internal imports ---------------------------- */
+use super::reactions_parser::{present, serialize_present};
+
+/* -------------------------------------------------------------------------- */
+/*                         FUNCTION/STRUCT DEFINITIONS                        */
+/* -------------------------------------------------------------------------- */
+// Uncertainty on a rate constant, pKa or G-value
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum Uncertainty {
+    Factor(f64), // value / f to value . f
+    Sigma(f64),  // Standard deviation, in the unit of the value
+}
+
+impl Uncertainty {
+    pub fn is_valid(&self) -> bool {
+        match self {
+            Uncertainty::Factor(f) => f.is_finite() && *f >= 1.0,
+            Uncertainty::Sigma(s) => s.is_finite() && *s >= 0.0,
+        }
+    }
+}
+
+impl fmt::Display for Uncertainty {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Uncertainty::Factor(x) => write!(f, "factor {x}"),
+            Uncertainty::Sigma(x) => write!(f, "± {x}"),
+        }
+    }
+}
+
+// Traceability of a reaction, acid/base couple or G-value. Every field is
+// optional in the reactions files.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Metadata {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub label: String,
+    // Citation or DOI of the value
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub reference: String,
+    #[serde(default, deserialize_with = "present", serialize_with = "serialize_present",
+            skip_serializing_if = "Option::is_none")]
+    pub uncertainty: Option<Uncertainty>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub notes: String,
+}
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        *self == Metadata::default()
+    }
+}
+
+// Non empty fields on one line, e.g. "R12, Buxton 1988, factor 1.5"
+impl fmt::Display for Metadata {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        let mut parts: Vec<String> = [&self.id, &self.label, &self.reference]
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .cloned()
+            .collect();
+        if let Some(uncertainty) = self.uncertainty {
+            parts.push(format!("{uncertainty}"));
+        }
+        if !self.notes.is_empty() {
+            parts.push(self.notes.clone());
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
@@ -0,0 +1,197 @@
+/* ---------------------------- External imports ---------------------------- */
+use std::collections::HashMap;
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+/* ---------------------------- Internal imports ---------------------------- */
+use super::errors::RadioBioError;
+use crate::units::{self, Quantity};
+
+/* -------------------------------------------------------------------------- */
+/*                         FUNCTION/STRUCT DEFINITIONS                        */
+/* -------------------------------------------------------------------------- */
+// Time dependent concentration of a fixed (clamped) species.
+// Points are (time [s], concentration [mol/l]) sorted by time.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum ConcentrationProfile {
+    Constant(f64),
+    // Piecewise constant: each value holds until the next point
+    Steps(Vec<(f64, f64)>),
+    // Linear interpolation of a measured trace, held constant outside
+    Table(Vec<(f64, f64)>),
+}
+
+impl ConcentrationProfile {
+    pub fn at(&self, time:f64) -> f64 {
+        match self {
+            ConcentrationProfile::Constant(cc) => *cc,
+            ConcentrationProfile::Steps(points) => {
+                let idx = points.partition_point(|(t, _)| *t <= time);
+                points[idx.saturating_sub(1)].1
+            },
+            ConcentrationProfile::Table(points) => {
+                let idx = points.partition_point(|(t, _)| *t <= time);
+                if idx == 0 { return points[0].1; }
+                if idx == points.len() { return points[idx-1].1; }
+                let (t0, c0) = points[idx-1];
+                let (t1, c1) = points[idx];
+                c0 + (c1 - c0) * (time - t0) / (t1 - t0)
+            },
+        }
+    }
+
+    // Times where the profile is discontinuous or has a kink
+    pub fn breakpoints(&self) -> Vec<f64> {
+        match self {
+            ConcentrationProfile::Constant(_) => vec![],
+            ConcentrationProfile::Steps(points) |
+            ConcentrationProfile::Table(points) =>
+                points.iter().map(|(t, _)| *t).collect(),
+        }
+    }
+
+    pub fn check(&self, species:&str) -> Result<(), RadioBioError> {
+        let points = match self {
+            ConcentrationProfile::Constant(cc) => {
+                if *cc < 0.0 {
+                    return Err(RadioBioError::NegativeConcentration(
+                        *cc, species.to_string()));
+                }
+                return Ok(());
+            },
+            ConcentrationProfile::Steps(points) |
+            ConcentrationProfile::Table(points) => points,
+        };
+        if points.is_empty() {
+            return Err(RadioBioError::InvalidProfile(
+                species.to_string(), String::from("no point given")));
+        }
+        if points.windows(2).any(|w| w[1].0 <= w[0].0) {
+            return Err(RadioBioError::InvalidProfile(
+                species.to_string(), String::from("times must be increasing")));
+        }
+        match points.iter().find(|(_, cc)| *cc < 0.0) {
+            Some((_, cc)) => Err(RadioBioError::NegativeConcentration(
+                *cc, species.to_string())),
+            None => Ok(()),
+        }
+    }
+}
+
+// Profile as written in the files, with units: Steps([(0, "40 mmHg"),
+// ("10 ms", "5 mmHg")])
+#[derive(Deserialize)]
+enum RawProfile {
+    Constant(Quantity),
+    Steps(Vec<(Quantity, Quantity)>),
+    Table(Vec<(Quantity, Quantity)>),
+}
+
+impl RawProfile {
+    fn resolve(self, species:&str) -> Result<ConcentrationProfile, RadioBioError> {
+        let points = |points:Vec<(Quantity, Quantity)>| points.into_iter()
+            .map(|(t, cc)| Ok((t.time()?, cc.concentration(species)?)))
+            .collect::<Result<Vec<_>, RadioBioError>>();
+        Ok(match self {
+            RawProfile::Constant(cc) => ConcentrationProfile::Constant(cc.concentration(species)?),
+            RawProfile::Steps(p) => ConcentrationProfile::Steps(points(p)?),
+            RawProfile::Table(p) => ConcentrationProfile::Table(points(p)?),
+        })
+    }
+}
+
+// Profiles by species, converted to [s] and [mol/l] when read
+pub(crate) fn profiles<'de, D>(deserializer: D)
+-> Result<HashMap<String, ConcentrationProfile>, D::Error>
+where D: Deserializer<'de> {
+    let raw = HashMap::<String, RawProfile>::deserialize(deserializer)?;
+    raw.into_iter()
+       .map(|(sp, profile)| profile.resolve(&sp).map(|p| (sp, p)))
+       .collect::<Result<_, _>>()
+       .map_err(de::Error::custom)
+}
+
+// Instantaneous addition of a tracked species (e.g. scavenger injection)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(try_from = "RawBolus")]
+pub struct Bolus {
+    pub time: f64,      // [s]
+    pub species: String,
+    pub amount: f64,    // Concentration increase [mol/l]
+}
+
+#[derive(Deserialize)]
+struct RawBolus {
+    #[serde(deserialize_with = "units::seconds")]
+    time: f64,
+    species: String,
+    amount: Quantity,
+}
+
+impl TryFrom<RawBolus> for Bolus {
+    type Error = RadioBioError;
+    fn try_from(raw:RawBolus) -> Result<Self, Self::Error> {
+        Ok(Bolus {
+            amount: raw.amount.concentration(&raw.species)?,
+            time: raw.time,
+            species: raw.species,
+        })
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   TESTING                                  */
+/* -------------------------------------------------------------------------- */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::{Beam, ODESolver};
+    use crate::ode_solver::rk4::Rk4;
+    use crate::reactions::parse_reactions_file;
+
+    #[test]
+    fn test_profiles() {
+        let points = vec![(0.0, 1.0), (1.0, 3.0), (2.0, 2.0)];
+        let steps = ConcentrationProfile::Steps(points.clone());
+        assert_eq!(steps.at(-1.0), 1.0);
+        assert_eq!(steps.at(0.5), 1.0);
+        assert_eq!(steps.at(1.0), 3.0);
+        assert_eq!(steps.at(5.0), 2.0);
+
+        let table = ConcentrationProfile::Table(points);
+        assert_eq!(table.at(-1.0), 1.0);
+        assert_float_absolute_eq!(table.at(0.5), 2.0, 1e-12);
+        assert_float_absolute_eq!(table.at(1.5), 2.5, 1e-12);
+        assert_eq!(table.at(5.0), 2.0);
+        assert!(table.check("O2").is_ok());
+        assert!(ConcentrationProfile::Table(vec![(1.0, 1.0), (0.0, 1.0)])
+                .check("O2").is_err());
+    }
+
+    #[test]
+    fn boluses_are_added_at_their_time() {
+        // H2 is inert without beam in the simple mechanism
+        let file = format!("{}/data/reactions_simple.ron", env!("CARGO_MANIFEST_DIR"));
+        let content = fs::read_to_string(file).unwrap();
+        let end = content.rfind(')').unwrap();
+        let content = format!("{}    boluses: [(time: 0.5, species: \"H2\", amount: 1e-5),
+                                      (time: 1.0, species: \"H2\", amount: 2e-5)],\n)",
+                              &content[..end]);
+        let path = crate::test_dir("boluses").join("reactions.ron");
+        fs::write(&path, content).unwrap();
+
+        let env = parse_reactions_file(path.to_str().unwrap()).unwrap();
+        let idx = env.map_all_species()["H2"];
+        let y0 = env.get_initial_values();
+        let chem = ODESolver::new(env, Beam::new_constant(String::from("e"), 0.0).unwrap());
+        let mut rk4 = Rk4::new(chem, 0.0, y0, 1.0, 0.1);
+        rk4.integrate().unwrap();
+        let (x, y) = (rk4.x_out(), rk4.y_out());
+        // [µmol/l] before and after each bolus, the one at x_end included
+        let h2: Vec<(f64, f64)> = x.iter().zip(y.iter()).map(|(t, y)| (*t, y[idx])).collect();
+        let at = |t:f64| h2.iter().filter(|(x, _)| *x == t).map(|(_, y)| *y).collect::<Vec<_>>();
+        assert_eq!(at(0.5), vec![0.0, 10.0]);
+        assert_eq!(at(1.0), vec![10.0, 30.0]);
+        assert_eq!(h2[h2.len()-1], (1.0, 30.0));
+    }
+}
@@ -0,0 +1,64 @@
+/* ---------------------------- External imports ---------------------------- */
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+/* ---------------------------- Internal imports ---------------------------- */
+
+
+/* -------------------------------------------------------------------------- */
+/*                         FUNCTION/STRUCT DEFINITIONS                        */
+/* -------------------------------------------------------------------------- */
+// File where an entry of the merged mechanism was last defined
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Origin {
+    pub kind: String,  // "reaction", "G-value", "initial concentration"...
+    pub name: String,  // Reaction id (or equation), species label...
+    pub file: String,
+}
+
+// Origin of every entry of a mechanism built from several files,
+// in the order the entries were first defined
+#[derive(Debug, Clone, Default)]
+pub struct Provenance {
+    origins: Vec<Origin>,
+}
+
+impl Provenance {
+    pub fn new() -> Self { Self::default() }
+
+    // Later definitions replace earlier ones
+    pub fn set(&mut self, kind:&str, name:&str, file:&str) {
+        match self.origins.iter_mut().find(|o| o.kind == kind && o.name == name) {
+            Some(origin) => origin.file = file.to_string(),
+            None => self.origins.push(Origin {
+                kind: kind.to_string(),
+                name: name.to_string(),
+                file: file.to_string(),
+            }),
+        }
+    }
+    pub fn remove(&mut self, kind:&str, name:&str) {
+        self.origins.retain(|o| !(o.kind == kind && o.name == name));
+    }
+
+    pub fn origin_of(&self, kind:&str, name:&str) -> Option<&str> {
+        self.origins.iter()
+            .find(|o| o.kind == kind && o.name == name)
+            .map(|o| o.file.as_str())
+    }
+    pub fn iter(&self) -> impl Iterator<Item=&Origin> {
+        self.origins.iter()
+    }
+    pub fn is_empty(&self) -> bool { self.origins.is_empty() }
+}
+
+// One entry per line: "reaction R2: data/base.ron"
+impl fmt::Display for Provenance {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        for (idx, o) in self.origins.iter().enumerate() {
+            if idx > 0 { writeln!(f)?; }
+            write!(f, "{} {}: {}", o.kind, o.name, o.file)?;
+        }
+        Ok(())
+    }
+}
@@ -1,17 +1,24 @@
 #![allow(dead_code)]
+/* --------------------------- Module declarations -------------------------- */
+mod json;
+mod kpp;
+mod includes;
+
 /* ---------------------------- External imports ---------------------------- */
 use std::ops::IndexMut;
-use std::{fs::File};
-use std::collections::HashMap;
+use std::path::Path;
+use std::fs;
+use std::collections::{HashMap, BTreeMap, BTreeSet};
 use itertools::{chain};
-use ron::{de::from_reader};
-use serde::Deserialize;
+use ron::de::from_str;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 /* ---------------------------- Internal imports ---------------------------- */
 use super::k_reactions::{
     ReactionRateIndex,
     ChemicalReaction,
-    RadiolyticReaction};
+    RadiolyticReaction,
+    TransferReaction};
 use super::traits::{
     RawSpecies,
     IsTrackedSpecies,
@@ -25,7 +32,17 @@ use super::{
 };
 use super::species::ReactionSpecies;
 use super::errors::RadioBioError;
+use super::compartments::{Compartment, is_namespaced, namespaced};
+use super::profiles::{self, ConcentrationProfile, Bolus};
+use super::validation::{ValidationReport, PKA_RANGE};
+use super::equation::Equation;
+use super::provenance::Provenance;
+use super::metadata::{Metadata, Uncertainty};
+use super::composition::reaction_balance;
+use super::registry::{SpeciesEntry, SpeciesRegistry};
 use crate::env::Env;
+use crate::units;
+use crate::physics::gas::Headspace;
 /* -------------------------------------------------------------------------- */
 /*                         FUNCTION/STRUCT DEFINITIONS                        */
 /* -------------------------------------------------------------------------- */
@@ -35,28 +52,108 @@ impl IsChemicalReactionList for Vec<ChemicalReaction> {
     }
 }
 
-#[derive(Debug, Deserialize)]
+// Every field is optional so that a file can be a module of another one.
+// Optional parts are only written back when used.
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
 struct RonReactions {
-    pub bio_param: BioParam,
+    #[serde(default)]
+    pub bio_param: RonBioParam,
+    #[serde(default, deserialize_with = "units::concentrations", serialize_with = "sorted")]
     pub fixed_concentrations: HashMap<String, f64>,
+    #[serde(default, deserialize_with = "units::concentrations", serialize_with = "sorted")]
     pub initial_concentrations: HashMap<String, f64>,
+    #[serde(default)]
     pub acid_base: Vec<RonAcidBase>,
+    #[serde(default)]
     pub k_reactions: Vec<RonKReaction>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub compartments: Vec<Compartment>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transfers: Vec<RonTransfer>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headspace: Option<Headspace>,
+    #[serde(default, deserialize_with = "profiles::profiles",
+            skip_serializing_if = "HashMap::is_empty", serialize_with = "sorted")]
+    pub fixed_profiles: HashMap<String, ConcentrationProfile>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub boluses: Vec<Bolus>,
+    // Registry: composition of labels not following the convention,
+    // display names, aliases, physical properties
+    #[serde(default, skip_serializing_if = "HashMap::is_empty", serialize_with = "sorted")]
+    pub species: HashMap<String, SpeciesEntry>,
+    // Files merged before this one, paths relative to this file
+    #[serde(default, skip_serializing)]
+    pub include: Vec<String>,
+    // New k values of included reactions, by reaction id
+    #[serde(default, skip_serializing)]
+    pub k_overrides: HashMap<String, f64>,
+    // Entries of the included files to drop
+    #[serde(default, skip_serializing)]
+    pub remove: RonRemovals,
 }
-//Struct for Ron deserialization
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Default, Clone)]
+struct RonRemovals {
+    #[serde(default)]
+    reactions: Vec<String>, // Reaction ids
+    #[serde(default)]
+    species: Vec<String>,   // Every entry involving the species
+}
+//Struct for Ron deserialization. Species are given either as lists or
+//as an equation: (equation: "2 e_aq -> H2 + 2 OH_minus", k: 1.1e10)
+//followed by the optional metadata fields (reference: "...", ...)
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
 struct RonKReaction {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    id: String,
+    #[serde(default)]
     reactants: Vec<String>,
+    #[serde(default)]
     products: Vec<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    equation: String,
+    #[serde(alias = "k")]
     k_value: f64,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    label: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    reference: String,
+    #[serde(default, deserialize_with = "present", serialize_with = "serialize_present",
+            skip_serializing_if = "Option::is_none")]
+    uncertainty: Option<Uncertainty>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    notes: String,
 }
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
 #[allow(non_snake_case)]
 struct RonAcidBase {
     acid: String,
     base: String,
     pKa: f64,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    label: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    reference: String,
+    #[serde(default, deserialize_with = "present", serialize_with = "serialize_present",
+            skip_serializing_if = "Option::is_none")]
+    uncertainty: Option<Uncertainty>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    notes: String,
 }
+// First order exchange of a species between two compartments. At
+// equilibrium [to] = partition * [from].
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct RonTransfer {
+    species: String,
+    from: String,
+    to: String,
+    k_value: f64,
+    #[serde(default = "default_partition")]
+    partition: f64,
+}
+fn default_partition() -> f64 { 1.0 }
+
 #[derive(Debug, Deserialize, Clone)]
 #[allow(non_snake_case)]
 pub struct BioParam {
@@ -64,33 +161,276 @@ pub struct BioParam {
     pub radiolytic: HashMap<String, f64>
 }
 
-// Read & Parse from .ron file
-pub fn parse_reactions_file(path: &str) -> Result<Env, RadioBioError> {
-    let file = File::open(&path).expect("Failed Opening
-        config reactions file");
+// pH used when no file of the mechanism gives one
+pub const NEUTRAL_PH: f64 = 7.0;
+
+// Same as BioParam but the pH may be left to an included file
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[allow(non_snake_case)]
+struct RonBioParam {
+    #[serde(default, deserialize_with = "present", serialize_with = "serialize_present",
+            skip_serializing_if = "Option::is_none")]
+    pH: Option<f64>,
+    #[serde(default, serialize_with = "sorted")]
+    radiolytic: HashMap<String, f64>,
+    // Reference and uncertainty of the G-values, by species
+    #[serde(default, skip_serializing_if = "HashMap::is_empty", serialize_with = "sorted")]
+    radiolytic_metadata: HashMap<String, Metadata>,
+}
+
+// Optional field written without Some(...)
+pub(crate) fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where D: Deserializer<'de>, T: Deserialize<'de> {
+    T::deserialize(deserializer).map(Some)
+}
+pub(crate) fn serialize_present<S, T>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+where S: Serializer, T: Serialize {
+    match value {
+        Some(value) => value.serialize(serializer),
+        None => serializer.serialize_none(),
+    }
+}
+// Maps are written in a reproducible order
+fn sorted<S, T>(map: &HashMap<String, T>, serializer: S) -> Result<S::Ok, S::Error>
+where S: Serializer, T: Serialize {
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+// Fully resolved content of the files an Env was built from: includes
+// merged, compartments expanded and headspace species declared. Written
+// back to a file, it gives the same Env.
+#[derive(Debug, Clone)]
+pub struct Mechanism {
+    config: RonReactions,
+}
+
+impl Mechanism {
+    // The provenance is kept as a comment header
+    pub fn to_ron(&self, provenance:&Provenance) -> Result<String, RadioBioError> {
+        let body = ron::ser::to_string_pretty(&self.config,
+                                              ron::ser::PrettyConfig::new().compact_arrays(true))
+            .map_err(|e| RadioBioError::Serialization(e.to_string()))?;
+        let mut out = String::from("// Resolved reactions mechanism\n");
+        for origin in provenance.iter() {
+            out.push_str(&format!("// {} {}: {}\n", origin.kind, origin.name, origin.file));
+        }
+        out.push_str(&body);
+        out.push('\n');
+        Ok(out)
+    }
+    pub fn to_json(&self, provenance:&Provenance) -> Result<String, RadioBioError> {
+        json::to_json(&self.config, provenance)
+    }
+    // Format from the extension, RON by default
+    pub fn write(&self, path:&str, provenance:&Provenance) -> Result<(), RadioBioError> {
+        let content = match is_json_file(path) {
+            true => self.to_json(provenance)?,
+            false => self.to_ron(provenance)?,
+        };
+        fs::write(path, content).map_err(|source| RadioBioError::FileAccess {
+            path: path.to_string(),
+            source,
+        })
+    }
 
-    // Get data from file
-    let config: RonReactions = match from_reader(file){
-        Ok(x) => x,
-        Err(e) => {
-            println!("Failed to parse reactions data file: {}", e);
-            std::process::exit(1);
+    // Env of the mechanism with some values changed, the changes are
+    // recorded in the provenance
+    pub fn build_with(&self, changes:&[(ChemistryParameter, f64)], provenance:&Provenance)
+    -> Result<Env, RadioBioError> {
+        let mut config = self.config.clone();
+        let mut provenance = provenance.clone();
+        let registry = SpeciesRegistry::new(&config.species)?;
+        for (parameter, value) in changes {
+            match parameter {
+                ChemistryParameter::KValue(name) => {
+                    let reaction = config.k_reactions.iter_mut()
+                        .find(|r| r.key() == *name || r.equation_label() == *name
+                                  || r.to_kreaction().to_string() == *name)
+                        .ok_or_else(|| RadioBioError::UnknownReactionId(
+                            name.clone(), String::from(CHANGED)))?;
+                    reaction.k_value = *value;
+                    provenance.set("k value", &reaction.key(), CHANGED);
+                },
+                ChemistryParameter::GValue(sp) => {
+                    let sp = registry.resolve(sp);
+                    provenance.set("G-value", &sp, CHANGED);
+                    config.bio_param.radiolytic.insert(sp, *value);
+                },
+                ChemistryParameter::InitialConcentration(sp) => {
+                    let sp = registry.resolve(sp);
+                    provenance.set("initial concentration", &sp, CHANGED);
+                    config.initial_concentrations.insert(sp, *value);
+                },
+            }
         }
+        let env = build_env(config, provenance)?;
+        // A value of an untracked species would silently do nothing
+        let tracked = env.map_all_species();
+        for (parameter, _) in changes {
+            match parameter {
+                ChemistryParameter::GValue(sp) | ChemistryParameter::InitialConcentration(sp)
+                    if !tracked.contains_key(&registry.resolve(sp)) =>
+                    return Err(RadioBioError::UnknownSpecies(format!(
+                        "{sp} (changed but not simulated)"))),
+                _ => {},
+            }
+        }
+        Ok(env)
+    }
+}
+
+// Origin of the values changed after reading the files
+const CHANGED: &str = "changed parameters";
+
+// Value of a mechanism changed without editing its files
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum ChemistryParameter {
+    // By reaction id or equation ("2 e_aq -> H2 + 2 OH_minus")
+    KValue(String),
+    // [radical / 100eV / incident particle]
+    GValue(String),
+    // [mol/l]
+    InitialConcentration(String),
+}
+
+fn is_json_file(path:&str) -> bool {
+    Path::new(path).extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+// KPP equations, species or combined files
+fn is_kpp_file(path:&str) -> bool {
+    Path::new(path).extension()
+        .is_some_and(|ext| ["eqn", "spc", "kpp"].iter().any(|kpp| ext.eq_ignore_ascii_case(kpp)))
+}
+
+// Read & Parse from .ron file, with the files it includes
+pub fn parse_reactions_file(path: &str) -> Result<Env, RadioBioError> {
+    let (config, provenance) = includes::read_with_includes(path)?;
+    build_env(config, provenance)
+}
+
+// Run the semantic checks on a reactions file. Issues are reported, not
+// returned as errors: only reading/parsing failures give an Err.
+pub fn validate_reactions_file(path: &str) -> Result<ValidationReport, RadioBioError> {
+    let (mut config, _) = includes::read_with_includes(path)?;
+    resolve_aliases(&mut config)?;
+    expand_compartments(&mut config)?;
+    setup_headspace(&mut config)?;
+    Ok(check_parsed_reactions(&config))
+}
+
+// Get data from a single file
+fn read_reactions_file(path: &str) -> Result<RonReactions, RadioBioError> {
+    let content = fs::read_to_string(path)
+        .map_err(|source| RadioBioError::FileAccess {
+            path: path.to_string(),
+            source,
+        })?;
+    // Format from the extension, RON by default
+    let mut config: RonReactions = if is_json_file(path) {
+        json::from_json(path, &content)?
+    } else if is_kpp_file(path) {
+        kpp::from_kpp(path, &content)?
+    } else {
+        from_str(&content).map_err(|e| RadioBioError::Parse {
+            path: path.to_string(),
+            line: e.position.line,
+            column: e.position.col,
+            message: e.code.to_string(),
+        })?
     };
+    expand_equations(&mut config)?;
+    Ok(config)
+}
 
-    // Parse kReactions
-    let mut reactions_list: Vec<ChemicalReaction> = vec![];
-    for elt in &config.k_reactions {
-        let mut kr =
-            KReaction::new_empty(elt.get_k_value());
+// Replace the aliases declared in the registry by the labels they stand for
+fn resolve_aliases(config: &mut RonReactions) -> Result<SpeciesRegistry, RadioBioError> {
+    let registry = SpeciesRegistry::new(&config.species)?;
+    let resolve = |sp:&mut String| *sp = registry.resolve(sp);
+    for r in config.k_reactions.iter_mut() {
+        r.reactants.iter_mut().for_each(resolve);
+        r.products.iter_mut().for_each(resolve);
+    }
+    for ab in config.acid_base.iter_mut() {
+        resolve(&mut ab.acid);
+        resolve(&mut ab.base);
+    }
+    config.transfers.iter_mut().for_each(|t| resolve(&mut t.species));
+    config.boluses.iter_mut().for_each(|b| resolve(&mut b.species));
+    if let Some(headspace) = config.headspace.as_mut() {
+        resolve(&mut headspace.species);
+    }
 
-        for sp in elt.iter_reactants() {
-            kr.add_reactant(sp);
+    fn rename_keys<T>(map:&mut HashMap<String, T>, registry:&SpeciesRegistry, what:&str)
+    -> Result<(), RadioBioError> {
+        let mut out = HashMap::new();
+        for (sp, value) in map.drain() {
+            let label = registry.resolve(&sp);
+            if out.insert(label.clone(), value).is_some() {
+                return Err(RadioBioError::InvalidAlias(format!(
+                    "{label} is given twice in the {what}, under an alias")));
+            }
         }
-        for sp in elt.iter_products() {
-            kr.add_product(sp);
+        *map = out;
+        Ok(())
+    }
+    rename_keys(&mut config.fixed_concentrations, &registry, "fixed concentrations")?;
+    rename_keys(&mut config.initial_concentrations, &registry, "initial concentrations")?;
+    rename_keys(&mut config.fixed_profiles, &registry, "fixed profiles")?;
+    rename_keys(&mut config.bio_param.radiolytic, &registry, "G-values")?;
+    rename_keys(&mut config.bio_param.radiolytic_metadata, &registry, "G-value metadata")?;
+    Ok(registry)
+}
+
+// Turn the equation strings into reactants/products lists
+fn expand_equations(config: &mut RonReactions) -> Result<(), RadioBioError> {
+    for (idx, reaction) in config.k_reactions.iter_mut().enumerate() {
+        let invalid = |equation:&str, message:String| RadioBioError::InvalidEquation {
+            index: idx+1,
+            equation: equation.to_string(),
+            message,
+        };
+        let equation = std::mem::take(&mut reaction.equation);
+        if equation.is_empty() {
+            if reaction.reactants.is_empty() {
+                return Err(invalid(&reaction.equation_label(), String::from(
+                    "no reactant given, use `equation` or `reactants`/`products`")));
+            }
+            continue;
+        }
+        if !reaction.reactants.is_empty() || !reaction.products.is_empty() {
+            return Err(invalid(&equation, String::from(
+                "`equation` cannot be combined with `reactants`/`products`")));
         }
+        let parsed = Equation::parse(&equation)
+            .map_err(|message| invalid(&equation, message))?;
+        reaction.reactants = parsed.expanded_reactants();
+        reaction.products = parsed.expanded_products();
+    }
+    Ok(())
+}
 
+// Turn the content of a reactions file into a simulation environment
+fn build_env(mut config: RonReactions, provenance: Provenance)
+-> Result<Env, RadioBioError> {
+    let registry = resolve_aliases(&mut config)?;
+    expand_compartments(&mut config)?;
+    setup_headspace(&mut config)?;
+    for (sp, profile) in config.fixed_profiles.iter() {
+        profile.check(sp)?;
+    }
+    let report = check_parsed_reactions(&config);
+    if report.has_errors() {
+        return Err(RadioBioError::Validation(report));
+    }
+    let mechanism = Mechanism { config: config.clone() };
+
+    // Parse kReactions
+    let mut reactions_list: Vec<ChemicalReaction> = vec![];
+    for elt in &config.k_reactions {
+        let mut kr = elt.to_kreaction();
+        kr.set_metadata(elt.metadata());
         reactions_list.push_k_reaction(kr);
     }
 
@@ -100,12 +440,27 @@ pub fn parse_reactions_file(path: &str) -> Result<Env, RadioBioError> {
     // Parse radiolytic yields
     for (sp, ge) in config.bio_param.radiolytic.iter() {
         if !tracked_sp.contains(sp) { continue; }
-        reactions_list.push_radiolytic(
-            RadiolyticReaction::new_from_ge(sp.clone(), *ge));
+        let mut reaction = RadiolyticReaction::new_from_ge(sp.clone(), *ge);
+        if let Some(metadata) = config.bio_param.radiolytic_metadata.get(sp) {
+            reaction.set_metadata(metadata.clone());
+        }
+        reactions_list.push_radiolytic(reaction);
+    }
+
+    // Parse transfers between compartments
+    for transfer in make_transfers(&config)? {
+        reactions_list.push_reaction(ChemicalReaction::Transfer(transfer));
     }
 
     // Link Species to ChemicalReactions
     let map_species = map_all_species(&sim_sp);
+    for bolus in config.boluses.iter() {
+        match map_species.get(&bolus.species) {
+            Some(idx) if *idx < tracked_count(&sim_sp) => {},
+            _ => return Err(RadioBioError::UnknownSpecies(format!(
+                "{} (bolus of an untracked species)", bolus.species))),
+        }
+    }
     for (r_idx, reaction) in reactions_list.iter().enumerate() {
         for sp in reaction.species() {
 
@@ -125,7 +480,7 @@ pub fn parse_reactions_file(path: &str) -> Result<Env, RadioBioError> {
                     {sp.link_kreaction(rrate_idx);},
                 SimSpecies::ABCouple(ab) =>
                     {ab.link_kreaction(rrate_idx);},
-                _ => {();},
+                _ => {},
             }
         }
     }
@@ -133,8 +488,16 @@ pub fn parse_reactions_file(path: &str) -> Result<Env, RadioBioError> {
     return Ok(Env {
         reactions: reactions_list,
         species: sim_sp,
-        bio_param: config.bio_param.clone(),
+        bio_param: BioParam {
+            pH: config.pH(),
+            radiolytic: config.bio_param.radiolytic.clone(),
+        },
         initial_cc: config.initial_concentrations,
+        compartments: config.compartments,
+        boluses: config.boluses,
+        provenance: provenance,
+        mechanism: mechanism,
+        registry: registry,
     });
 
 }
@@ -150,24 +513,36 @@ fn make_species_from_config(config: &RonReactions)
     let mut tracked_species = vec![];
 
     // Manually add H_plus & OH_minus as constant A/B partners (pH related)
-    untracked.push(SimSpecies::new_cst_species(
-        String::from("H_plus"),
-        f64::powf(10.0, -config.bio_param.pH)));
-
-    untracked.push(SimSpecies::new_cst_species(
-        String::from("OH_minus"),
-        f64::powf(10.0, -14.0+config.bio_param.pH)));
+    #[allow(non_snake_case)]
+    let mut push_water_ions = |h_plus:String, oh_minus:String, pH:f64| {
+        untracked.push(SimSpecies::new_cst_species(
+            h_plus,
+            f64::powf(10.0, -pH)));
+        untracked.push(SimSpecies::new_cst_species(
+            oh_minus,
+            f64::powf(10.0, -14.0+pH)));
+    };
+    if config.compartments.is_empty() {
+        push_water_ions(String::from("H_plus"),
+                        String::from("OH_minus"),
+                        config.pH());
+    }
+    for comp in config.compartments.iter() {
+        push_water_ions(comp.label("H_plus"),
+                        comp.label("OH_minus"),
+                        comp.pH.unwrap_or(config.pH()));
+    }
 
     // Add also the Acid/Base couples with it
     for elt in &config.acid_base {
-        out.push(
-            SimSpecies::ABCouple(
-                AcidBase::new(
-                    elt.acid(),
-                    elt.base(),
-                    elt.pKa(),
-                    idx,
-                )));
+        let mut couple = AcidBase::new(
+            elt.acid(),
+            elt.base(),
+            elt.pKa(),
+            idx,
+        );
+        couple.set_metadata(elt.metadata());
+        out.push(SimSpecies::ABCouple(couple));
         // Create both Acid and Base "RawSpecies" which will later be
         // appended to the final vector with all species
         tracked_species.push(elt.acid());
@@ -181,34 +556,44 @@ fn make_species_from_config(config: &RonReactions)
         idx += 1;
     }
 
-    // Loop over all k reactions to add all their reactants (not products)
-    for reaction in &config.k_reactions {
-        for sp in reaction.reactants.iter() {
-            // First check if involved in a A/B reaction => skipped
-            if untracked.iter()
-                          .any(|elt| elt.as_owned_str()==*sp){
-                continue;
-            }
-            // Second check if Species is declared as constant
-            if config.fixed_concentrations.contains_key(sp) {
-                untracked.push(
-                    SimSpecies::new_cst_species(
-                        sp.to_string(),
-                        config.fixed_concentrations[sp]));
-                continue;
-            }
-            // Third check if already added in final vector
-            if out.iter()
-                  .any(|elt| elt.as_owned_str()==*sp) {
-                continue;
-            }
-            // Then create the new species and append it to the final vector
-            out.push(SimSpecies::new_tracked_species(
-                            sp.clone(),
-                            idx));
-            tracked_species.push(sp.clone());
-            idx += 1;
+    // Loop over all k reactions to add all their reactants (not products),
+    // and over both ends of the transfers between compartments
+    let reactants = config.k_reactions.iter()
+        .flat_map(|r| r.reactants.iter().cloned());
+    let transferred = config.transfers.iter()
+        .flat_map(|t| t.endpoints())
+        .chain(config.headspace.iter()
+                     .flat_map(|h| [h.species.clone(), h.gas_label()]));
+    for sp in chain(reactants, transferred) {
+        // First check if involved in a A/B reaction => skipped
+        if untracked.iter()
+                      .any(|elt| elt.as_owned_str()==sp){
+            continue;
+        }
+        // Second check if Species is declared as constant
+        if let Some(profile) = config.fixed_profiles.get(&sp) {
+            untracked.push(
+                SimSpecies::new_clamped_species(sp.clone(), profile.clone()));
+            continue;
+        }
+        if config.fixed_concentrations.contains_key(&sp) {
+            untracked.push(
+                SimSpecies::new_cst_species(
+                    sp.to_string(),
+                    config.fixed_concentrations[&sp]));
+            continue;
+        }
+        // Third check if already added in final vector
+        if out.iter()
+              .any(|elt| elt.as_owned_str()==sp) {
+            continue;
         }
+        // Then create the new species and append it to the final vector
+        out.push(SimSpecies::new_tracked_species(
+                        sp.clone(),
+                        idx));
+        tracked_species.push(sp);
+        idx += 1;
     }
 
     // Finally append the Untracked Species by consuming untracked
@@ -218,7 +603,154 @@ fn make_species_from_config(config: &RonReactions)
     (out, tracked_species)
 }
 
-pub fn map_all_species(sp:&Vec<SimSpecies>) -> HashMap<String, usize> {
+// Replicate reactions, acid/base couples and concentrations in every
+// compartment under namespaced labels ("intra:O2"). Entries that already
+// use a namespaced label are specific to one compartment and kept as is.
+fn expand_compartments(config: &mut RonReactions) -> Result<(), RadioBioError> {
+    let comps = config.compartments.clone();
+    for (i, comp) in comps.iter().enumerate() {
+        if is_namespaced(comp.name()) || comps[..i].iter().any(|c| c.name()==comp.name()) {
+            return Err(RadioBioError::UnknownCompartment(format!(
+                "{} (duplicated or invalid name)", comp.name())));
+        }
+    }
+    for transfer in config.transfers.iter() {
+        transfer.check(&comps)?;
+    }
+    if comps.is_empty() { return Ok(()); }
+
+    fn expand_map<T:Clone>(map:&HashMap<String, T>, targets:&[&Compartment])
+    -> HashMap<String, T> {
+        let mut out = HashMap::new();
+        for (sp, val) in map.iter().filter(|(sp, _)| !is_namespaced(sp)) {
+            for comp in targets.iter() {
+                out.insert(comp.label(sp), val.clone());
+            }
+        }
+        // Compartment specific values win over generic ones
+        for (sp, val) in map.iter().filter(|(sp, _)| is_namespaced(sp)) {
+            out.insert(sp.clone(), val.clone());
+        }
+        out
+    }
+    let all: Vec<&Compartment> = comps.iter().collect();
+    let irradiated: Vec<&Compartment> = comps.iter()
+        .filter(|c| c.is_irradiated())
+        .collect();
+    config.fixed_concentrations = expand_map(&config.fixed_concentrations, &all);
+    config.initial_concentrations = expand_map(&config.initial_concentrations, &all);
+    config.bio_param.radiolytic = expand_map(&config.bio_param.radiolytic, &irradiated);
+    config.bio_param.radiolytic_metadata =
+        expand_map(&config.bio_param.radiolytic_metadata, &irradiated);
+    config.fixed_profiles = expand_map(&config.fixed_profiles, &all);
+
+    let mut k_reactions = vec![];
+    for reaction in config.k_reactions.iter() {
+        if chain(reaction.iter_reactants(), reaction.iter_products())
+            .any(|sp| is_namespaced(sp)) {
+            k_reactions.push(reaction.clone());
+            continue;
+        }
+        for comp in comps.iter() {
+            k_reactions.push(RonKReaction {
+                id: match reaction.id.is_empty() {
+                    true => String::new(),
+                    false => comp.label(&reaction.id),
+                },
+                reactants: reaction.reactants.iter().map(|sp| comp.label(sp)).collect(),
+                products: reaction.products.iter().map(|sp| comp.label(sp)).collect(),
+                equation: String::new(),
+                ..reaction.clone()
+            });
+        }
+    }
+    config.k_reactions = k_reactions;
+
+    let mut acid_base = vec![];
+    for couple in config.acid_base.iter() {
+        if is_namespaced(&couple.acid) || is_namespaced(&couple.base) {
+            acid_base.push(couple.clone());
+            continue;
+        }
+        for comp in comps.iter() {
+            acid_base.push(RonAcidBase {
+                acid: comp.label(&couple.acid),
+                base: comp.label(&couple.base),
+                id: match couple.id.is_empty() {
+                    true => String::new(),
+                    false => comp.label(&couple.id),
+                },
+                ..couple.clone()
+            });
+        }
+    }
+    config.acid_base = acid_base;
+    Ok(())
+}
+
+// Each transfer gives a pair of reactions (from => to, to => from) such
+// that d[from]/dt = -k.([from] - [to]/partition) and matter is conserved.
+fn make_transfers(config: &RonReactions)
+-> Result<Vec<TransferReaction>, RadioBioError> {
+    let mut out = vec![];
+    for transfer in config.transfers.iter() {
+        let volume = |name:&str| config.compartments.iter()
+            .find(|c| c.name()==name)
+            .map(|c| c.volume())
+            .ok_or(RadioBioError::UnknownCompartment(name.to_string()));
+        let ratio = volume(&transfer.from)? / volume(&transfer.to)?;
+        let [from, to] = transfer.endpoints();
+        out.extend(transfer_pair(from, to, transfer.k_value, ratio, transfer.partition));
+    }
+    // Gas-liquid exchange with the headspace of the vial
+    if let Some(hs) = &config.headspace {
+        out.extend(transfer_pair(
+            hs.species.clone(), hs.gas_label(), hs.kla, hs.volume_ratio(), 1.0));
+    }
+    Ok(out)
+}
+
+// ratio = V_from / V_to
+fn transfer_pair(from:String, to:String, k:f64, ratio:f64, partition:f64)
+-> [TransferReaction; 2] {
+    [TransferReaction::new(from.clone(), to.clone(), k, ratio),
+     TransferReaction::new(to, from, k * ratio / partition, 1.0/ratio)]
+}
+
+// Declare the gas phase reservoir: tracked if the vial is sealed, constant
+// otherwise. Unless given, the liquid starts in equilibrium with the gas.
+fn setup_headspace(config: &mut RonReactions) -> Result<(), RadioBioError> {
+    let hs = match &config.headspace {
+        Some(hs) => hs.clone(),
+        None => return Ok(()),
+    };
+    for (name, value) in [("kla", hs.kla),
+                          ("liquid_volume", hs.liquid_volume),
+                          ("gas_volume", hs.gas_volume),
+                          ("henry", hs.henry),
+                          ("temperature", hs.temperature)] {
+        if value <= 0.0 {
+            return Err(RadioBioError::InvalidTransfer(
+                hs.species.clone(), format!("headspace {name} must be positive")));
+        }
+    }
+    let cc_eq = hs.equilibrium_concentration();
+    config.initial_concentrations
+          .entry(hs.species.clone())
+          .or_insert(cc_eq);
+    if hs.sealed {
+        config.initial_concentrations.insert(hs.gas_label(), cc_eq);
+    } else {
+        config.fixed_concentrations.insert(hs.gas_label(), cc_eq);
+    }
+    Ok(())
+}
+
+fn tracked_count(sp:&[SimSpecies]) -> usize {
+    sp.iter().filter(|x| x.is_tracked()).count()
+}
+
+pub fn map_all_species(sp:&[SimSpecies]) -> HashMap<String, usize> {
     let mut out = HashMap::new();
     for (idx, sim_sp) in sp.iter().enumerate() {
         match sim_sp {
@@ -241,11 +773,192 @@ pub fn map_all_species(sp:&Vec<SimSpecies>) -> HashMap<String, usize> {
     return out;
 }
 
-// Check basic rules of chemistry/logic from .ron file
-#[allow(unused_variables)]
-fn check_parsed_reactions(config: &RonReactions) {
-    todo!();
-    // A Constant species cannot be involved in an Acid Base reaction /!\
+// Check basic rules of chemistry/logic from .ron file, once compartments
+// and headspace are expanded. Every problem is collected in the report.
+fn check_parsed_reactions(config: &RonReactions) -> ValidationReport {
+    let mut report = ValidationReport::new();
+
+    // Constant species: fixed ones and water ions (pH related)
+    let mut water_ions: Vec<String> = vec![];
+    if config.compartments.is_empty() {
+        water_ions.extend([String::from("H_plus"), String::from("OH_minus")]);
+    }
+    for comp in config.compartments.iter() {
+        water_ions.extend([comp.label("H_plus"), comp.label("OH_minus")]);
+    }
+    let fixed: BTreeSet<&String> = config.fixed_concentrations.keys()
+        .chain(config.fixed_profiles.keys())
+        .chain(water_ions.iter())
+        .collect();
+    let reactants: BTreeSet<&String> = config.k_reactions.iter()
+        .flat_map(|r| r.iter_reactants())
+        .collect();
+    let products: BTreeSet<&String> = config.k_reactions.iter()
+        .flat_map(|r| r.iter_products())
+        .collect();
+    let transferred: Vec<String> = config.transfers.iter()
+        .flat_map(|t| t.endpoints())
+        .chain(config.headspace.iter()
+                     .flat_map(|h| [h.species.clone(), h.gas_label()]))
+        .collect();
+    let partners: BTreeSet<&String> = config.acid_base.iter()
+        .flat_map(|ab| [&ab.acid, &ab.base])
+        .collect();
+    let known: BTreeSet<&String> = reactants.iter().chain(products.iter())
+        .cloned()
+        .chain(transferred.iter())
+        .chain(partners.iter().cloned())
+        .chain(fixed.iter().cloned())
+        .collect();
+    let in_reactions = |sp:&String| reactants.contains(sp)
+                                 || products.contains(sp)
+                                 || transferred.contains(sp);
+
+    // pH and acid/base couples
+    if !(0.0..=14.0).contains(&config.pH()) {
+        report.error(format!("pH = {} is out of [0, 14]", config.pH()));
+    }
+    for ab in config.acid_base.iter() {
+        for sp in [&ab.acid, &ab.base] {
+            if fixed.contains(sp) {
+                report.error(format!(
+                    "{} is a constant species and cannot be involved in the \
+                     acid/base couple {}", sp, ab.couple_label()));
+            }
+        }
+        if !(PKA_RANGE.0..=PKA_RANGE.1).contains(&ab.pKa) {
+            report.error(format!("pKa = {} of {} is out of [{}, {}]",
+                ab.pKa, ab.couple_label(), PKA_RANGE.0, PKA_RANGE.1));
+        }
+        if !in_reactions(&ab.acid) && !in_reactions(&ab.base) {
+            report.warning(format!(
+                "Acid/base couple {} is involved in no reaction", ab.couple_label()));
+        }
+    }
+
+    // Uncertainties of the pKa, k and G-values
+    let uncertainties = config.acid_base.iter()
+        .map(|ab| (ab.couple_label(), ab.uncertainty))
+        .chain(config.k_reactions.iter().map(|r| (r.equation_label(), r.uncertainty)))
+        .chain(config.bio_param.radiolytic_metadata.iter()
+                     .map(|(sp, m)| (format!("G-value of {sp}"), m.uncertainty)));
+    for (name, uncertainty) in uncertainties {
+        if let Some(u) = uncertainty.filter(|u| !u.is_valid()) {
+            report.error(format!("{}: invalid uncertainty ({}), a factor must be \
+                                  at least 1 and a sigma positive", name, u));
+        }
+    }
+    for sp in config.bio_param.radiolytic_metadata.keys() {
+        if !config.bio_param.radiolytic.contains_key(sp) {
+            report.warning(format!("Metadata given for the G-value of {}, which has none", sp));
+        }
+    }
+
+    // Rate constants and duplicated reactions
+    let mut seen: Vec<(Vec<&String>, Vec<&String>)> = vec![];
+    for (idx, r) in config.k_reactions.iter().enumerate() {
+        if r.k_value <= 0.0 || !r.k_value.is_finite() {
+            report.error(format!("Reaction #{} ({}) has an invalid rate constant: {}",
+                idx+1, r.equation_label(), r.k_value));
+        }
+        let mut key = (r.iter_reactants().collect::<Vec<_>>(),
+                       r.iter_products().collect::<Vec<_>>());
+        key.0.sort();
+        key.1.sort();
+        if let Some(first) = seen.iter().position(|other| *other == key) {
+            report.error(format!(
+                "Reaction #{} ({}) duplicates reaction #{}", idx+1, r.equation_label(), first+1));
+        }
+        seen.push(key);
+    }
+
+    // Registry entries
+    let mut declared = HashMap::new();
+    for (sp, entry) in config.species.iter() {
+        match entry.composition() {
+            Some(Ok(comp)) => {declared.insert(sp.clone(), comp);},
+            Some(Err(e)) => report.error(format!("{}: {}", sp, e)),
+            None => {},
+        }
+        if entry.diffusion.is_some_and(|d| d < 0.0 || !d.is_finite()) {
+            report.error(format!("{}: invalid diffusion coefficient", sp));
+        }
+        if entry.molar_mass.is_some_and(|m| m <= 0.0 || !m.is_finite()) {
+            report.error(format!("{}: invalid molar mass", sp));
+        }
+    }
+
+    // Conservation of atoms and charge
+    let mut unknown_composition = BTreeSet::new();
+    for (idx, r) in config.k_reactions.iter().enumerate() {
+        let kr = r.to_kreaction();
+        match reaction_balance(&kr, &declared) {
+            Ok(balance) if balance.is_zero() => {},
+            Ok(balance) => report.warning(format!(
+                "Reaction #{} ({}) is not balanced (products - reactants: {})",
+                idx+1, kr, balance)),
+            Err(sp) => {unknown_composition.insert(sp);},
+        }
+    }
+    for sp in unknown_composition {
+        report.warning(format!(
+            "Unknown composition of {}, declare it in `species` to check \
+             the balance of its reactions", sp));
+    }
+
+    // Radiolytic yields
+    for (sp, ge) in config.bio_param.radiolytic.iter() {
+        if *ge < 0.0 {
+            report.error(format!("Negative radiolytic yield for {}: {}", sp, ge));
+        }
+        if !known.contains(sp) {
+            report.error(format!(
+                "Radiolytic yield given for unknown species {}", sp));
+        } else if fixed.contains(sp) {
+            report.warning(format!(
+                "Radiolytic yield of {} has no effect: constant species", sp));
+        } else if !reactants.contains(sp) && !partners.contains(sp)
+                  && !transferred.contains(sp) {
+            report.warning(format!(
+                "Radiolytic yield of {} has no effect: never consumed so not tracked", sp));
+        }
+    }
+
+    // Concentrations
+    for (sp, cc) in config.initial_concentrations.iter()
+                          .chain(config.fixed_concentrations.iter()) {
+        if *cc < 0.0 {
+            report.error(format!("Negative concentration for {}: {}", sp, cc));
+        }
+    }
+    for sp in config.initial_concentrations.keys()
+                    .chain(config.fixed_concentrations.keys())
+                    .chain(config.fixed_profiles.keys()) {
+        if !in_reactions(sp) && !partners.contains(sp) {
+            report.warning(format!(
+                "{} is declared but never produced or consumed", sp));
+        }
+    }
+    for bolus in config.boluses.iter() {
+        if bolus.amount < 0.0 {
+            report.error(format!("Negative bolus of {} at t = {}",
+                bolus.species, bolus.time));
+        }
+    }
+
+    // Tracked species consumed but never fed: their reactions never happen
+    for sp in reactants.iter() {
+        if fixed.contains(sp) || partners.contains(sp) || products.contains(sp)
+           || transferred.contains(sp)
+           || config.bio_param.radiolytic.contains_key(*sp)
+           || config.initial_concentrations.get(*sp).is_some_and(|cc| *cc > 0.0)
+           || config.boluses.iter().any(|b| b.species == **sp) {
+            continue;
+        }
+        report.warning(format!(
+            "{} is consumed but never produced and starts at 0", sp));
+    }
+    return report;
 }
 
 impl RonReactions {
@@ -267,19 +980,352 @@ impl RonAcidBase {
     pub fn acid(&self) -> String {self.acid.clone()}
     pub fn base(&self) -> String {self.base.clone()}
     pub fn pKa(&self) -> f64   {self.pKa  }
-    pub fn label(&self) -> String {
+    pub fn couple_label(&self) -> String {
         format!("{}/{}", self.acid, self.base)
     }
+    pub fn metadata(&self) -> Metadata {
+        Metadata {
+            id: self.id.clone(),
+            label: self.label.clone(),
+            reference: self.reference.clone(),
+            uncertainty: self.uncertainty,
+            notes: self.notes.clone(),
+        }
+    }
+}
+
+impl RonTransfer {
+    pub fn endpoints(&self) -> [String; 2] {
+        [namespaced(&self.from, &self.species), namespaced(&self.to, &self.species)]
+    }
+    fn check(&self, comps:&[Compartment]) -> Result<(), RadioBioError> {
+        for name in [&self.from, &self.to] {
+            if !comps.iter().any(|c| c.name()==name) {
+                return Err(RadioBioError::UnknownCompartment(name.clone()));
+            }
+        }
+        let invalid = |msg:&str| Err(RadioBioError::InvalidTransfer(
+            self.species.clone(), msg.to_string()));
+        if self.from == self.to {
+            return invalid("source and target compartments are the same");
+        }
+        if self.k_value < 0.0 {
+            return invalid("negative transfer rate");
+        }
+        if self.partition <= 0.0 {
+            return invalid("partition coefficient must be positive");
+        }
+        for comp in comps.iter().filter(|c| [&self.from, &self.to].contains(&&c.name)) {
+            if comp.volume() <= 0.0 {
+                return invalid("compartment volumes must be positive");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl RonReactions {
+    #[allow(non_snake_case)]
+    pub fn pH(&self) -> f64 {
+        self.bio_param.pH.unwrap_or(NEUTRAL_PH)
+    }
 }
 
 impl RonKReaction {
+    // Name of the reaction in provenance and overrides
+    pub fn key(&self) -> String {
+        match self.id.is_empty() {
+            true => self.equation_label(),
+            false => self.id.clone(),
+        }
+    }
+    // Equation form used in messages ("e_aq + H2O -> H_r + OH_minus")
+    pub fn equation_label(&self) -> String {
+        format!("{} -> {}", self.reactants.join(" + "), self.products.join(" + "))
+    }
+    pub fn metadata(&self) -> Metadata {
+        Metadata {
+            id: self.id.clone(),
+            label: self.label.clone(),
+            reference: self.reference.clone(),
+            uncertainty: self.uncertainty,
+            notes: self.notes.clone(),
+        }
+    }
     pub fn iter_reactants(&self) -> impl Iterator<Item = &String> {
         self.reactants.iter()
     }
     pub fn iter_products(&self) -> impl Iterator<Item = &String> {
         self.products.iter()
     }
+    pub fn to_kreaction(&self) -> KReaction {
+        let mut kr = KReaction::new_empty(self.get_k_value());
+        for sp in self.iter_reactants() {
+            kr.add_reactant(sp);
+        }
+        for sp in self.iter_products() {
+            kr.add_product(sp);
+        }
+        kr
+    }
     pub fn get_k_value(&self) -> Option<f64> {
         Some(self.k_value)
     }
-}
\ No newline at end of file
+}
+/* -------------------------------------------------------------------------- */
+/*                                   TESTING                                  */
+/* -------------------------------------------------------------------------- */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Beam, ODESolver, State};
+    use crate::ode_solver::traits::System;
+    use crate::physics::gas::{HENRY_O2, MMHG_PER_ATM};
+    use crate::units::to_state;
+
+    #[test]
+    fn parse_errors_are_returned() {
+        let err = parse_reactions_file("does/not/exist.ron").unwrap_err();
+        let io = match &err {
+            RadioBioError::FileAccess { path, source } => {
+                assert_eq!(path, "does/not/exist.ron");
+                source.to_string()
+            },
+            other => panic!("Unexpected error: {:?}", other),
+        };
+        // As printed by the CLI, the I/O error once
+        assert_eq!(format!("{:#}", anyhow::Error::new(err)),
+                   format!("Cannot read does/not/exist.ron: {io}"));
+
+        let path = crate::test_dir("bad_reactions").join("reactions.ron");
+        fs::write(&path, "(\n    bio_param: (\n        pH: \"seven\",\n").unwrap();
+        let path = path.to_str().unwrap();
+        match parse_reactions_file(path) {
+            Err(RadioBioError::Parse { path: p, line, column, .. }) => {
+                assert_eq!(p, path);
+                assert_eq!(line, 3);
+                assert!(column > 1);
+            },
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn shipped_files_are_valid() {
+        for file in ["reactions.ron", "reactions_simple.ron",
+                     "reactions_compartments.ron", "reactions_vial.ron",
+                     "reactions.json"] {
+            let path = format!("{}/data/{}", env!("CARGO_MANIFEST_DIR"), file);
+            let report = validate_reactions_file(&path).unwrap();
+            assert!(!report.has_errors(), "{file}:\n{report}");
+        }
+    }
+
+    #[test]
+    fn all_issues_are_reported() {
+        let path = crate::test_dir("invalid_reactions").join("reactions.ron");
+        fs::write(&path, r#"(
+            bio_param: (pH: 7, radiolytic: { "e_aq": 2.8, "X": 1.0 }),
+            fixed_concentrations: { "H2O": 55, "O2_minus": 1e-6 },
+            initial_concentrations: {},
+            acid_base: [ (acid: "HO2_r", base: "O2_minus", pKa: 4.8) ],
+            k_reactions: [
+                (reactants: ["e_aq", "H2O"], products: ["H_r", "OH_minus"], k_value: 19),
+                (reactants: ["H2O", "e_aq"], products: ["OH_minus", "H_r"], k_value: 19),
+                (reactants: ["e_aq", "HO2_r"], products: ["H2O"], k_value: -1,
+                 uncertainty: Factor(0.5)),
+            ],
+        )"#).unwrap();
+        let path = path.to_str().unwrap();
+        let report = validate_reactions_file(path).unwrap();
+        assert_eq!(report.iter_errors().count(), 5, "{report}");
+        match parse_reactions_file(path) {
+            Err(RadioBioError::Validation(r)) => assert!(r.has_errors()),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn equations_are_expanded() {
+        let content = r#"(
+            bio_param: (pH: 7, radiolytic: {}),
+            fixed_concentrations: {}, initial_concentrations: {}, acid_base: [],
+            k_reactions: [
+                (equation: "2 e_aq -> H2 + 2 OH_minus", k: 1.1e10),
+                (reactants: ["e_aq", "e_aq"], products: ["H2", "OH_minus", "OH_minus"],
+                 k_value: 1.1e10),
+            ],
+        )"#;
+        let mut config: RonReactions = from_str(content).unwrap();
+        expand_equations(&mut config).unwrap();
+        let (a, b) = (config.k_reactions[0].to_kreaction(), config.k_reactions[1].to_kreaction());
+        assert_eq!(format!("{a}"), format!("{b}"));
+        assert_eq!(a.get_stoichio("e_aq").unwrap(), 2);
+
+        config.k_reactions[0].equation = String::from("2 e_aq -> H2 +");
+        config.k_reactions[0].reactants.clear();
+        config.k_reactions[0].products.clear();
+        match expand_equations(&mut config) {
+            Err(RadioBioError::InvalidEquation { index, message, .. }) => {
+                assert_eq!(index, 1);
+                assert!(message.starts_with("products"));
+            },
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn metadata_is_kept() {
+        let path = crate::test_dir("metadata").join("metadata.ron");
+        fs::write(&path, r#"(
+            bio_param: (pH: 7, radiolytic: { "e_aq": 2.8 },
+                        radiolytic_metadata: { "e_aq": (uncertainty: Sigma(0.1)) }),
+            fixed_concentrations: { "H2O": 55 },
+            acid_base: [ (acid: "HO2_r", base: "O2_r_minus", pKa: 4.8, id: "AB1",
+                          reference: "doi:10.1063/1.555805") ],
+            k_reactions: [
+                (id: "R1", equation: "e_aq + H2O -> H_r + OH_minus", k: 19,
+                 label: "Hydrolysis of e_aq", uncertainty: Factor(1.5), notes: "slow"),
+                (equation: "e_aq + O2_r_minus -> HO2_minus + OH_minus", k: 1.3e10),
+            ],
+        )"#).unwrap();
+        let env = parse_reactions_file(path.to_str().unwrap()).unwrap();
+        let metadata: Vec<&Metadata> = env.reactions.iter()
+            .filter_map(|r| match r {
+                ChemicalReaction::KReaction(kr) => Some(kr.metadata()),
+                ChemicalReaction::Radiolytic(r) => Some(r.metadata()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(format!("{}", metadata[0]), "R1, Hydrolysis of e_aq, factor 1.5, slow");
+        assert!(metadata[1].is_empty());
+        assert_eq!(metadata[2].uncertainty, Some(Uncertainty::Sigma(0.1)));
+        let couple = env.iter_ABCouples().next().unwrap();
+        assert_eq!(couple.metadata().id, "AB1");
+
+        let ron = env.mechanism_to_ron().unwrap();
+        assert!(ron.contains("uncertainty: Factor(1.5)"), "{ron}");
+        let json = env.mechanism_to_json().unwrap();
+        assert!(json.contains("\"reference\": \"doi:10.1063/1.555805\""), "{json}");
+    }
+
+    #[test]
+    fn aliases_are_resolved() {
+        let path = crate::test_dir("aliases").join("aliases.ron");
+        fs::write(&path, r#"(
+            bio_param: (pH: 7, radiolytic: { "OH": 2.8 }),
+            species: { "OH_r": (display: "•OH", aliases: ["OH", "HO_r"], diffusion: 2.3e-9) },
+            initial_concentrations: { "H2": 1e-5 },
+            k_reactions: [
+                (equation: "OH + H2 -> H_r + H2O", k: 4.2e7),
+                (equation: "2 HO_r -> H2O2", k: 1.1e10),
+            ],
+        )"#).unwrap();
+        let env = parse_reactions_file(path.to_str().unwrap()).unwrap();
+        let labels = env.species_label();
+        assert!(labels.contains(&String::from("OH_r")));
+        assert!(!labels.iter().any(|sp| sp == "OH" || sp == "HO_r"));
+        assert!(env.bio_param.radiolytic.contains_key("OH_r"));
+        let idx = labels.iter().position(|sp| sp == "OH_r").unwrap();
+        assert_eq!(env.species_display_names()[idx], "•OH");
+        assert_eq!(env.diffusion_coefficients()["OH_r"], 2.3e-9);
+
+        fs::write(&path, r#"(
+            species: { "OH_r": (aliases: ["OH"]) },
+            initial_concentrations: { "OH": 1e-5, "OH_r": 1e-6 },
+            k_reactions: [ (equation: "OH -> H_r", k: 1.0) ],
+        )"#).unwrap();
+        assert!(matches!(parse_reactions_file(path.to_str().unwrap()),
+                         Err(RadioBioError::InvalidAlias(_))));
+    }
+
+    #[test]
+    fn units_are_converted() {
+        let path = crate::test_dir("units").join("units.ron");
+        fs::write(&path, r#"(
+            fixed_concentrations: { "H2O": "55 M" },
+            initial_concentrations: { "O2": "40 mmHg", "GSH": "2 mM", "H2": 1e-5 },
+            fixed_profiles: { "DMSO": Steps([(0, "10 µM"), ("5 ms", "0.1 mM")]) },
+            boluses: [ (time: "100 µs", species: "GSH", amount: "500 uM") ],
+            k_reactions: [
+                (equation: "GSH + O2 -> GSSG", k: 1e3),
+                (equation: "H2 + DMSO -> H_r", k: 1e3),
+                (equation: "GSSG + H2O -> GSH", k: 1.0),
+            ],
+        )"#).unwrap();
+        let env = parse_reactions_file(path.to_str().unwrap()).unwrap();
+        let cc_o2 = 40.0 / MMHG_PER_ATM * HENRY_O2;
+        assert_float_relative_eq!(env.initial_cc["O2"], cc_o2, 1e-12);
+        assert_float_relative_eq!(env.initial_cc["GSH"], 2e-3, 1e-12);
+        assert_float_relative_eq!(env.boluses[0].time, 1e-4, 1e-12);
+        assert_float_relative_eq!(env.boluses[0].amount, 5e-4, 1e-12);
+        assert_float_relative_eq!(env.breakpoints()[2], 5e-3, 1e-12);
+
+        // The state vector is in µmol/l
+        let labels = env.species_label();
+        let y0 = env.get_initial_values();
+        let idx = labels.iter().position(|sp| sp == "O2").unwrap();
+        assert_float_relative_eq!(y0[idx], cc_o2 * 1e6, 1e-12);
+        let cc = env.mapped_cc_species(6e-3, &y0);
+        assert_float_relative_eq!(cc["H2"], 1e-5, 1e-12);
+        assert_float_relative_eq!(cc["DMSO"], 1e-4, 1e-12);
+
+        fs::write(&path, r#"(
+            initial_concentrations: { "H2": "40 mmHg" },
+            k_reactions: [ (equation: "H2 -> H_r", k: 1.0) ],
+        )"#).unwrap();
+        assert!(matches!(parse_reactions_file(path.to_str().unwrap()),
+                         Err(RadioBioError::Parse { .. })));
+    }
+
+    #[test]
+    fn kpp_rates_are_simulated_as_written() {
+        let path = crate::test_dir("kpp").join("mechanism.eqn");
+        fs::write(&path, "#DEFVAR A = IGNORE; B = IGNORE; C = IGNORE;\n\
+            #EQUATIONS <R1> A + B = C : 1e-11;\n\
+            #INITVALUES CFACTOR = 6.02214076e20; A = 1e-3; B = 2e-3;\n").unwrap();
+        let env = parse_reactions_file(path.to_str().unwrap()).unwrap();
+        let idx = env.species_label().iter().position(|sp| sp == "A").unwrap();
+        let chem = ODESolver::new(env, Beam::new_constant(String::from("e"), 0.0).unwrap());
+        let y = chem.env().get_initial_values();
+        let mut dy = State::zeros(y.len());
+        chem.system(0.0, &y, &mut dy);
+        // d[A]/dt = -k.[A].[B], k from cm3/molecule/s to l/mol/s
+        let k = 1e-11 * 6.02214076e20;
+        assert_float_relative_eq!(dy[idx], -to_state(k * 1e-3 * 2e-3), 1e-9);
+
+        // k.[A]^2 with A consumed twice has no equivalent
+        fs::write(&path, "#DEFVAR A = IGNORE; B = IGNORE;\n\
+            #EQUATIONS <R1> A + A = B : 1e-11;\n").unwrap();
+        match parse_reactions_file(path.to_str().unwrap()) {
+            Err(RadioBioError::KppImport { issues, .. }) =>
+                assert!(issues[0].contains("A appears more than once as a reactant")),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn resolved_mechanism_round_trips() {
+        fn summary(env:&Env) -> (Vec<String>, Vec<String>, Vec<f64>) {
+            let mut reactions: Vec<String> = env.reactions.iter()
+                .map(|r| format!("{:?}", r))
+                .collect();
+            reactions.sort();
+            (env.species_label(), reactions, env.get_initial_values().iter().cloned().collect())
+        }
+        let dir = crate::test_dir("resolved");
+        for file in ["reactions.ron", "reactions.json", "reactions_compartments.ron",
+                     "reactions_vial.ron"] {
+            let path = format!("{}/data/{}", env!("CARGO_MANIFEST_DIR"), file);
+            let env = parse_reactions_file(&path).unwrap();
+            for ext in ["ron", "json"] {
+                let out = dir.join(format!("{file}.{ext}"));
+                let out = out.to_str().unwrap();
+                env.write_mechanism(out).unwrap();
+                let again = parse_reactions_file(out).unwrap();
+                assert_eq!(summary(&env), summary(&again), "{file} as {ext}");
+                assert_eq!(again.bio_param.pH, env.bio_param.pH);
+            }
+        }
+    }
+}
@@ -0,0 +1,275 @@
+/* ---------------------------- External imports ---------------------------- */
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/* ---------------------------- Internal imports ---------------------------- */
+use super::{RonReactions, RonRemovals, read_reactions_file};
+use crate::reactions::errors::RadioBioError;
+use crate::reactions::provenance::Provenance;
+
+/* -------------------------------------------------------------------------- */
+/*                         FUNCTION/STRUCT DEFINITIONS                        */
+/* -------------------------------------------------------------------------- */
+// Read a reactions file and merge the files it includes, depth first and
+// in order. A file included several times is only merged once.
+pub(super) fn read_with_includes(path:&str)
+-> Result<(RonReactions, Provenance), RadioBioError> {
+    let mut merger = Merger {
+        config: RonReactions::default(),
+        provenance: Provenance::new(),
+        stack: vec![],
+        done: vec![],
+    };
+    merger.merge_file(Path::new(path))?;
+    Ok((merger.config, merger.provenance))
+}
+
+struct Merger {
+    config: RonReactions,
+    provenance: Provenance,
+    stack: Vec<PathBuf>, // Files being merged, to detect cycles
+    done: Vec<PathBuf>,
+}
+
+impl Merger {
+    fn merge_file(&mut self, path:&Path) -> Result<(), RadioBioError> {
+        let name = path.to_string_lossy().to_string();
+        let canonical = fs::canonicalize(path)
+            .map_err(|source| RadioBioError::FileAccess { path: name.clone(), source })?;
+        if self.stack.contains(&canonical) {
+            return Err(RadioBioError::IncludeCycle(name));
+        }
+        if self.done.contains(&canonical) { return Ok(()); }
+
+        let mut file = read_reactions_file(&name)?;
+        self.stack.push(canonical.clone());
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for include in std::mem::take(&mut file.include) {
+            self.merge_file(&dir.join(include))?;
+        }
+        self.stack.pop();
+        self.done.push(canonical);
+
+        self.remove(&file.remove, &name)?;
+        self.add(file, &name)
+    }
+
+    fn remove(&mut self, removals:&RonRemovals, file:&str) -> Result<(), RadioBioError> {
+        let config = &mut self.config;
+        let prov = &mut self.provenance;
+        for id in removals.reactions.iter() {
+            let before = config.k_reactions.len();
+            config.k_reactions.retain(|r| r.id != *id);
+            if config.k_reactions.len() == before {
+                return Err(RadioBioError::UnknownReactionId(id.clone(), file.to_string()));
+            }
+            prov.remove("reaction", id);
+            prov.remove("k value", id);
+        }
+
+        for sp in removals.species.iter() {
+            let mut found = false;
+            let maps = [
+                ("G-value", &mut config.bio_param.radiolytic),
+                ("initial concentration", &mut config.initial_concentrations),
+                ("fixed concentration", &mut config.fixed_concentrations),
+            ];
+            for (kind, map) in maps {
+                if map.remove(sp).is_some() {
+                    prov.remove(kind, sp);
+                    found = true;
+                }
+            }
+            if config.fixed_profiles.remove(sp).is_some() {
+                prov.remove("fixed profile", sp);
+                found = true;
+            }
+            if config.species.remove(sp).is_some() {
+                prov.remove("species", sp);
+            }
+            if config.bio_param.radiolytic_metadata.remove(sp).is_some() {
+                prov.remove("G-value metadata", sp);
+            }
+            for r in config.k_reactions.iter()
+                .filter(|r| r.iter_reactants().chain(r.iter_products()).any(|s| s == sp)) {
+                prov.remove("reaction", &r.key());
+                prov.remove("k value", &r.key());
+                found = true;
+            }
+            config.k_reactions.retain(|r|
+                !r.iter_reactants().chain(r.iter_products()).any(|s| s == sp));
+            for ab in config.acid_base.iter().filter(|ab| ab.acid == *sp || ab.base == *sp) {
+                prov.remove("acid/base couple", &ab.couple_label());
+                found = true;
+            }
+            config.acid_base.retain(|ab| ab.acid != *sp && ab.base != *sp);
+            for t in config.transfers.iter().filter(|t| t.species == *sp) {
+                prov.remove("transfer", &transfer_key(&t.species, &t.from, &t.to));
+                found = true;
+            }
+            config.transfers.retain(|t| t.species != *sp);
+            for b in config.boluses.iter().filter(|b| b.species == *sp) {
+                prov.remove("bolus", &format!("{} at t = {}", b.species, b.time));
+                found = true;
+            }
+            config.boluses.retain(|b| b.species != *sp);
+            if config.headspace.as_ref().is_some_and(|h| h.species == *sp) {
+                config.headspace = None;
+                prov.remove("headspace", sp);
+                found = true;
+            }
+            if !found {
+                return Err(RadioBioError::UnknownSpecies(format!(
+                    "{} (removed in {})", sp, file)));
+            }
+        }
+        Ok(())
+    }
+
+    fn add(&mut self, file:RonReactions, name:&str) -> Result<(), RadioBioError> {
+        let config = &mut self.config;
+        let prov = &mut self.provenance;
+
+        if let Some(ph) = file.bio_param.pH {
+            config.bio_param.pH = Some(ph);
+            prov.set("parameter", "pH", name);
+        }
+        merge_map(&mut config.bio_param.radiolytic, file.bio_param.radiolytic,
+                  prov, "G-value", name);
+        merge_map(&mut config.initial_concentrations, file.initial_concentrations,
+                  prov, "initial concentration", name);
+        merge_map(&mut config.fixed_concentrations, file.fixed_concentrations,
+                  prov, "fixed concentration", name);
+        merge_map(&mut config.fixed_profiles, file.fixed_profiles,
+                  prov, "fixed profile", name);
+        merge_map(&mut config.species, file.species, prov, "species", name);
+        merge_map(&mut config.bio_param.radiolytic_metadata, file.bio_param.radiolytic_metadata,
+                  prov, "G-value metadata", name);
+
+        // Entries with the same id (or acid, compartment...) are replaced
+        for ab in file.acid_base {
+            prov.set("acid/base couple", &ab.couple_label(), name);
+            match config.acid_base.iter_mut().find(|other| other.acid == ab.acid) {
+                Some(other) => *other = ab,
+                None => config.acid_base.push(ab),
+            }
+        }
+        for r in file.k_reactions {
+            prov.set("reaction", &r.key(), name);
+            match config.k_reactions.iter_mut()
+                .find(|other| !r.id.is_empty() && other.id == r.id) {
+                Some(other) => *other = r,
+                None => config.k_reactions.push(r),
+            }
+        }
+        for comp in file.compartments {
+            prov.set("compartment", comp.name(), name);
+            match config.compartments.iter_mut().find(|other| other.name == comp.name) {
+                Some(other) => *other = comp,
+                None => config.compartments.push(comp),
+            }
+        }
+        for t in file.transfers {
+            let key = transfer_key(&t.species, &t.from, &t.to);
+            prov.set("transfer", &key, name);
+            match config.transfers.iter_mut()
+                .find(|other| transfer_key(&other.species, &other.from, &other.to) == key) {
+                Some(other) => *other = t,
+                None => config.transfers.push(t),
+            }
+        }
+        if let Some(headspace) = file.headspace {
+            prov.set("headspace", &headspace.species, name);
+            config.headspace = Some(headspace);
+        }
+        for b in file.boluses {
+            prov.set("bolus", &format!("{} at t = {}", b.species, b.time), name);
+            config.boluses.push(b);
+        }
+
+        for (id, k) in file.k_overrides {
+            match config.k_reactions.iter_mut().find(|r| r.id == id) {
+                Some(r) => r.k_value = k,
+                None => return Err(RadioBioError::UnknownReactionId(id, name.to_string())),
+            }
+            prov.set("k value", &id, name);
+        }
+        Ok(())
+    }
+}
+
+fn merge_map<T>(target:&mut HashMap<String, T>, values:HashMap<String, T>,
+                prov:&mut Provenance, kind:&str, file:&str) {
+    // Sorted for a reproducible provenance
+    let mut values: Vec<(String, T)> = values.into_iter().collect();
+    values.sort_by(|a, b| a.0.cmp(&b.0));
+    for (sp, value) in values {
+        prov.set(kind, &sp, file);
+        target.insert(sp, value);
+    }
+}
+
+fn transfer_key(species:&str, from:&str, to:&str) -> String {
+    format!("{species}: {from} => {to}")
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   TESTING                                  */
+/* -------------------------------------------------------------------------- */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reactions::parse_reactions_file;
+
+    #[test]
+    fn modules_are_merged() {
+        let dir = crate::test_dir("includes");
+        fs::create_dir_all(dir.join("modules")).unwrap();
+        fs::write(dir.join("base.ron"), r#"(
+            bio_param: (pH: 7, radiolytic: { "e_aq": 2.8, "OH_r": 2.8 }),
+            fixed_concentrations: { "H2O": 55 },
+            initial_concentrations: { "O2": 75e-6 },
+            k_reactions: [
+                (id: "R1", equation: "e_aq + H2O -> H_r + OH_minus", k: 19),
+                (id: "R2", equation: "2 e_aq -> H2 + 2 OH_minus", k: 1.1e10),
+                (id: "R3", equation: "e_aq + O2 -> O2_r_minus", k: 1.9e10),
+                (id: "R4", equation: "OH_r + H2 -> H_r + H2O", k: 4.2e7),
+                (id: "R5", equation: "2 OH_r -> H2O2", k: 1.1e10),
+            ],
+        )"#).unwrap();
+        fs::write(dir.join("modules/no_h2.ron"), r#"(
+            include: ["../base.ron"],
+            remove: (reactions: ["R2"], species: ["H2"]),
+            k_overrides: { "R3": 2.0e10 },
+            initial_concentrations: { "O2": 1e-5 },
+        )"#).unwrap();
+        fs::write(dir.join("experiment.ron"), r#"(
+            include: ["base.ron", "modules/no_h2.ron"],
+            bio_param: (pH: 7.4, radiolytic: {}),
+        )"#).unwrap();
+
+        let path = dir.join("experiment.ron");
+        let (config, prov) = read_with_includes(path.to_str().unwrap()).unwrap();
+        let ids: Vec<&str> = config.k_reactions.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, vec!["R1", "R3", "R5"]);
+        assert_eq!(config.k_reactions[1].k_value, 2.0e10);
+        assert_eq!(config.initial_concentrations["O2"], 1e-5);
+        assert_eq!(config.pH(), 7.4);
+        assert_eq!(config.bio_param.radiolytic.len(), 2);
+
+        let origin = |kind, name| prov.origin_of(kind, name).unwrap().to_string();
+        assert!(origin("reaction", "R1").ends_with("base.ron"));
+        assert!(origin("k value", "R3").ends_with("no_h2.ron"));
+        assert!(origin("initial concentration", "O2").ends_with("no_h2.ron"));
+        assert!(origin("parameter", "pH").ends_with("experiment.ron"));
+        assert!(prov.origin_of("reaction", "R2").is_none());
+
+        let env = parse_reactions_file(path.to_str().unwrap()).unwrap();
+        assert_eq!(env.provenance.iter().count(), prov.iter().count());
+
+        fs::write(dir.join("base.ron"), r#"(include: ["experiment.ron"])"#).unwrap();
+        assert!(matches!(read_with_includes(path.to_str().unwrap()),
+                         Err(RadioBioError::IncludeCycle(_))));
+    }
+}
@@ -0,0 +1,288 @@
+/* ---------------------------- External imports ---------------------------- */
+use std::collections::{BTreeMap, HashMap};
+use serde::{Deserialize, Serialize};
+
+/* ---------------------------- Internal imports ---------------------------- */
+use super::{RonReactions, RonKReaction, RonAcidBase, RonBioParam, RonTransfer, RonRemovals,
+            sorted};
+use crate::reactions::compartments::Compartment;
+use crate::reactions::registry::SpeciesEntry;
+use crate::reactions::errors::RadioBioError;
+use crate::reactions::profiles::{self, ConcentrationProfile, Bolus};
+use crate::reactions::provenance::{Origin, Provenance};
+use crate::reactions::metadata::Uncertainty;
+use crate::physics::gas::Headspace;
+use crate::units;
+
+/* -------------------------------------------------------------------------- */
+/*                         FUNCTION/STRUCT DEFINITIONS                        */
+/* -------------------------------------------------------------------------- */
+// Reactions database as exported from Python (data/reactions.json).
+// Every other key must be a list of k reactions named
+// "<species>-reactions-list".
+#[derive(Debug, Deserialize, Serialize, Default)]
+#[serde(rename_all = "kebab-case")]
+struct JsonReactions {
+    // Written with resolved mechanisms, ignored when read
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    provenance: Vec<Origin>,
+    #[serde(default)]
+    bio_param: RonBioParam,
+    #[serde(default, deserialize_with = "units::concentrations", serialize_with = "sorted")]
+    fixed_concentrations: HashMap<String, f64>,
+    #[serde(default, deserialize_with = "units::concentrations", serialize_with = "sorted")]
+    initial_concentrations: HashMap<String, f64>,
+    #[serde(default)]
+    acid_base_reactions: Vec<JsonAcidBase>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    compartments: Vec<Compartment>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    transfers: Vec<RonTransfer>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    headspace: Option<Headspace>,
+    #[serde(default, deserialize_with = "profiles::profiles",
+            skip_serializing_if = "HashMap::is_empty", serialize_with = "sorted")]
+    fixed_profiles: HashMap<String, ConcentrationProfile>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    boluses: Vec<Bolus>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty", serialize_with = "sorted")]
+    species: HashMap<String, SpeciesEntry>,
+    #[serde(default, skip_serializing)]
+    include: Vec<String>,
+    #[serde(default, skip_serializing)]
+    k_overrides: HashMap<String, f64>,
+    #[serde(default, skip_serializing)]
+    remove: RonRemovals,
+    #[serde(flatten)]
+    reactions_lists: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct JsonAcidBase {
+    acid: String,
+    base: String,
+    pka: f64,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    label: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    reference: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    uncertainty: Option<Uncertainty>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    notes: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct JsonKReaction {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    id: String,
+    #[serde(default)]
+    reactants: Vec<String>,
+    #[serde(default)]
+    products: Vec<String>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    equation: String,
+    k_value: f64,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    label: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    reference: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    uncertainty: Option<Uncertainty>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    notes: String,
+}
+
+pub(super) fn from_json(path:&str, content:&str) -> Result<RonReactions, RadioBioError> {
+    let json: JsonReactions = serde_json::from_str(content)
+        .map_err(|e| {
+            // Position is already given by the error fields
+            let message = e.to_string();
+            let message = match message.rfind(" at line ") {
+                Some(idx) => message[..idx].to_string(),
+                None => message,
+            };
+            RadioBioError::Parse {
+                path: path.to_string(),
+                line: e.line(),
+                column: e.column(),
+                message,
+            }
+        })?;
+
+    // Checked by hand to report unknown keys by name
+    let mut k_reactions = vec![];
+    for (key, value) in json.reactions_lists {
+        let (line, column) = position(content, &key);
+        let error = |message:String| RadioBioError::Parse {
+            path: path.to_string(), line, column, message
+        };
+        if !key.ends_with("reactions-list") {
+            return Err(error(format!(
+                "unknown key \"{key}\", lists of k reactions end with \"reactions-list\"")));
+        }
+        let list: Vec<JsonKReaction> = serde_json::from_value(value)
+            .map_err(|e| error(format!("in \"{key}\": {e}")))?;
+        k_reactions.extend(list);
+    }
+
+    Ok(RonReactions {
+        bio_param: json.bio_param,
+        fixed_concentrations: json.fixed_concentrations,
+        initial_concentrations: json.initial_concentrations,
+        acid_base: json.acid_base_reactions.into_iter()
+            .map(|ab| RonAcidBase {
+                acid: ab.acid,
+                base: ab.base,
+                pKa: ab.pka,
+                id: ab.id,
+                label: ab.label,
+                reference: ab.reference,
+                uncertainty: ab.uncertainty,
+                notes: ab.notes,
+            })
+            .collect(),
+        k_reactions: k_reactions.into_iter()
+            .map(|r| RonKReaction {
+                id: r.id,
+                reactants: r.reactants,
+                products: r.products,
+                equation: r.equation,
+                k_value: r.k_value,
+                label: r.label,
+                reference: r.reference,
+                uncertainty: r.uncertainty,
+                notes: r.notes,
+            })
+            .collect(),
+        compartments: json.compartments,
+        transfers: json.transfers,
+        headspace: json.headspace,
+        fixed_profiles: json.fixed_profiles,
+        boluses: json.boluses,
+        species: json.species,
+        include: json.include,
+        k_overrides: json.k_overrides,
+        remove: json.remove,
+    })
+}
+
+// Line and column of the first occurrence of a key, (0, 0) if not found
+fn position(content:&str, key:&str) -> (usize, usize) {
+    let idx = match content.find(&format!("\"{key}\"")) {
+        Some(idx) => idx,
+        None => return (0, 0),
+    };
+    let before = &content[..idx];
+    let line = before.matches('\n').count() + 1;
+    let column = idx - before.rfind('\n').map(|i| i + 1).unwrap_or(0) + 1;
+    (line, column)
+}
+
+// Same schema, all k reactions in a single "reactions-list"
+pub(super) fn to_json(config:&RonReactions, provenance:&Provenance)
+-> Result<String, RadioBioError> {
+    let reactions: Vec<JsonKReaction> = config.k_reactions.iter()
+        .map(|r| JsonKReaction {
+            id: r.id.clone(),
+            reactants: r.reactants.clone(),
+            products: r.products.clone(),
+            equation: r.equation.clone(),
+            k_value: r.k_value,
+            label: r.label.clone(),
+            reference: r.reference.clone(),
+            uncertainty: r.uncertainty,
+            notes: r.notes.clone(),
+        })
+        .collect();
+    let reactions = serde_json::to_value(reactions)
+        .map_err(|e| RadioBioError::Serialization(e.to_string()))?;
+    let json = JsonReactions {
+        provenance: provenance.iter().cloned().collect(),
+        bio_param: config.bio_param.clone(),
+        fixed_concentrations: config.fixed_concentrations.clone(),
+        initial_concentrations: config.initial_concentrations.clone(),
+        acid_base_reactions: config.acid_base.iter()
+            .map(|ab| JsonAcidBase {
+                acid: ab.acid.clone(),
+                base: ab.base.clone(),
+                pka: ab.pKa,
+                id: ab.id.clone(),
+                label: ab.label.clone(),
+                reference: ab.reference.clone(),
+                uncertainty: ab.uncertainty,
+                notes: ab.notes.clone(),
+            })
+            .collect(),
+        compartments: config.compartments.clone(),
+        transfers: config.transfers.clone(),
+        headspace: config.headspace.clone(),
+        fixed_profiles: config.fixed_profiles.clone(),
+        boluses: config.boluses.clone(),
+        species: config.species.clone(),
+        reactions_lists: BTreeMap::from([(String::from("reactions-list"), reactions)]),
+        ..Default::default()
+    };
+    serde_json::to_string_pretty(&json)
+        .map_err(|e| RadioBioError::Serialization(e.to_string()))
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   TESTING                                  */
+/* -------------------------------------------------------------------------- */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reactions::parse_reactions_file;
+    use crate::reactions::k_reactions::ChemicalReaction;
+    use crate::Env;
+
+    fn k_reactions(env:&Env) -> Vec<String> {
+        let mut out: Vec<String> = env.reactions.iter()
+            .filter_map(|r| match r {
+                ChemicalReaction::KReaction(kr) => Some(format!("{kr} ({})", kr.k_value())),
+                _ => None,
+            })
+            .collect();
+        out.sort();
+        out
+    }
+
+    #[test]
+    fn json_matches_ron_database() {
+        let dir = format!("{}/data", env!("CARGO_MANIFEST_DIR"));
+        let json = parse_reactions_file(&format!("{dir}/reactions.json")).unwrap();
+        let ron = parse_reactions_file(&format!("{dir}/reactions.ron")).unwrap();
+        assert_eq!(k_reactions(&json), k_reactions(&ron));
+        assert_eq!(json.iter_ABCouples().count(), ron.iter_ABCouples().count());
+        assert_eq!(json.bio_param.pH, 7.0);
+
+        match from_json("bad.json", "{\n  \"acid-base-reactions\": [ {\"acid\": 1} ]\n}") {
+            Err(RadioBioError::Parse { line, message, .. }) => {
+                assert_eq!(line, 2);
+                assert!(!message.contains(" at line "));
+            },
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        // Misspelt sections are reported by name
+        let content = "{\n  \"acid-base-reaction\": []\n}";
+        match from_json("bad.json", content) {
+            Err(RadioBioError::Parse { line, column, message, .. }) => {
+                assert_eq!((line, column), (2, 3));
+                assert!(message.contains("\"acid-base-reaction\""));
+            },
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        let content = "{\n  \"O2-reactions-list\": [ {\"k-value\": \"fast\"} ]\n}";
+        match from_json("bad.json", content) {
+            Err(RadioBioError::Parse { line, message, .. }) => {
+                assert_eq!(line, 2);
+                assert!(message.contains("\"O2-reactions-list\""));
+            },
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,292 @@
+/* ---------------------------- Internal imports ---------------------------- */
+use super::{RonReactions, RonKReaction};
+use crate::reactions::errors::RadioBioError;
+use crate::units::MOLECULES_PER_CM3;
+
+/* -------------------------------------------------------------------------- */
+/*                         FUNCTION/STRUCT DEFINITIONS                        */
+/* -------------------------------------------------------------------------- */
+// Statement of a KPP file, ended by ';'
+struct Statement {
+    section: String,
+    text: String,
+    line: usize,
+}
+
+// Read the #EQUATIONS, #DEFVAR, #DEFFIX and #INITVALUES sections of a KPP
+// file (.eqn, .spc or .kpp). Other sections (#INCLUDE, #INLINE...) are
+// skipped: species and equations files are combined with `include` from a
+// reactions file. Only constant rates are read, everything that cannot be
+// imported is reported at once.
+// KPP works in molecule/cm3: concentrations (after CFACTOR) are converted to
+// mol/l, and a rate constant of order m, in (cm3/molecule)^(m-1)/s, is
+// multiplied by (N_A/1000)^(m-1). Fixed species count in the order. Files
+// written by crate::export::kpp follow the same convention.
+// Species repeated on one side of an equation are reported: the simulated
+// rate k.[X]/n changes X once, see crate::export.
+pub(super) fn from_kpp(path:&str, content:&str) -> Result<RonReactions, RadioBioError> {
+    let mut config = RonReactions::default();
+    let mut issues = vec![];
+    let mut variables = vec![];
+    let mut fixed = vec![];
+    let mut values = vec![];
+    let mut cfactor = 1.0;
+
+    for st in split_statements(path, content)? {
+        match st.section.as_str() {
+            "#EQUATIONS" => match read_equation(&st.text) {
+                Ok(reaction) => config.k_reactions.push(reaction),
+                Err(message) => issues.push(format!("line {}: {}", st.line, message)),
+            },
+            "#DEFVAR" | "#DEFFIX" => {
+                let name = st.text.split('=').next().unwrap_or("").trim().to_string();
+                match st.section == "#DEFVAR" {
+                    true => variables.push(name),
+                    false => fixed.push(name),
+                }
+            },
+            "#INITVALUES" => {
+                let (name, value) = st.text.split_once('=').unwrap_or((&st.text, ""));
+                let name = name.trim();
+                match read_constant(value) {
+                    Some(value) if name == "CFACTOR" => cfactor = value,
+                    Some(value) => values.push((name.to_string(), value)),
+                    None => issues.push(format!(
+                        "line {}: unsupported initial value for {}: {}",
+                        st.line, name, value.trim())),
+                }
+            },
+            _ => {},
+        }
+    }
+
+    // ALL_SPEC sets every variable species, explicit values win
+    let scale = cfactor / MOLECULES_PER_CM3;
+    let default = values.iter().find(|(name, _)| name == "ALL_SPEC").map(|(_, v)| *v);
+    for sp in variables.iter() {
+        match values.iter().find(|(name, _)| name == sp) {
+            Some((_, value)) => {config.initial_concentrations.insert(sp.clone(), value*scale);},
+            None => if let Some(value) = default.filter(|v| *v != 0.0) {
+                config.initial_concentrations.insert(sp.clone(), value*scale);
+            },
+        }
+    }
+    for sp in fixed.iter() {
+        match values.iter().find(|(name, _)| name == sp) {
+            Some((_, value)) => {config.fixed_concentrations.insert(sp.clone(), value*scale);},
+            None => issues.push(format!("fixed species {sp} has no value in #INITVALUES")),
+        }
+    }
+
+    match issues.is_empty() {
+        true => Ok(config),
+        false => Err(RadioBioError::KppImport { path: path.to_string(), issues }),
+    }
+}
+
+// Comments ({...}) removed, #INLINE blocks skipped
+fn split_statements(path:&str, content:&str) -> Result<Vec<Statement>, RadioBioError> {
+    let mut out = vec![];
+    let mut section = String::new();
+    let mut current = String::new();
+    let mut start = 0;
+    let mut in_comment = false;
+    let mut in_inline = false;
+    for (idx, line) in content.lines().enumerate() {
+        let mut text = String::new();
+        for c in line.chars() {
+            match (in_comment, c) {
+                (false, '{') => in_comment = true,
+                (true, '}') => in_comment = false,
+                (false, _) => text.push(c),
+                (true, _) => {},
+            }
+        }
+        let trimmed = text.trim();
+        if in_inline {
+            in_inline = !trimmed.starts_with("#ENDINLINE");
+            continue;
+        }
+        let mut rest = trimmed;
+        if trimmed.starts_with('#') {
+            if !current.trim().is_empty() {
+                return Err(unterminated(path, start, &current));
+            }
+            let (keyword, tail) = trimmed.split_once(char::is_whitespace)
+                                         .unwrap_or((trimmed, ""));
+            section = keyword.to_uppercase();
+            in_inline = section == "#INLINE";
+            rest = tail;
+        }
+        if in_inline { continue; }
+        for c in rest.chars() {
+            if current.trim().is_empty() { start = idx+1; }
+            match c {
+                ';' => {
+                    out.push(Statement {
+                        section: section.clone(),
+                        text: current.trim().to_string(),
+                        line: start,
+                    });
+                    current.clear();
+                },
+                _ => current.push(c),
+            }
+        }
+        current.push(' ');
+    }
+    if !current.trim().is_empty() && section_needs_semicolon(&section) {
+        return Err(unterminated(path, start, &current));
+    }
+    Ok(out)
+}
+
+fn section_needs_semicolon(section:&str) -> bool {
+    matches!(section, "#EQUATIONS" | "#DEFVAR" | "#DEFFIX" | "#INITVALUES")
+}
+
+fn unterminated(path:&str, line:usize, text:&str) -> RadioBioError {
+    RadioBioError::Parse {
+        path: path.to_string(),
+        line: line,
+        column: 1,
+        message: format!("missing ';' after '{}'", text.trim()),
+    }
+}
+
+// "<R1> A + B = C : 1.0e10" -> (id: "R1", equation: "A + B = C", k: 1e10)
+fn read_equation(text:&str) -> Result<RonKReaction, String> {
+    let (id, text) = match text.strip_prefix('<') {
+        Some(tail) => tail.split_once('>')
+                          .map(|(id, tail)| (id.trim().to_string(), tail))
+                          .ok_or_else(|| format!("unclosed label in '{text}'"))?,
+        None => (String::new(), text),
+    };
+    let (equation, rate) = text.split_once(':')
+        .ok_or_else(|| format!("missing rate in '{}'", text.trim()))?;
+    let name = match id.is_empty() {
+        true => equation.trim().to_string(),
+        false => format!("<{id}>"),
+    };
+    let fractional = |term:&str| term.trim().chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .any(|c| c == '.');
+    if equation.split(['=', '+']).any(fractional) {
+        return Err(format!("{name}: fractional coefficients are not supported"));
+    }
+    // The simulation changes each species once per reaction, with the rate
+    // k.[X]/n: "A + A" or "2 A" would not be second order in A
+    let (left, right) = equation.split_once('=').unwrap_or((equation, ""));
+    for (side, what) in [(left, "reactant"), (right, "product")] {
+        let mut seen: Vec<&str> = vec![];
+        for term in side.split('+').map(str::trim).filter(|t| !t.is_empty()) {
+            let species = term.trim_start_matches(|c:char| c.is_ascii_digit());
+            let count:u32 = term[..term.len()-species.len()].parse().unwrap_or(1);
+            let species = species.trim();
+            if count > 1 || seen.contains(&species) {
+                return Err(format!("{name}: {species} appears more than once as a {what}, \
+                                    which the simulated rate law cannot represent"));
+            }
+            seen.push(species);
+        }
+    }
+    let k_value = read_constant(rate)
+        .ok_or_else(|| format!("{name}: unsupported rate expression '{}'", rate.trim()))?;
+    let order = left.split('+').filter(|term| !term.trim().is_empty()).count() as i32;
+    Ok(RonKReaction {
+        id: id,
+        reactants: vec![],
+        products: vec![],
+        equation: equation.split_whitespace().collect::<Vec<_>>().join(" "),
+        k_value: k_value * MOLECULES_PER_CM3.powi(order-1),
+        ..Default::default()
+    })
+}
+
+// Number literals, Fortran exponents (1.5D-10) included, possibly
+// multiplied or divided by each other
+fn read_constant(expression:&str) -> Option<f64> {
+    let expression = expression.trim();
+    let expression = expression.strip_prefix('(')
+                               .and_then(|e| e.strip_suffix(')'))
+                               .unwrap_or(expression);
+    let mut value = 1.0;
+    let mut divide = false;
+    let mut term = String::new();
+    let mut apply = |term:&str, divide:bool| -> Option<()> {
+        let number = term.trim().replace(['D', 'd'], "e").parse::<f64>().ok()?;
+        match divide {
+            true => value /= number,
+            false => value *= number,
+        }
+        Some(())
+    };
+    for c in expression.chars() {
+        match c {
+            '*' | '/' => {
+                apply(&term, divide)?;
+                divide = c == '/';
+                term.clear();
+            },
+            _ => term.push(c),
+        }
+    }
+    apply(&term, divide)?;
+    Some(value)
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   TESTING                                  */
+/* -------------------------------------------------------------------------- */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kpp_files_are_imported() {
+        let content = "{ Water radiolysis }\n\
+            #DEFVAR\n  e_aq = IGNORE;\n  O2 = O + O; { composition is ignored }\n\
+            #DEFFIX\n  H2O = IGNORE;\n\
+            #INLINE F90_RATES\n  REAL(dp) FUNCTION ARR(a, b)\n#ENDINLINE\n\
+            #EQUATIONS\n\
+            <R1> e_aq + H2O = H_r + OH_minus : 1.9D1;\n\
+            <R2> e_aq + O2 =\n     O2_r_minus : (1.9e10);\n\
+            e_aq + H_r = H2 + 1OH_minus : 2.5e10*0.5;\n\
+            #INITVALUES\n  CFACTOR = 1.0e-6;\n  O2 = 75;\n  H2O = 55.5e6;\n";
+        let config = from_kpp("mechanism.eqn", content).unwrap();
+        assert_eq!(config.k_reactions.len(), 3);
+        assert_eq!(config.k_reactions[0].id, "R1");
+        assert_eq!(config.k_reactions[1].equation, "e_aq + O2 = O2_r_minus");
+        // molecule/cm3 -> mol/l, by reaction order
+        let na = MOLECULES_PER_CM3;
+        assert_float_relative_eq!(config.k_reactions[0].k_value, 19.0*na, 1e-12);
+        assert_float_relative_eq!(config.k_reactions[2].k_value, 1.25e10*na, 1e-12);
+        assert_eq!(config.k_reactions[2].equation, "e_aq + H_r = H2 + 1OH_minus");
+        assert_float_relative_eq!(config.initial_concentrations["O2"], 75e-6/na, 1e-12);
+        assert_float_relative_eq!(config.fixed_concentrations["H2O"], 55.5/na, 1e-12);
+
+        let content = "#DEFVAR A = IGNORE; B = IGNORE;\n#EQUATIONS\n\
+            <R1> A = B : 2.0;\n<R2> A + B + C = B : 3.0;\n\
+            #INITVALUES\n  ALL_SPEC = 6.02214076e20;\n";
+        let config = from_kpp("orders.eqn", content).unwrap();
+        assert_eq!(config.k_reactions[0].k_value, 2.0);
+        assert_float_relative_eq!(config.k_reactions[1].k_value, 3.0*na*na, 1e-12);
+        assert_float_relative_eq!(config.initial_concentrations["A"], 1.0, 1e-12);
+
+        let content = "#DEFFIX N2 = IGNORE;\n#EQUATIONS\n\
+            <R1> OH + CO = HO2 + CO2 : ARR2(1.5e-13, 0.0, TEMP);\n\
+            <R2> NO2 = 0.5 NO + 0.5 NO3 : 1e-3;\n\
+            <R3> HO2 + HO2 = H2O2 + O2 : 1.5e-12;\n<R4> N2O5 = 2NO2 + O : 1e-2;\n";
+        match from_kpp("atmo.eqn", content) {
+            Err(RadioBioError::KppImport { issues, .. }) => {
+                assert_eq!(issues.len(), 5);
+                assert!(issues[0].contains("<R1>: unsupported rate expression 'ARR2"));
+                assert!(issues[1].contains("fractional"));
+                assert!(issues[2].contains("<R3>: HO2 appears more than once as a reactant"));
+                assert!(issues[3].contains("<R4>: NO2 appears more than once as a product"));
+                assert!(issues[4].contains("N2"));
+            },
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,232 @@
+/* ---------------------------- External imports ---------------------------- */
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+
+/* ---------------------------- Internal imports ---------------------------- */
+use super::compartments::{split_namespace, namespaced};
+use super::composition::Composition;
+use super::errors::RadioBioError;
+use super::reactions_parser::{present, serialize_present};
+
+/* -------------------------------------------------------------------------- */
+/*                         FUNCTION/STRUCT DEFINITIONS                        */
+/* -------------------------------------------------------------------------- */
+// Molar masses [g/mol] of the elements found in radiobiology mechanisms
+const ATOMIC_MASSES: [(&str, f64); 16] = [
+    ("H", 1.008), ("C", 12.011), ("N", 14.007), ("O", 15.999), ("F", 18.998),
+    ("Na", 22.990), ("Mg", 24.305), ("P", 30.974), ("S", 32.06), ("Cl", 35.45),
+    ("K", 39.098), ("Ca", 40.078), ("Fe", 55.845), ("Cu", 63.546), ("Br", 79.904),
+    ("I", 126.904),
+];
+
+// Declaration of a species in a reactions file, every field is optional:
+// "OH_r": (display: "•OH", aliases: ["OH"], diffusion: 2.3e-9)
+// The formula is only needed for labels not following the naming
+// convention, e.g. "DMSO": (formula: "C2H6OS")
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct SpeciesEntry {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub formula: String,
+    #[serde(default, deserialize_with = "present", serialize_with = "serialize_present",
+            skip_serializing_if = "Option::is_none")]
+    pub charge: Option<i32>,
+    // Name used in output headers and reports
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub display: String,
+    // Other labels of the species, replaced when the files are read
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    // [m²/s]
+    #[serde(default, deserialize_with = "present", serialize_with = "serialize_present",
+            skip_serializing_if = "Option::is_none")]
+    pub diffusion: Option<f64>,
+    // [g/mol]
+    #[serde(default, deserialize_with = "present", serialize_with = "serialize_present",
+            skip_serializing_if = "Option::is_none")]
+    pub molar_mass: Option<f64>,
+}
+
+impl SpeciesEntry {
+    // Only when a formula is given
+    pub fn composition(&self) -> Option<Result<Composition, RadioBioError>> {
+        match self.formula.is_empty() {
+            true => None,
+            false => Some(Composition::from_formula(&self.formula, self.charge.unwrap_or(0))),
+        }
+    }
+}
+
+// Every species declared in the reactions files, with their aliases.
+// Undeclared species get what can be read from their label.
+#[derive(Debug, Clone, Default)]
+pub struct SpeciesRegistry {
+    entries: HashMap<String, SpeciesEntry>,
+    aliases: HashMap<String, String>, // alias -> label
+}
+
+impl SpeciesRegistry {
+    pub fn new(entries:&HashMap<String, SpeciesEntry>) -> Result<Self, RadioBioError> {
+        let mut aliases: HashMap<String, String> = HashMap::new();
+        for (label, entry) in entries.iter() {
+            for alias in entry.aliases.iter().filter(|alias| *alias != label) {
+                if entries.contains_key(alias) {
+                    return Err(RadioBioError::InvalidAlias(format!(
+                        "{alias} is an alias of {label} and a declared species")));
+                }
+                if let Some(other) = aliases.insert(alias.clone(), label.clone()) {
+                    if other != *label {
+                        return Err(RadioBioError::InvalidAlias(format!(
+                            "{alias} is an alias of both {other} and {label}")));
+                    }
+                }
+            }
+        }
+        Ok(Self { entries: entries.clone(), aliases })
+    }
+
+    // Label of the species an alias stands for, in the same compartment
+    pub fn resolve(&self, label:&str) -> String {
+        if let Some(target) = self.aliases.get(label) {
+            return target.clone();
+        }
+        match split_namespace(label) {
+            (Some(comp), species) => match self.aliases.get(species) {
+                Some(target) => namespaced(comp, target),
+                None => label.to_string(),
+            },
+            (None, _) => label.to_string(),
+        }
+    }
+
+    // Entries of namespaced labels default to the generic declaration
+    pub fn entry(&self, label:&str) -> Option<&SpeciesEntry> {
+        let (_, species) = split_namespace(label);
+        self.entries.get(label).or_else(|| self.entries.get(species))
+    }
+    pub fn iter(&self) -> impl Iterator<Item=(&String, &SpeciesEntry)> {
+        self.entries.iter()
+    }
+
+    pub fn composition(&self, label:&str) -> Option<Composition> {
+        match self.entry(label).and_then(|e| e.composition()) {
+            Some(comp) => comp.ok(),
+            None => Composition::from_label(label),
+        }
+    }
+    pub fn charge(&self, label:&str) -> Option<i32> {
+        self.entry(label).and_then(|e| e.charge)
+            .or_else(|| self.composition(label).map(|c| c.charge()))
+    }
+    pub fn diffusion(&self, label:&str) -> Option<f64> {
+        self.entry(label).and_then(|e| e.diffusion)
+    }
+    // Declared, else computed from the composition
+    pub fn molar_mass(&self, label:&str) -> Option<f64> {
+        if let Some(mass) = self.entry(label).and_then(|e| e.molar_mass) {
+            return Some(mass);
+        }
+        let comp = self.composition(label)?;
+        let mut mass = 0.0;
+        let mut n_atoms = 0;
+        for (element, n) in comp.iter_elements() {
+            let (_, m) = ATOMIC_MASSES.iter().find(|(el, _)| el == element)?;
+            mass += m * *n as f64;
+            n_atoms += n;
+        }
+        match n_atoms > 0 {
+            true => Some(mass),
+            false => None,
+        }
+    }
+
+    // "O2_r_minus" -> "O₂•⁻", "intra:OH_r" -> "intra:OH•". Acid/base
+    // couples give "HO₂•/O₂•⁻".
+    pub fn display_name(&self, label:&str) -> String {
+        if let Some((acid, base)) = label.split_once('/') {
+            return format!("{}/{}", self.display_name(acid), self.display_name(base));
+        }
+        let (comp, species) = split_namespace(label);
+        let name = match self.entry(label).filter(|e| !e.display.is_empty()) {
+            Some(entry) => entry.display.clone(),
+            None => display_from_label(species),
+        };
+        match comp {
+            Some(comp) => namespaced(comp, &name),
+            None => name,
+        }
+    }
+}
+
+fn display_from_label(species:&str) -> String {
+    if species == "e_aq" {
+        return String::from("e⁻(aq)");
+    }
+    let mut parts = species.split('_');
+    let formula = parts.next().unwrap_or("");
+    let (mut radical, mut charge) = (false, 0_i32);
+    for tag in parts {
+        match tag {
+            "r" => radical = true,
+            "minus" => charge -= 1,
+            "plus" => charge += 1,
+            _ => return species.to_string(),
+        }
+    }
+    let mut out: String = formula.chars().map(|c| match c.to_digit(10) {
+        Some(d) => ['₀', '₁', '₂', '₃', '₄', '₅', '₆', '₇', '₈', '₉'][d as usize],
+        None => c,
+    }).collect();
+    if radical {
+        out.push('•');
+    }
+    if charge.abs() > 1 {
+        out.extend(charge.abs().to_string().chars().map(|c| {
+            ['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'][c.to_digit(10).unwrap() as usize]
+        }));
+    }
+    match charge {
+        c if c < 0 => out.push('⁻'),
+        c if c > 0 => out.push('⁺'),
+        _ => {},
+    }
+    out
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   TESTING                                  */
+/* -------------------------------------------------------------------------- */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry() {
+        let entries = HashMap::from([
+            (String::from("OH_r"), SpeciesEntry {
+                display: String::from("•OH"),
+                aliases: vec![String::from("OH"), String::from("HO_r")],
+                diffusion: Some(2.3e-9),
+                ..Default::default()
+            }),
+            (String::from("DMSO"), SpeciesEntry {
+                formula: String::from("C2H6OS"),
+                ..Default::default()
+            }),
+        ]);
+        let registry = SpeciesRegistry::new(&entries).unwrap();
+        assert_eq!(registry.resolve("OH"), "OH_r");
+        assert_eq!(registry.resolve("intra:HO_r"), "intra:OH_r");
+        assert_eq!(registry.resolve("O2"), "O2");
+        assert_eq!(registry.display_name("intra:OH_r"), "intra:•OH");
+        assert_eq!(registry.display_name("O2_r_minus"), "O₂•⁻");
+        assert_eq!(registry.display_name("HO2_r/O2_minus_minus"), "HO₂•/O₂²⁻");
+        assert_eq!(registry.diffusion("extra:OH_r"), Some(2.3e-9));
+        assert_eq!(registry.charge("O2_r_minus"), Some(-1));
+        assert_float_relative_eq!(registry.molar_mass("DMSO").unwrap(), 78.13, 1e-3);
+        assert!(registry.molar_mass("e_aq").is_none());
+
+        let mut entries = entries;
+        entries.insert(String::from("HO_r"), SpeciesEntry::default());
+        assert!(SpeciesRegistry::new(&entries).is_err());
+    }
+}
@@ -6,6 +6,7 @@ use anyhow::{Result, bail};
 use super::acid_base::{AcidBase, ABPartner};
 use super::traits::{IsTrackedSpecies, RawSpecies};
 use super::k_reactions::ReactionRateIndex;
+use super::profiles::ConcentrationProfile;
 
 // Internal module use
 //use super::errors::RadioBioError;
@@ -43,7 +44,7 @@ impl ReactionSpecies {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum SimSpecies {
     TrackedSpecies(SimpleSpecies),
     CstSpecies(CstSpecies), //No need to track it.
@@ -67,6 +68,9 @@ impl SimSpecies {
     pub fn new_cst_species(label:String, cc:f64) -> Self {
         Self::CstSpecies(CstSpecies::new(label, cc))
     }
+    pub fn new_clamped_species(label:String, profile:ConcentrationProfile) -> Self {
+        Self::CstSpecies(CstSpecies::new_clamped(label, profile))
+    }
     pub fn new_acid_partner(label:String, index:usize) -> Self {
         Self::ABPartner(ABPartner::new_acid(label, index))
     }
@@ -74,19 +78,12 @@ impl SimSpecies {
         Self::ABPartner(ABPartner::new_base(label, index))
     }
     pub fn is_tracked(&self) -> bool {
-        match self {
-            Self::TrackedSpecies(_)  => true,
-            Self::ABCouple(_)  => true,
-            _ => false,
-        }
+        matches!(self, Self::TrackedSpecies(_) | Self::ABCouple(_))
     }
 
     #[allow(non_snake_case)]
     pub fn is_ABCouple(&self) -> bool {
-        match self {
-            Self::ABCouple(_)  => true,
-            _ => false,
-        }
+        matches!(self, Self::ABCouple(_))
     }
     pub fn unwrap_tracked(&self) -> Result<&dyn IsTrackedSpecies> {
         match self {
@@ -111,7 +108,7 @@ impl Display for SimSpecies {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SimpleSpecies {
     label: String,
     index: usize,
@@ -124,7 +121,7 @@ impl RawSpecies for SimpleSpecies {
 
 impl IsTrackedSpecies for SimpleSpecies {
     fn index(&self) -> usize { self.index }
-    fn iter_kreaction_indexes(&self) -> std::slice::Iter<ReactionRateIndex>{
+    fn iter_kreaction_indexes(&self) -> std::slice::Iter<'_, ReactionRateIndex>{
         self.kreaction.iter()
     }
     fn link_kreaction(&mut self, index:ReactionRateIndex) {
@@ -151,10 +148,11 @@ impl Display for SimpleSpecies {
 }
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CstSpecies {
     label: String,
     cc_value: f64,
+    profile: Option<ConcentrationProfile>, // Overrides cc_value if any
 }
 
 impl RawSpecies for CstSpecies {
@@ -163,11 +161,23 @@ impl RawSpecies for CstSpecies {
 
 impl CstSpecies {
     pub fn new(label:String, cc:f64) -> Self {
-        Self {label, cc_value:cc}
+        Self {label, cc_value:cc, profile:None}
+    }
+    pub fn new_clamped(label:String, profile:ConcentrationProfile) -> Self {
+        Self {label, cc_value:profile.at(0.0), profile:Some(profile)}
     }
 
     pub fn name(&self) -> &str { &self.label }
     pub fn cc_value(&self) -> f64 { self.cc_value }
+    pub fn cc_at(&self, time:f64) -> f64 {
+        match &self.profile {
+            Some(profile) => profile.at(time),
+            None => self.cc_value,
+        }
+    }
+    pub fn profile(&self) -> Option<&ConcentrationProfile> {
+        self.profile.as_ref()
+    }
 }
 
 // For use in println!()
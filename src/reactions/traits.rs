@@ -24,11 +24,11 @@ pub trait IsChemicalReactionList {
 pub trait IsChemicalReaction {
     fn compute_reaction(&self, current_dose_rate:f64, sp:&HashMap<String, f64>)
     -> Result<f64>;
-    fn species(&self) -> std::slice::Iter<ReactionSpecies>;
-    fn reactants(&self) -> ReactantsIter{
+    fn species(&self) -> std::slice::Iter<'_, ReactionSpecies>;
+    fn reactants(&self) -> ReactantsIter<'_>{
         ReactantsIter { inner: self.species() }
     }
-    fn products(&self) -> ProductsIter{
+    fn products(&self) -> ProductsIter<'_>{
         ProductsIter { inner: self.species() }
     }
 }
@@ -40,7 +40,7 @@ pub trait RawSpecies {
 
 pub trait IsTrackedSpecies {
     fn index(&self) -> usize;
-    fn iter_kreaction_indexes(&self) -> std::slice::Iter<ReactionRateIndex>;
+    fn iter_kreaction_indexes(&self) -> std::slice::Iter<'_, ReactionRateIndex>;
     fn link_kreaction(&mut self, index:ReactionRateIndex);
 }
 
@@ -55,10 +55,7 @@ impl<'a> Iterator for ReactantsIter<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            let res = match self.inner.nth(0) {
-                Some(x) => x,
-                None => return None
-            };
+            let res = self.inner.next()?;
             match res {
                 ReactionSpecies::Product(_) => continue ,
                 ReactionSpecies::Reactant(sp) => return Some(sp),
@@ -76,10 +73,7 @@ impl<'a> Iterator for ProductsIter<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            let res = match self.inner.nth(0) {
-                Some(x) => x,
-                None => return None
-            };
+            let res = self.inner.next()?;
             match res {
                 ReactionSpecies::Product(sp) => return Some(sp) ,
                 ReactionSpecies::Reactant(_) => continue,
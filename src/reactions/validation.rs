@@ -0,0 +1,76 @@
+/* ---------------------------- External imports ---------------------------- */
+use std::fmt;
+
+/* ---------------------------- Internal imports ---------------------------- */
+
+
+/* -------------------------------------------------------------------------- */
+/*                         FUNCTION/STRUCT DEFINITIONS                        */
+/* -------------------------------------------------------------------------- */
+// Accepted pKa values. Outside of this range the couple is fully
+// dissociated (or not at all) in water and should not be declared.
+pub const PKA_RANGE: (f64, f64) = (-2.0, 16.0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning, // Suspicious but simulated as written
+    Error,   // The file is rejected
+}
+
+#[derive(Debug, Clone)]
+pub struct Issue {
+    pub severity: Severity,
+    pub message: String,
+}
+
+// Every problem found in a reactions file, not only the first one
+#[derive(Debug, Clone, Default)]
+pub struct ValidationReport {
+    issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn error(&mut self, message:String) {
+        self.issues.push(Issue { severity: Severity::Error, message });
+    }
+    pub fn warning(&mut self, message:String) {
+        self.issues.push(Issue { severity: Severity::Warning, message });
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=&Issue> {
+        self.issues.iter()
+    }
+    pub fn iter_errors(&self) -> impl Iterator<Item=&Issue> {
+        self.issues.iter().filter(|i| i.severity == Severity::Error)
+    }
+    pub fn iter_warnings(&self) -> impl Iterator<Item=&Issue> {
+        self.issues.iter().filter(|i| i.severity == Severity::Warning)
+    }
+    pub fn has_errors(&self) -> bool {
+        self.iter_errors().next().is_some()
+    }
+    pub fn is_empty(&self) -> bool { self.issues.is_empty() }
+    pub fn len(&self) -> usize { self.issues.len() }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "error: {}", self.message),
+            Severity::Warning => write!(f, "warning: {}", self.message),
+        }
+    }
+}
+
+// One issue per line
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        for (idx, issue) in self.issues.iter().enumerate() {
+            if idx > 0 { writeln!(f)?; }
+            write!(f, "{}", issue)?;
+        }
+        Ok(())
+    }
+}
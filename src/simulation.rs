@@ -0,0 +1,543 @@
+/* ---------------------------- External imports ---------------------------- */
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/* ---------------------------- Internal imports ---------------------------- */
+use crate::{Env, ODESolver, State, Time};
+use crate::analysis::{self, AnalysisConfig, Slopes, SpeciesMetrics};
+use crate::ode_solver::dopri5::Dopri5;
+use crate::ode_solver::rk4::Rk4;
+use crate::ode_solver::rosenbrock::Rosenbrock;
+use crate::ode_solver::traits::{Stats, System};
+use crate::physics::beam::Beam;
+use crate::physics::schedule::{Fraction, TreatmentSchedule};
+use crate::reactions::errors::RadioBioError;
+use crate::reactions::parse_reactions_file;
+use crate::units::{self, to_state, OutputUnits};
+
+/* -------------------------------------------------------------------------- */
+/*                         FUNCTION/STRUCT DEFINITIONS                        */
+/* -------------------------------------------------------------------------- */
+// Everything needed to run a simulation, e.g. data/simulation.ron. Paths
+// are relative to the configuration file. Times, dose rates and
+// concentrations accept units ("100 µs", "40 Gy/s", "1e-6 µM").
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SimulationConfig {
+    pub reactions: String,
+    pub beam: BeamConfig,
+    // Irradiation in several fractions, with `beam` unless they give one.
+    // The beam stays ON until the end time when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fractions: Vec<FractionConfig>,
+    pub solver: SolverConfig,
+    #[serde(deserialize_with = "units::seconds")]
+    pub end_time: f64,
+    #[serde(default)]
+    pub output: OutputConfig,
+    #[serde(default)]
+    pub analysis: AnalysisConfig,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum BeamConfig {
+    Constant {
+        #[serde(default = "default_particle")]
+        particle: String,
+        #[serde(deserialize_with = "units::gray_per_second")]
+        dose_rate: f64, // [Gy/s]
+    },
+    // `dose_rate` is the average over a period
+    Pulsed {
+        #[serde(default = "default_particle")]
+        particle: String,
+        #[serde(deserialize_with = "units::gray_per_second")]
+        dose_rate: f64, // [Gy/s]
+        #[serde(deserialize_with = "units::seconds")]
+        period: f64,
+        #[serde(deserialize_with = "units::seconds")]
+        on_time: f64,
+    },
+}
+
+pub(crate) fn default_particle() -> String { String::from("e") }
+
+impl BeamConfig {
+    pub fn build(&self) -> Result<Beam> {
+        match self {
+            BeamConfig::Constant { particle, dose_rate } =>
+                Beam::new_constant(particle.clone(), *dose_rate),
+            BeamConfig::Pulsed { particle, dose_rate, period, on_time } =>
+                Beam::new_pulsed(particle.clone(), *dose_rate, *period, *on_time),
+        }
+    }
+}
+
+// Either the duration or the dose [Gy] of the fraction is given
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct FractionConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub beam: Option<BeamConfig>,
+    #[serde(default, deserialize_with = "optional_seconds",
+            skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dose: Option<f64>,
+    #[serde(default, deserialize_with = "units::seconds")]
+    pub gap: f64,
+}
+
+fn optional_seconds<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where D: serde::Deserializer<'de> {
+    units::seconds(deserializer).map(Some)
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum SolverConfig {
+    Rk4 {
+        #[serde(deserialize_with = "units::seconds")]
+        step: f64,
+    },
+    // Adaptive steps, atol is a concentration. The integration fails after
+    // max_steps steps, accepted or rejected.
+    Dopri5 {
+        #[serde(default = "default_rtol")]
+        rtol: f64,
+        #[serde(default = "default_atol", deserialize_with = "units::molar")]
+        atol: f64, // [mol/l]
+        #[serde(default = "default_max_steps")]
+        max_steps: u32,
+    },
+    // Implicit adaptive steps, for runs much longer than the lifetime of
+    // the radicals (conventional dose rates)
+    Rosenbrock {
+        #[serde(default = "default_rtol")]
+        rtol: f64,
+        #[serde(default = "default_atol", deserialize_with = "units::molar")]
+        atol: f64, // [mol/l]
+        #[serde(default = "default_max_steps")]
+        max_steps: u32,
+    },
+}
+
+impl SolverConfig {
+    pub fn max_steps(&self) -> Option<u32> {
+        match self {
+            SolverConfig::Rk4 { .. } => None,
+            SolverConfig::Dopri5 { max_steps, .. }
+            | SolverConfig::Rosenbrock { max_steps, .. } => Some(*max_steps),
+        }
+    }
+}
+
+// Largest h . rate of a stable Dopri5 step on a first order loss
+const DOPRI5_STABILITY: f64 = 3.3;
+
+fn default_rtol() -> f64 { 1e-6 }
+fn default_atol() -> f64 { 1e-18 }
+fn default_max_steps() -> u32 { 100_000 }
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OutputConfig {
+    #[serde(default = "default_output")]
+    pub path: String,
+    // Time between two written states, every solver step if not given
+    #[serde(default, deserialize_with = "optional_seconds",
+            skip_serializing_if = "Option::is_none")]
+    pub sampling: Option<f64>,
+    #[serde(default)]
+    pub units: OutputUnits,
+    // Headers with display names ("O₂•⁻") instead of labels ("O2_r_minus")
+    #[serde(default)]
+    pub display_names: bool,
+}
+
+fn default_output() -> String { String::from("output/simulation.dat") }
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            path: default_output(),
+            sampling: None,
+            units: OutputUnits::default(),
+            display_names: false,
+        }
+    }
+}
+
+impl SimulationConfig {
+    pub fn from_file(path:&str) -> Result<Self, RadioBioError> {
+        let content = fs::read_to_string(path)
+            .map_err(|source| RadioBioError::FileAccess {
+                path: path.to_string(),
+                source,
+            })?;
+        ron::de::from_str(&content).map_err(|e| RadioBioError::Parse {
+            path: path.to_string(),
+            line: e.position.line,
+            column: e.position.col,
+            message: e.code.to_string(),
+        })
+    }
+
+    pub fn schedule(&self) -> Result<TreatmentSchedule> {
+        if self.fractions.is_empty() {
+            return Ok(TreatmentSchedule::single(self.beam.build()?));
+        }
+        let mut fractions = vec![];
+        for (idx, f) in self.fractions.iter().enumerate() {
+            let beam = f.beam.as_ref().unwrap_or(&self.beam).build()?;
+            let fraction = match (f.duration, f.dose) {
+                (Some(duration), None) => Fraction::new(beam, duration, f.gap),
+                (None, Some(dose)) => Fraction::new_from_dose(beam, dose, f.gap),
+                _ => anyhow::bail!("Fraction #{}: give either a duration or a dose", idx+1),
+            };
+            fractions.push(fraction.with_context(|| format!("Fraction #{}", idx+1))?);
+        }
+        TreatmentSchedule::new(fractions)
+    }
+
+    // Catch configuration errors before running
+    fn check(&self) -> Result<()> {
+        self.schedule()?;
+        if self.end_time <= 0.0 {
+            anyhow::bail!("The end time must be positive, got {} s", self.end_time);
+        }
+        Ok(())
+    }
+}
+
+// Simulation ready to run: the configuration and its parsed reactions
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    pub config: SimulationConfig,
+    pub env: Env,
+    // Directory of the configuration file
+    base_dir: PathBuf,
+}
+
+impl Simulation {
+    pub fn from_file(path:&str) -> Result<Self> {
+        let config = SimulationConfig::from_file(path)?;
+        let base_dir = Path::new(path).parent().unwrap_or(Path::new("")).to_path_buf();
+        Simulation::new(config, &base_dir)
+            .with_context(|| format!("While loading the simulation {path}"))
+    }
+
+    pub fn new(config:SimulationConfig, base_dir:&Path) -> Result<Self> {
+        let reactions = base_dir.join(&config.reactions);
+        let env = parse_reactions_file(&reactions.to_string_lossy())?;
+        config.check()?;
+        Ok(Self { config, env, base_dir: base_dir.to_path_buf() })
+    }
+
+    // Same simulation with another configuration or chemistry, the
+    // reactions file is not read again
+    pub fn modified(&self, config:SimulationConfig, env:Env) -> Result<Self> {
+        config.check()?;
+        Ok(Self { config, env, base_dir: self.base_dir.clone() })
+    }
+
+    pub fn reactions_path(&self) -> PathBuf {
+        self.base_dir.join(&self.config.reactions)
+    }
+    pub fn output_path(&self) -> PathBuf {
+        self.base_dir.join(&self.config.output.path)
+    }
+
+    pub fn solver(&self) -> Result<ODESolver> {
+        let mut sim = ODESolver::new_with_schedule(self.env.clone(), self.config.schedule()?);
+        sim.set_end_time(self.config.end_time);
+        Ok(sim)
+    }
+
+    // Rough number of steps needed by the solver, None when the steps are
+    // not limited by the fastest reactions (Rosenbrock)
+    pub fn expected_steps(&self) -> Result<Option<f64>> {
+        let end = self.config.end_time;
+        match self.config.solver {
+            SolverConfig::Rk4 { step } => Ok(Some(end / step)),
+            SolverConfig::Dopri5 { .. } => Ok(Some(end * self.stiffness()? / DOPRI5_STABILITY)),
+            SolverConfig::Rosenbrock { .. } => Ok(None),
+        }
+    }
+
+    // Fastest first order loss at the start, -∂f_i/∂y_i [1/s]
+    fn stiffness(&self) -> Result<f64> {
+        let sim = self.solver()?;
+        let y0 = sim.env().get_initial_values();
+        let mut f0 = State::zeros(y0.len());
+        sim.try_system(0.0, &y0, &mut f0)?;
+        let mut out: f64 = 0.0;
+        for idx in 0..y0.len() {
+            let mut y = y0.clone();
+            let delta = 1e-6 * y0[idx].max(1.0);
+            y[idx] += delta;
+            let mut f = State::zeros(y0.len());
+            sim.try_system(0.0, &y, &mut f)?;
+            out = out.max((f0[idx] - f[idx]) / delta);
+        }
+        Ok(out)
+    }
+
+    // Fails before running when the solver would need more steps than it
+    // is allowed
+    pub fn check_steps(&self) -> Result<()> {
+        let expected = self.expected_steps()?;
+        if let (Some(expected), Some(max_steps)) = (expected, self.config.solver.max_steps()) {
+            if expected > max_steps as f64 {
+                anyhow::bail!("About {expected:.0} steps are needed for {} s, more than \
+                               max_steps ({max_steps}): raise it or use the Rosenbrock solver",
+                              self.config.end_time);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn run(&self) -> Result<Trajectory> {
+        let sim = self.solver()?;
+        let y0 = sim.env().get_initial_values();
+        let labels = sim.env().species_label();
+        let schedule = self.config.schedule()?;
+        let end = self.config.end_time;
+        let (times, states, stats) = match self.config.solver {
+            SolverConfig::Rk4 { step } => {
+                let mut stepper = Rk4::new(sim, 0.0, y0, end, step);
+                let stats = stepper.integrate()?;
+                (stepper.x_out().clone(), stepper.y_out().clone(), stats)
+            },
+            SolverConfig::Dopri5 { rtol, atol, max_steps } => {
+                let mut stepper = Dopri5::new(sim, 0.0, end, 0.0, y0, rtol, to_state(atol));
+                stepper.set_max_steps(max_steps);
+                let stats = stepper.integrate()?;
+                (stepper.x_out().clone(), stepper.y_out().clone(), stats)
+            },
+            SolverConfig::Rosenbrock { rtol, atol, max_steps } => {
+                let mut stepper = Rosenbrock::new(sim, 0.0, end, 0.0, y0, rtol, to_state(atol));
+                stepper.set_max_steps(max_steps);
+                let stats = stepper.integrate()?;
+                (stepper.x_out().clone(), stepper.y_out().clone(), stats)
+            },
+        };
+        let fractions = schedule.label_times(&times);
+        Ok(Trajectory { labels, times, states, fractions, stats, slopes: None })
+    }
+
+    // Derivatives at both ends of every interval of the trajectory, for
+    // the dense integrals. Evaluated just inside the interval: the beam may
+    // switch at a sample.
+    pub fn add_slopes(&self, trajectory:&mut Trajectory) -> Result<()> {
+        let sim = self.solver()?;
+        let mut slopes = Slopes::new();
+        for (t, y) in trajectory.times.windows(2).zip(trajectory.states.windows(2)) {
+            let eps = 1e-9 * (t[1] - t[0]);
+            let mut start = State::zeros(y[0].len());
+            let mut end = State::zeros(y[1].len());
+            sim.try_system(t[0] + eps, &y[0], &mut start)?;
+            sim.try_system(t[1] - eps, &y[1], &mut end)?;
+            slopes.push((start, end));
+        }
+        trajectory.slopes = Some(slopes);
+        Ok(())
+    }
+
+    // Metrics of every species on every solver step of the trajectory
+    pub fn analyse(&self, trajectory:&Trajectory) -> Result<Vec<SpeciesMetrics>> {
+        let thresholds = &self.config.analysis.thresholds;
+        if self.config.analysis.dense_auc && trajectory.slopes.is_none() {
+            let mut dense = trajectory.clone();
+            self.add_slopes(&mut dense)?;
+            return analysis::analyse(&dense, thresholds);
+        }
+        analysis::analyse(trajectory, thresholds)
+    }
+
+    // The trajectory as asked in `output`: sampled, with the display names
+    // in the headers
+    pub fn write(&self, trajectory:&Trajectory, path:&Path) -> Result<()> {
+        let output = &self.config.output;
+        let mut written = match output.sampling {
+            Some(dt) => trajectory.sampled(dt),
+            None => trajectory.clone(),
+        };
+        if output.display_names {
+            written.labels = self.env.species_display_names();
+        }
+        written.write(path, output.units)
+    }
+}
+
+// Indices of the times at least dt apart, the last one always kept
+fn decimate(times:&[Time], dt:f64) -> Vec<usize> {
+    let mut out = vec![];
+    let mut next = f64::NEG_INFINITY;
+    for (idx, t) in times.iter().enumerate() {
+        if *t >= next || idx + 1 == times.len() {
+            out.push(idx);
+            next = t + dt * (1.0 - 1e-9);
+        }
+    }
+    out
+}
+
+// Result of a simulation on every solver step, states in the units of the
+// solver [µmol/l]
+#[derive(Debug, Clone)]
+pub struct Trajectory {
+    pub labels: Vec<String>,
+    pub times: Vec<Time>,
+    pub states: Vec<State>,
+    // Fraction delivered at each time (None during gaps)
+    pub fractions: Vec<Option<usize>>,
+    pub stats: Stats,
+    // See Simulation::add_slopes
+    pub slopes: Option<Slopes>,
+}
+
+impl Trajectory {
+    // Column of a species, or of the acid/base couple it belongs to
+    // ("H2O2" in "H2O2/HO2_minus")
+    pub fn species_index(&self, species:&str) -> Option<usize> {
+        self.labels.iter().position(|label| label == species)
+            .or_else(|| self.labels.iter()
+                            .position(|label| label.split('/').any(|sp| sp == species)))
+    }
+
+    // Values of one species over time [µmol/l]
+    pub fn series(&self, species:&str) -> Result<Vec<f64>, RadioBioError> {
+        let idx = self.species_index(species)
+            .ok_or_else(|| RadioBioError::UnknownSpecies(species.to_string()))?;
+        Ok(self.states.iter().map(|y| y[idx]).collect())
+    }
+
+    // States at least dt apart, see OutputConfig::sampling. The slopes are
+    // dropped.
+    pub fn sampled(&self, dt:f64) -> Trajectory {
+        let kept = decimate(&self.times, dt);
+        Trajectory {
+            labels: self.labels.clone(),
+            times: kept.iter().map(|&idx| self.times[idx]).collect(),
+            states: kept.iter().map(|&idx| self.states[idx].clone()).collect(),
+            fractions: kept.iter().map(|&idx| self.fractions[idx]).collect(),
+            stats: self.stats,
+            slopes: None,
+        }
+    }
+
+    // CSV with the units in the headers, parent directories are created.
+    // The last column is the fraction delivered at each time, counted from
+    // 1, or 0 between fractions.
+    pub fn write(&self, path:&Path, units:OutputUnits) -> Result<()> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Cannot create {}", dir.display()))?;
+        }
+        let file = File::create(path)
+            .with_context(|| format!("Cannot create {}", path.display()))?;
+        let mut buf = BufWriter::new(file);
+        write!(buf, "{}", units.time_header())?;
+        for label in self.labels.iter() {
+            write!(buf, ", {}", units.species_header(label))?;
+        }
+        writeln!(buf, ", fraction")?;
+        for ((t, state), fraction) in self.times.iter().zip(self.states.iter())
+                                                .zip(self.fractions.iter()) {
+            write!(buf, "{}", units.time.from_seconds(*t))?;
+            for value in state.iter() {
+                write!(buf, ", {}", units.concentration.from_state(*value))?;
+            }
+            writeln!(buf, ", {}", fraction.map(|f| f + 1).unwrap_or(0))?;
+        }
+        buf.flush()?;
+        Ok(())
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   TESTING                                  */
+/* -------------------------------------------------------------------------- */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simulations_are_loaded_and_run() {
+        let path = format!("{}/data/simulation.ron", env!("CARGO_MANIFEST_DIR"));
+        let sim = Simulation::from_file(&path).unwrap();
+        assert!(matches!(sim.config.solver, SolverConfig::Dopri5 { .. }));
+        // The sample needs 166 338 steps
+        let expected = sim.expected_steps().unwrap().unwrap();
+        assert!((1.5e5..2e5).contains(&expected), "{expected}");
+        sim.check_steps().unwrap();
+        assert_float_relative_eq!(sim.config.end_time, 1e-4, 1e-12);
+
+        let mut config = sim.config.clone();
+        config.beam = BeamConfig::Pulsed {
+            particle: default_particle(), dose_rate: 40.0, period: 1e-5, on_time: 1e-6,
+        };
+        config.fractions = vec![
+            FractionConfig { duration: Some(2e-5), gap: 1e-5, ..Default::default() },
+            FractionConfig { dose: Some(1e-3), ..Default::default() },
+        ];
+        config.output.sampling = Some(1e-5);
+        config.solver = SolverConfig::Dopri5 {
+            rtol: default_rtol(), atol: default_atol(), max_steps: default_max_steps(),
+        };
+        let sim = Simulation::new(config.clone(), &sim.base_dir).unwrap();
+        let schedule = sim.config.schedule().unwrap();
+        assert_float_relative_eq!(schedule.total_dose(), 1.8e-3, 1e-12);
+        let trajectory = sim.run().unwrap();
+        assert!(trajectory.states.iter().flatten().all(|x| x.is_finite() && *x >= 0.0));
+        // Every solver step is kept, sampled when written
+        let sampled = trajectory.sampled(1e-5);
+        assert!(trajectory.times.len() > 11 && sampled.times.len() <= 11);
+        assert!(sampled.times.windows(3).all(|t| t[1] - t[0] >= 1e-5 * (1.0 - 1e-9)));
+        assert_eq!(sampled.times.last(), trajectory.times.last());
+        // 0 - 20 µs: fraction 1, 20 - 30 µs: gap, 30 - 55 µs: fraction 2
+        let fraction = |t:f64| match t {
+            t if t < 2e-5 => Some(0),
+            t if t < 3e-5 => None,
+            t if t < 5.5e-5 => Some(1),
+            _ => None,
+        };
+        assert!(sampled.times.iter().zip(sampled.fractions.iter())
+                       .all(|(t, f)| fraction(*t) == *f));
+        let mut output = sim.modified(sim.config.clone(), sim.env.clone()).unwrap();
+        output.config.output.display_names = true;
+        let path = crate::test_dir("simulation").join("trajectory.dat");
+        output.write(&trajectory, &path).unwrap();
+        let csv = fs::read_to_string(&path).unwrap();
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows.len(), sampled.times.len() + 1);
+        assert!(rows[0].contains("OH•") && rows[0].ends_with(", fraction"));
+        assert!(rows[1].ends_with(", 1") && rows[5].ends_with(", 2"));
+        assert_eq!(trajectory.labels, vec!["e_aq", "OH_r", "H2"]);
+
+        // Metrics on every solver step, with the threshold of the file
+        let metrics = sim.analyse(&trajectory).unwrap();
+        assert_eq!(metrics.iter().map(|m| m.species.as_str()).collect::<Vec<_>>(),
+                   vec!["e_aq", "OH_r", "H2"]);
+        let h2 = &metrics[2];
+        assert!(h2.net > 0.0 && h2.auc > 0.0 && h2.crossing.unwrap() < sim.config.end_time);
+        assert_float_relative_eq!(h2.threshold.unwrap(), 1e-5, 1e-12);
+
+        // Default tolerances are tight enough for the e_aq recombination
+        config.solver = SolverConfig::Dopri5 { rtol: 1e-9, atol: 1e-22, max_steps: 1_000_000 };
+        let reference = Simulation::new(config.clone(), &sim.base_dir).unwrap().run().unwrap();
+        let (last, last_ref) = (trajectory.states.last().unwrap(), reference.states.last().unwrap());
+        for (a, b) in last.iter().zip(last_ref.iter()) {
+            assert_float_absolute_eq!(*a, *b, 1e-3 * b.abs());
+        }
+        config.solver = SolverConfig::Rk4 { step: 1e-8 };
+        let rk4 = Simulation::new(config, &sim.base_dir).unwrap().run().unwrap();
+        assert_eq!(rk4.times.len(), 10_001);
+        assert_eq!(rk4.sampled(1e-5).times.len(), 11);
+
+        let ron = r#"(reactions: "reactions_simple.ron", beam: Constant(dose_rate: "2 Gy/min"),
+                      fractions: [(gap: "1 ms")], solver: Rk4(step: "1 ns"), end_time: "1 ms")"#;
+        let config: SimulationConfig = ron::de::from_str(ron).unwrap();
+        assert!(config.schedule().is_err());
+    }
+}
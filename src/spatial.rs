@@ -0,0 +1,422 @@
+//! 1D reaction-diffusion solver built by the method of lines.
+//!
+//! The domain (planar tissue slab or Krogh cylinder) is cut into `n_cells`
+//! finite volumes. Every cell runs the full reaction network of the
+//! wrapped [`ODESolver`] and neighbouring cells exchange matter through
+//! Fick's law with per-species diffusion coefficients.
+//!
+//! The state vector is cell-major: `y[cell * dim + species]`.
+//!
+//! Units: lengths in [m], diffusion coefficients in [m²/s]. Concentrations
+//! share the units of the state vector of the wrapped [`ODESolver`].
+//! Being explicit, [`crate::ode_solver::rk4::Rk4`] is only stable for steps
+//! below `dx² / (2 D_max)`.
+
+/* ---------------------------- External imports ---------------------------- */
+use std::collections::HashMap;
+use anyhow::{Result, bail, Context};
+
+/* ---------------------------- Internal imports ---------------------------- */
+use crate::ode_solver::traits::System;
+use crate::reactions::errors::RadioBioError;
+use crate::{ODESolver, State, Time};
+use crate::units::{to_molar, to_state, Quantity};
+
+/* -------------------------------------------------------------------------- */
+/*                         FUNCTION/STRUCT DEFINITIONS                        */
+/* -------------------------------------------------------------------------- */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Geometry {
+    // Slab of tissue, x in [inner, outer]
+    Planar,
+    // Krogh cylinder around a capillary, r in [inner, outer]
+    Radial,
+}
+
+#[derive(Debug, Clone)]
+pub enum Boundary {
+    NoFlux,
+    // Species clamped at the boundary value, in mol/l without unit ("1e-5",
+    // "20 µM"). O2 also accepts a partial pressure, e.g. the capillary pO2
+    // ("40 mmHg"). Species that are not listed see a no-flux condition.
+    Fixed(HashMap<String, Quantity>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Domain {
+    geometry: Geometry,
+    inner: f64,
+    outer: f64,
+    n_cells: usize,
+}
+
+impl Domain {
+    pub fn new(geometry:Geometry, inner:f64, outer:f64, n_cells:usize)
+    -> Result<Self> {
+        if n_cells == 0 {
+            bail!("A spatial domain needs at least one cell");
+        }
+        if inner < 0.0 || outer <= inner {
+            bail!("Invalid domain bounds: inner ({}) must be >= 0 and < outer ({})",
+                  inner,
+                  outer
+            );
+        }
+        Ok(Self { geometry, inner, outer, n_cells })
+    }
+    pub fn new_planar(thickness:f64, n_cells:usize) -> Result<Self> {
+        Domain::new(Geometry::Planar, 0.0, thickness, n_cells)
+    }
+    pub fn new_krogh(capillary_radius:f64, tissue_radius:f64, n_cells:usize)
+    -> Result<Self> {
+        Domain::new(Geometry::Radial, capillary_radius, tissue_radius, n_cells)
+    }
+
+    pub fn geometry(&self) -> Geometry { self.geometry }
+    pub fn n_cells(&self) -> usize { self.n_cells }
+    pub fn cell_width(&self) -> f64 {
+        (self.outer - self.inner) / self.n_cells as f64
+    }
+    pub fn face_position(&self, face:usize) -> f64 {
+        self.inner + face as f64 * self.cell_width()
+    }
+    pub fn cell_centers(&self) -> Vec<f64> {
+        (0..self.n_cells)
+            .map(|i| self.inner + (i as f64 + 0.5) * self.cell_width())
+            .collect()
+    }
+    // Face areas per unit depth (planar) or per unit length and radian
+    // (radial). There are n_cells + 1 faces.
+    pub fn face_areas(&self) -> Vec<f64> {
+        (0..=self.n_cells)
+            .map(|i| match self.geometry {
+                Geometry::Planar => 1.0,
+                Geometry::Radial => self.face_position(i),
+            })
+            .collect()
+    }
+    // Cell volumes consistent with face_areas()
+    pub fn cell_volumes(&self) -> Vec<f64> {
+        (0..self.n_cells)
+            .map(|i| {
+                let (r0, r1) = (self.face_position(i), self.face_position(i+1));
+                match self.geometry {
+                    Geometry::Planar => r1 - r0,
+                    Geometry::Radial => 0.5 * (r1*r1 - r0*r0),
+                }
+            })
+            .collect()
+    }
+}
+
+pub struct SpatialSolver {
+    pub chem: ODESolver,
+    pub domain: Domain,
+    // Diffusion coefficient of each tracked species (0 => immobile)
+    diffusion: Vec<f64>,
+    // (species index, boundary value) for both ends of the domain
+    inner_fixed: Vec<(usize, f64)>,
+    outer_fixed: Vec<(usize, f64)>,
+    areas: Vec<f64>,
+    volumes: Vec<f64>,
+}
+
+impl SpatialSolver {
+    pub fn new(
+        chem: ODESolver,
+        domain: Domain,
+        diffusion: &HashMap<String, f64>,
+        inner: Boundary,
+        outer: Boundary,
+    ) -> Result<Self> {
+        let mut coefs = vec![0_f64; chem.dimension()];
+        for (sp, d) in diffusion.iter() {
+            if *d < 0.0 {
+                bail!("Negative diffusion coefficient ({}) for species {}", d, sp);
+            }
+            coefs[tracked_index(&chem, sp)?] = *d;
+        }
+        let inner_fixed = resolve_boundary(&chem, &inner)?;
+        let outer_fixed = resolve_boundary(&chem, &outer)?;
+        // A clamped species that does not diffuse never sees its boundary
+        for (sp, _) in inner_fixed.iter().chain(outer_fixed.iter()) {
+            if coefs[*sp] == 0.0 {
+                bail!("Species {} is fixed at a boundary but has no diffusion coefficient",
+                      chem.env().species_label()[*sp]);
+            }
+        }
+        Ok(Self {
+            areas: domain.face_areas(),
+            volumes: domain.cell_volumes(),
+            chem,
+            domain,
+            diffusion: coefs,
+            inner_fixed,
+            outer_fixed,
+        })
+    }
+
+    // Number of chemical species per cell
+    pub fn species_per_cell(&self) -> usize { self.chem.dimension() }
+    pub fn dimension(&self) -> usize {
+        self.domain.n_cells() * self.species_per_cell()
+    }
+
+    // Same initial concentrations in every cell
+    pub fn get_initial_values(&self) -> State {
+        let y_cell = self.chem.env().get_initial_values();
+        let dim = self.species_per_cell();
+        State::from_fn(self.dimension(), |i, _| y_cell[i % dim])
+    }
+
+    // Labels as "species[cell]", following the state vector layout
+    pub fn species_label(&self) -> Vec<String> {
+        let labels = self.chem.env().species_label();
+        let mut out = vec![];
+        for cell in 0..self.domain.n_cells() {
+            for sp in labels.iter() {
+                out.push(format!("{sp}[{cell}]"));
+            }
+        }
+        return out;
+    }
+
+    // Concentration of one species in every cell [mol/l]
+    pub fn profile(&self, y:&State, species:&str) -> Result<Vec<f64>> {
+        let idx = tracked_index(&self.chem, species)?;
+        let dim = self.species_per_cell();
+        Ok((0..self.domain.n_cells()).map(|c| to_molar(y[c*dim + idx])).collect())
+    }
+
+    // Volume fraction of the domain where the species is below threshold
+    // [mol/l]
+    pub fn hypoxic_fraction(&self, y:&State, species:&str, threshold:f64)
+    -> Result<f64> {
+        let profile = self.profile(y, species)?;
+        let total: f64 = self.volumes.iter().sum();
+        let hypoxic: f64 = profile.iter()
+            .zip(self.volumes.iter())
+            .filter(|(cc, _)| **cc < threshold)
+            .map(|(_, vol)| vol)
+            .sum();
+        Ok(hypoxic / total)
+    }
+
+    fn add_diffusion(&self, y:&State, dy:&mut State) {
+        let dim = self.species_per_cell();
+        let n = self.domain.n_cells();
+        let dx = self.domain.cell_width();
+
+        for (sp, d) in self.diffusion.iter().enumerate() {
+            if *d == 0.0 { continue; }
+            // Internal faces between cell i-1 and cell i
+            for i in 1..n {
+                let flux = d * self.areas[i]
+                    * (y[(i-1)*dim + sp] - y[i*dim + sp]) / dx;
+                dy[(i-1)*dim + sp] -= flux / self.volumes[i-1];
+                dy[i*dim + sp] += flux / self.volumes[i];
+            }
+        }
+        // Dirichlet faces: half a cell between boundary and first center
+        for (sp, value) in self.inner_fixed.iter() {
+            let flux = self.diffusion[*sp] * self.areas[0]
+                * (value - y[*sp]) / (0.5*dx);
+            dy[*sp] += flux / self.volumes[0];
+        }
+        for (sp, value) in self.outer_fixed.iter() {
+            let last = (n-1)*dim + sp;
+            let flux = self.diffusion[*sp] * self.areas[n]
+                * (value - y[last]) / (0.5*dx);
+            dy[last] += flux / self.volumes[n-1];
+        }
+    }
+}
+
+impl System<State> for SpatialSolver {
+    fn system(&self, t: Time, y: &State, dy: &mut State) {
+        self.try_system(t, y, dy)
+            .expect("Oupsy, something went wrong in the spatial system");
+    }
+
+    fn try_system(&self, t: Time, y: &State, dy: &mut State) -> Result<()> {
+        let dim = self.species_per_cell();
+        let mut y_cell = State::zeros(dim);
+        let mut dy_cell = State::zeros(dim);
+
+        // Reaction part, cell by cell
+        for cell in 0..self.domain.n_cells() {
+            y_cell.copy_from(&y.rows(cell*dim, dim));
+            dy_cell.fill(0_f64);
+            self.chem
+                .try_system(t, &y_cell, &mut dy_cell)
+                .with_context(|| format!("In cell {cell}"))?;
+            dy.rows_mut(cell*dim, dim).copy_from(&dy_cell);
+        }
+        // Transport part
+        self.add_diffusion(y, dy);
+        Ok(())
+    }
+
+    fn breakpoints(&self) -> Vec<f64> {
+        self.chem.breakpoints()
+    }
+
+    fn apply_event(&self, t: Time, y: &mut State) {
+        let dim = self.species_per_cell();
+        let mut y_cell = State::zeros(dim);
+        for cell in 0..self.domain.n_cells() {
+            y_cell.copy_from(&y.rows(cell*dim, dim));
+            self.chem.apply_event(t, &mut y_cell);
+            y.rows_mut(cell*dim, dim).copy_from(&y_cell);
+        }
+    }
+}
+
+fn tracked_index(chem:&ODESolver, species:&str) -> Result<usize> {
+    let label = chem.env().registry.resolve(species);
+    match chem.env().map_all_species().get(&label) {
+        Some(idx) if *idx < chem.dimension() => Ok(*idx),
+        Some(_) => bail!("Species {} is not tracked and cannot diffuse", species),
+        None => bail!(RadioBioError::UnknownSpecies(species.to_string())),
+    }
+}
+
+fn resolve_boundary(chem:&ODESolver, bc:&Boundary) -> Result<Vec<(usize, f64)>> {
+    match bc {
+        Boundary::NoFlux => Ok(vec![]),
+        Boundary::Fixed(values) => values.iter()
+            .map(|(sp, value)| {
+                let molar = value.concentration(&chem.env().registry.resolve(sp))?;
+                Ok((tracked_index(chem, sp)?, to_state(molar)))
+            })
+            .collect(),
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   TESTING                                  */
+/* -------------------------------------------------------------------------- */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Beam;
+    use crate::ode_solver::rk4::Rk4;
+    use crate::physics::gas::{HENRY_O2, MMHG_PER_ATM};
+    use crate::reactions::parse_reactions_file;
+
+    fn simple_chem() -> ODESolver {
+        let file = format!("{}/data/reactions_simple.ron", env!("CARGO_MANIFEST_DIR"));
+        let env = parse_reactions_file(&file).unwrap();
+        ODESolver::new(env, Beam::new_constant(String::from("e"), 0.0).unwrap())
+    }
+
+    // H2 stands for O2, which the simple mechanism does not track
+    fn h2_solver(domain:Domain, inner:f64, outer:Boundary) -> SpatialSolver {
+        let diffusion = HashMap::from([(String::from("H2"), 2e-9)]);
+        let inner = Boundary::Fixed(HashMap::from([(String::from("H2"), Quantity::new(inner))]));
+        SpatialSolver::new(simple_chem(), domain, &diffusion, inner, outer).unwrap()
+    }
+
+    // H2 profile after `duration` seconds, starting from an empty domain [mol/l]
+    fn h2_profile(solver:SpatialSolver, duration:f64, dt:f64) -> Vec<f64> {
+        let y0 = State::zeros(solver.dimension());
+        let mut rk4 = Rk4::new(solver, 0.0, y0, duration, dt);
+        rk4.integrate().unwrap();
+        rk4.system().profile(rk4.y_out().last().unwrap(), "H2").unwrap()
+    }
+
+    #[test]
+    fn no_flux_diffusion_conserves_matter() {
+        let chem = simple_chem();
+        let domain = Domain::new_krogh(5e-6, 100e-6, 20).unwrap();
+        let diffusion = HashMap::from([(String::from("H2"), 2e-9)]);
+        let solver = SpatialSolver::new(
+            chem, domain, &diffusion, Boundary::NoFlux, Boundary::NoFlux
+        ).unwrap();
+
+        let dim = solver.species_per_cell();
+        let idx = tracked_index(&solver.chem, "H2").unwrap();
+        let mut y = State::zeros(solver.dimension());
+        for cell in 0..solver.domain.n_cells() {
+            y[cell*dim + idx] = (cell as f64).powi(2);
+        }
+        let mut dy = State::zeros(solver.dimension());
+        solver.system(0.0, &y, &mut dy);
+
+        let total: f64 = solver.volumes.iter().enumerate()
+            .map(|(c, vol)| vol * dy[c*dim + idx])
+            .sum();
+        assert_float_absolute_eq!(total, 0.0, 1e-12);
+        // Matter flows from the outer (rich) cells to the inner ones
+        assert!(dy[idx] > 0.0);
+    }
+
+    #[test]
+    fn capillary_boundary_gives_the_krogh_profile() {
+        // Fixed at the capillary wall and at the tissue radius: without
+        // reactions the steady profile is c0 + (c1 - c0) ln(r/r0) / ln(R/r0)
+        let (r0, r1, c0, c1) = (10e-6, 60e-6, 100e-6, 20e-6);
+        let domain = Domain::new_krogh(r0, r1, 20).unwrap();
+        let centers = domain.cell_centers();
+        let outer = Boundary::Fixed(HashMap::from([(String::from("H2"), Quantity::new(c1))]));
+        let profile = h2_profile(h2_solver(domain, c0, outer), 5.0, 1e-3);
+        for (r, cc) in centers.iter().zip(profile.iter()) {
+            let expected = c0 + (c1 - c0) * (r / r0).ln() / (r1 / r0).ln();
+            assert_float_relative_eq!(*cc, expected, 1e-2);
+        }
+    }
+
+    #[test]
+    fn planar_boundaries_give_linear_and_flat_profiles() {
+        let (length, c0) = (100e-6, 50e-6);
+        // Fixed on one side only: the slab fills up to the boundary value
+        let domain = Domain::new_planar(length, 10).unwrap();
+        let profile = h2_profile(h2_solver(domain, c0, Boundary::NoFlux), 30.0, 1e-2);
+        for cc in profile.iter() {
+            assert_float_relative_eq!(*cc, c0, 1e-3);
+        }
+        // Fixed on both sides: linear between the boundary values
+        let domain = Domain::new_planar(length, 10).unwrap();
+        let centers = domain.cell_centers();
+        let outer = Boundary::Fixed(HashMap::from([(String::from("H2"), Quantity::new(0.0))]));
+        let profile = h2_profile(h2_solver(domain, c0, outer), 30.0, 1e-2);
+        for (x, cc) in centers.iter().zip(profile.iter()) {
+            assert_float_absolute_eq!(*cc, c0 * (1.0 - x / length), 1e-9);
+        }
+    }
+
+    #[test]
+    fn fixed_species_must_diffuse() {
+        let domain = Domain::new_planar(100e-6, 10).unwrap();
+        let inner = Boundary::Fixed(HashMap::from([(String::from("H2"), Quantity::new(1.0))]));
+        let res = SpatialSolver::new(
+            simple_chem(), domain, &HashMap::new(), inner, Boundary::NoFlux
+        );
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn capillary_po2_is_converted() {
+        let file = format!("{}/data/reactions.ron", env!("CARGO_MANIFEST_DIR"));
+        let env = parse_reactions_file(&file).unwrap();
+        let chem = ODESolver::new(env, Beam::new_constant(String::from("e"), 0.0).unwrap());
+        let domain = Domain::new_krogh(5e-6, 100e-6, 20).unwrap();
+        let diffusion = HashMap::from([(String::from("O2"), 2e-9), (String::from("H2"), 5e-9)]);
+        let boundary = |sp:&str, value:&str| Boundary::Fixed(HashMap::from([
+            (String::from(sp), Quantity::parse(value).unwrap())
+        ]));
+        let solver = SpatialSolver::new(chem, domain.clone(), &diffusion,
+                                        boundary("O2", "40 mmHg"), boundary("H2", "20 µM"))
+            .unwrap();
+        let cc_o2 = 40.0 / MMHG_PER_ATM * HENRY_O2;
+        assert_float_relative_eq!(solver.inner_fixed[0].1, to_state(cc_o2), 1e-12);
+        assert_float_relative_eq!(solver.outer_fixed[0].1, 20.0, 1e-12);
+
+        // Only O2 has a known solubility
+        let chem = solver.chem;
+        let res = SpatialSolver::new(chem, domain, &diffusion,
+                                     boundary("H2", "40 mmHg"), Boundary::NoFlux);
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,418 @@
+/* ---------------------------- External imports ---------------------------- */
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use anyhow::{Context, Result, bail};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+/* ---------------------------- Internal imports ---------------------------- */
+use crate::analysis::Metric;
+use crate::reactions::ChemistryParameter;
+use crate::reactions::errors::RadioBioError;
+use crate::simulation::{BeamConfig, FractionConfig, Simulation, SimulationConfig};
+use crate::units::{OutputUnits, Quantity};
+
+/* -------------------------------------------------------------------------- */
+/*                         FUNCTION/STRUCT DEFINITIONS                        */
+/* -------------------------------------------------------------------------- */
+// Grid of parameters over a simulation, e.g. data/sweep.ron. Every
+// combination of the values is run and the metrics gathered in a table.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SweepConfig {
+    // Base simulation, relative to the sweep file
+    pub simulation: String,
+    pub parameters: Vec<SweepAxis>,
+    pub metrics: Vec<Metric>,
+    // Parallel runs, all the cores when 0
+    #[serde(default)]
+    pub threads: usize,
+    #[serde(default = "default_output")]
+    pub output: String,
+}
+
+fn default_output() -> String { String::from("output/sweep.csv") }
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SweepAxis {
+    pub parameter: Parameter,
+    // Units as in the simulation files ("40 Gy/s", "2 µs", "40 mmHg")
+    pub values: Vec<Quantity>,
+}
+
+// Beam parameters apply to every beam of the simulation. The end time is
+// never changed: the irradiation must fit in it.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum Parameter {
+    DoseRate,
+    Period,
+    OnTime,
+    // Shared between the fractions in proportion to their dose
+    TotalDose,
+    KValue(String),
+    GValue(String),
+    InitialConcentration(String),
+}
+
+impl Parameter {
+    pub fn name(&self) -> String {
+        match self {
+            Parameter::DoseRate => String::from("dose_rate"),
+            Parameter::Period => String::from("period"),
+            Parameter::OnTime => String::from("on_time"),
+            Parameter::TotalDose => String::from("total_dose"),
+            Parameter::KValue(reaction) => format!("k({reaction})"),
+            Parameter::GValue(sp) => format!("G({sp})"),
+            Parameter::InitialConcentration(sp) => format!("{sp}(0)"),
+        }
+    }
+
+    pub fn header(&self, units:OutputUnits) -> String {
+        match self {
+            Parameter::DoseRate => format!("{} [Gy/s]", self.name()),
+            Parameter::Period | Parameter::OnTime =>
+                format!("{} [{}]", self.name(), units.time.symbol()),
+            Parameter::TotalDose => format!("{} [Gy]", self.name()),
+            Parameter::InitialConcentration(_) =>
+                format!("{} [{}]", self.name(), units.concentration.symbol()),
+            Parameter::KValue(_) | Parameter::GValue(_) => self.name(),
+        }
+    }
+
+    // [Gy/s], [s], [Gy], [mol/l] or as given for k and G-values
+    pub fn resolve(&self, value:&Quantity) -> Result<f64, RadioBioError> {
+        match self {
+            Parameter::DoseRate => value.dose_rate(),
+            Parameter::Period | Parameter::OnTime => value.time(),
+            Parameter::TotalDose => value.dose(),
+            Parameter::InitialConcentration(sp) => value.concentration(sp),
+            Parameter::KValue(_) | Parameter::GValue(_) => match value.unit.is_empty() {
+                true => Ok(value.value),
+                false => Err(RadioBioError::InvalidQuantity(format!(
+                    "{} takes a number without unit, got '{value}'", self.name()))),
+            },
+        }
+    }
+
+    // From the resolved value to the output units
+    pub fn convert(&self, value:f64, units:OutputUnits) -> f64 {
+        match self {
+            Parameter::Period | Parameter::OnTime => units.time.from_seconds(value),
+            Parameter::InitialConcentration(_) => units.concentration.from_molar(value),
+            _ => value,
+        }
+    }
+
+    fn chemistry(&self) -> Option<ChemistryParameter> {
+        match self {
+            Parameter::KValue(r) => Some(ChemistryParameter::KValue(r.clone())),
+            Parameter::GValue(sp) => Some(ChemistryParameter::GValue(sp.clone())),
+            Parameter::InitialConcentration(sp) =>
+                Some(ChemistryParameter::InitialConcentration(sp.clone())),
+            _ => None,
+        }
+    }
+
+    fn set_beam(&self, beam:&mut BeamConfig, value:f64) -> Result<()> {
+        match (self, beam) {
+            (Parameter::DoseRate, BeamConfig::Constant { dose_rate, .. })
+            | (Parameter::DoseRate, BeamConfig::Pulsed { dose_rate, .. }) => *dose_rate = value,
+            (Parameter::Period, BeamConfig::Pulsed { period, .. }) => *period = value,
+            (Parameter::OnTime, BeamConfig::Pulsed { on_time, .. }) => *on_time = value,
+            (_, BeamConfig::Constant { .. }) =>
+                bail!("{} only applies to Pulsed beams", self.name()),
+            _ => unreachable!("only beam parameters are set on beams"),
+        }
+        Ok(())
+    }
+}
+
+// Configuration of one point of the grid, the chemistry is changed apart
+fn configure(config:&mut SimulationConfig, parameter:&Parameter, value:f64) -> Result<()> {
+    match parameter {
+        Parameter::DoseRate | Parameter::Period | Parameter::OnTime => {
+            parameter.set_beam(&mut config.beam, value)?;
+            for fraction in config.fractions.iter_mut() {
+                if let Some(beam) = fraction.beam.as_mut() {
+                    parameter.set_beam(beam, value)?;
+                }
+            }
+        },
+        Parameter::TotalDose => {
+            if config.fractions.is_empty() {
+                config.fractions.push(FractionConfig { dose: Some(value), ..Default::default() });
+            } else {
+                let schedule = config.schedule()?;
+                let factor = value / schedule.total_dose();
+                for (fraction, planned) in config.fractions.iter_mut()
+                                                 .zip(schedule.iter_fractions()) {
+                    fraction.dose = Some(planned.dose() * factor);
+                    fraction.duration = None;
+                }
+            }
+            let duration = config.schedule()?.total_duration();
+            if duration > config.end_time {
+                bail!("{value} Gy takes {duration} s, longer than the end time ({} s)",
+                      config.end_time);
+            }
+        },
+        _ => {},
+    }
+    Ok(())
+}
+
+// Sweep ready to run: its configuration and the base simulation
+#[derive(Debug, Clone)]
+pub struct Sweep {
+    pub config: SweepConfig,
+    pub base: Simulation,
+    // Directory of the sweep file
+    base_dir: PathBuf,
+}
+
+impl Sweep {
+    pub fn from_file(path:&str) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|source| RadioBioError::FileAccess {
+                path: path.to_string(),
+                source,
+            })?;
+        let config: SweepConfig = ron::de::from_str(&content)
+            .map_err(|e| RadioBioError::Parse {
+                path: path.to_string(),
+                line: e.position.line,
+                column: e.position.col,
+                message: e.code.to_string(),
+            })?;
+        let base_dir = Path::new(path).parent().unwrap_or(Path::new(""));
+        Sweep::new(config, base_dir)
+            .with_context(|| format!("While loading the sweep {path}"))
+    }
+
+    pub fn new(config:SweepConfig, base_dir:&Path) -> Result<Self> {
+        let simulation = base_dir.join(&config.simulation);
+        let base = Simulation::from_file(&simulation.to_string_lossy())?;
+        if config.parameters.iter().any(|axis| axis.values.is_empty()) {
+            bail!("Every swept parameter needs at least one value");
+        }
+        if config.metrics.is_empty() {
+            bail!("No metric to compute");
+        }
+        for axis in config.parameters.iter() {
+            for value in axis.values.iter() {
+                axis.parameter.resolve(value)
+                    .with_context(|| format!("Values of {}", axis.parameter.name()))?;
+            }
+        }
+        Ok(Self { config, base, base_dir: base_dir.to_path_buf() })
+    }
+
+    pub fn output_path(&self) -> PathBuf {
+        self.base_dir.join(&self.config.output)
+    }
+
+    // Every combination of the resolved values, the last parameter
+    // changing fastest
+    pub fn points(&self) -> Result<Vec<Vec<f64>>> {
+        let mut axes = vec![];
+        for axis in self.config.parameters.iter() {
+            let values = axis.values.iter()
+                .map(|value| axis.parameter.resolve(value))
+                .collect::<Result<Vec<f64>, _>>()?;
+            axes.push(values);
+        }
+        Ok(axes.into_iter().multi_cartesian_product().collect())
+    }
+
+    // Simulation at one point of the grid
+    pub fn simulation_at(&self, point:&[f64]) -> Result<Simulation> {
+        let mut config = self.base.config.clone();
+        let changes: Vec<(ChemistryParameter, f64)> = self.config.parameters.iter()
+            .zip(point.iter())
+            .filter_map(|(axis, value)| axis.parameter.chemistry().map(|p| (p, *value)))
+            .collect();
+        // The total dose depends on the beam, set last
+        let (dose, beam): (Vec<_>, Vec<_>) = self.config.parameters.iter()
+            .zip(point.iter())
+            .filter(|(axis, _)| axis.parameter.chemistry().is_none())
+            .partition(|(axis, _)| axis.parameter == Parameter::TotalDose);
+        for (axis, value) in beam.into_iter().chain(dose) {
+            configure(&mut config, &axis.parameter, *value)?;
+        }
+        let env = match changes.is_empty() {
+            true => self.base.env.clone(),
+            false => self.base.env.with_changes(&changes)?,
+        };
+        self.base.modified(config, env)
+    }
+
+    fn run_point(&self, point:&[f64]) -> Result<Vec<f64>> {
+        let simulation = self.simulation_at(point)?;
+        let mut trajectory = simulation.run()?;
+        if self.config.metrics.iter().any(|metric| metric.is_dense()) {
+            simulation.add_slopes(&mut trajectory)?;
+        }
+        self.config.metrics.iter()
+            .map(|metric| metric.evaluate(&trajectory))
+            .collect()
+    }
+
+    pub fn threads(&self, runs:usize) -> usize {
+        let threads = match self.config.threads {
+            0 => thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            n => n,
+        };
+        threads.min(runs).max(1)
+    }
+
+    // Runs spread on local threads, a failed run does not stop the others
+    pub fn run(&self) -> Result<SweepTable> {
+        let points = self.points()?;
+        let next = AtomicUsize::new(0);
+        let outcomes: Mutex<Vec<Option<Outcome>>> =
+            Mutex::new(points.iter().map(|_| None).collect());
+        thread::scope(|scope| {
+            for _ in 0..self.threads(points.len()) {
+                scope.spawn(|| loop {
+                    let idx = next.fetch_add(1, Ordering::Relaxed);
+                    if idx >= points.len() {
+                        break;
+                    }
+                    let outcome = self.run_point(&points[idx]).map_err(|e| format!("{:#}", e));
+                    outcomes.lock().unwrap()[idx] = Some(outcome);
+                });
+            }
+        });
+
+        let units = self.base.config.output.units;
+        let outcomes = outcomes.into_inner().unwrap();
+        let rows = points.iter().zip(outcomes).map(|(point, outcome)| SweepRow {
+            parameters: point.clone(),
+            metrics: outcome.unwrap_or_else(|| Err(String::from("not run"))),
+        }).collect();
+        Ok(SweepTable {
+            parameters: self.config.parameters.iter().map(|a| a.parameter.clone()).collect(),
+            metrics: self.config.metrics.clone(),
+            units,
+            rows,
+        })
+    }
+}
+
+// Metrics of every run, values in the units of the solver
+#[derive(Debug, Clone)]
+pub struct SweepTable {
+    pub parameters: Vec<Parameter>,
+    pub metrics: Vec<Metric>,
+    pub units: OutputUnits,
+    pub rows: Vec<SweepRow>,
+}
+
+// Metrics of a run, or why it failed
+pub type Outcome = Result<Vec<f64>, String>;
+
+#[derive(Debug, Clone)]
+pub struct SweepRow {
+    pub parameters: Vec<f64>,
+    pub metrics: Outcome,
+}
+
+impl SweepTable {
+    pub fn iter_failures(&self) -> impl Iterator<Item=(usize, &String)> {
+        self.rows.iter().enumerate()
+            .filter_map(|(idx, row)| row.metrics.as_ref().err().map(|e| (idx, e)))
+    }
+
+    // "dose_rate = 40 Gy/s, G(e_aq) = 2.8"
+    pub fn describe(&self, row:&SweepRow) -> String {
+        self.parameters.iter().zip(row.parameters.iter())
+            .map(|(p, v)| format!("{} = {}", p.header(self.units), p.convert(*v, self.units)))
+            .join(", ")
+    }
+
+    // CSV in the output units, NaN for the failed runs
+    pub fn write(&self, path:&Path) -> Result<()> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Cannot create {}", dir.display()))?;
+        }
+        let file = File::create(path)
+            .with_context(|| format!("Cannot create {}", path.display()))?;
+        let mut buf = BufWriter::new(file);
+        let headers = self.parameters.iter().map(|p| p.header(self.units))
+            .chain(self.metrics.iter().map(|m| m.header(self.units)));
+        writeln!(buf, "{}", headers.format(", "))?;
+        for row in self.rows.iter() {
+            let parameters = self.parameters.iter().zip(row.parameters.iter())
+                .map(|(p, v)| p.convert(*v, self.units));
+            let metrics: Vec<f64> = match &row.metrics {
+                Ok(values) => self.metrics.iter().zip(values.iter())
+                    .map(|(m, v)| m.convert(*v, self.units))
+                    .collect(),
+                Err(_) => vec![f64::NAN; self.metrics.len()],
+            };
+            writeln!(buf, "{}", parameters.chain(metrics).format(", "))?;
+        }
+        buf.flush()?;
+        Ok(())
+    }
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   TESTING                                  */
+/* -------------------------------------------------------------------------- */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grids_are_swept_in_parallel() {
+        let dir = format!("{}/data", env!("CARGO_MANIFEST_DIR"));
+        let ron = r#"(
+            simulation: "simulation.ron",
+            parameters: [
+                (parameter: DoseRate, values: ["1 Gy/s", "1 kGy/s"]),
+                (parameter: GValue("e_aq"), values: [0, 2.8]),
+                (parameter: KValue("2 e_aq -> H2 + 2 OH_minus"), values: [1.1e10]),
+            ],
+            metrics: [Final("H2"), Peak("e_aq"), Auc("e_aq")],
+            threads: 2,
+        )"#;
+        let config: SweepConfig = ron::de::from_str(ron).unwrap();
+        let mut sweep = Sweep::new(config, Path::new(&dir)).unwrap();
+        sweep.base.config.end_time = 1e-5;
+        assert_eq!(sweep.points().unwrap().len(), 4);
+
+        let table = sweep.run().unwrap();
+        assert_eq!(table.iter_failures().count(), 0);
+        let metrics: Vec<&Vec<f64>> = table.rows.iter()
+            .map(|row| row.metrics.as_ref().unwrap())
+            .collect();
+        // No yield, no chemistry
+        assert_eq!(metrics[0], &vec![0.0; 3]);
+        assert_eq!(metrics[2], &vec![0.0; 3]);
+        assert!(metrics[3][1] > 10.0 * metrics[1][1]);
+        assert!(metrics[1].iter().chain(metrics[3].iter()).all(|x| *x > 0.0));
+        assert_eq!(table.describe(&table.rows[3]),
+                   "dose_rate [Gy/s] = 1000, G(e_aq) = 2.8, k(2 e_aq -> H2 + 2 OH_minus) = 11000000000");
+
+        // Failed runs are reported, not fatal
+        sweep.config.parameters[0] = SweepAxis {
+            parameter: Parameter::Period,
+            values: vec![Quantity::parse("10 µs").unwrap()],
+        };
+        let table = sweep.run().unwrap();
+        assert_eq!(table.iter_failures().count(), 2);
+        assert!(table.iter_failures().all(|(_, e)| e.contains("only applies to Pulsed beams")));
+        sweep.config.parameters[0] = SweepAxis {
+            parameter: Parameter::InitialConcentration(String::from("O2")),
+            values: vec![Quantity::parse("40 mmHg").unwrap()],
+        };
+        let table = sweep.run().unwrap();
+        assert_eq!(table.rows.len(), 2);
+        assert!(table.iter_failures().all(|(_, e)| e.contains("O2 (changed but not simulated)")));
+    }
+}
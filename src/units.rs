@@ -0,0 +1,344 @@
+/* ---------------------------- External imports ---------------------------- */
+use std::collections::HashMap;
+use std::fmt;
+use physical_constants as CST;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/* ---------------------------- Internal imports ---------------------------- */
+use crate::physics::gas::{PartialPressure, HENRY_O2};
+use crate::reactions::compartments::split_namespace;
+use crate::reactions::errors::RadioBioError;
+
+/* -------------------------------------------------------------------------- */
+/*                         FUNCTION/STRUCT DEFINITIONS                        */
+/* -------------------------------------------------------------------------- */
+// The state vector of the solvers is in [µmol/l], the reactions and the
+// configuration files in [mol/l]. Every conversion between both goes
+// through these.
+pub const STATE_TO_MOLAR: f64 = 1e-6;
+pub const MOLAR_TO_STATE: f64 = 1e6;
+
+pub fn to_state(molar:f64) -> f64 { molar * MOLAR_TO_STATE }
+pub fn to_molar(state:f64) -> f64 { state * STATE_TO_MOLAR }
+
+// molecule/cm3 in 1 mol/l, the unit of KPP mechanisms
+pub const MOLECULES_PER_CM3: f64 = CST::AVOGADRO_CONSTANT / 1000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum ConcentrationUnit {
+    Molar,
+    Millimolar,
+    #[default]
+    Micromolar,
+    Nanomolar,
+}
+
+impl ConcentrationUnit {
+    pub fn from_symbol(symbol:&str) -> Option<Self> {
+        match symbol {
+            "M" | "mol/l" | "mol/L" => Some(ConcentrationUnit::Molar),
+            "mM" => Some(ConcentrationUnit::Millimolar),
+            "µM" | "μM" | "uM" => Some(ConcentrationUnit::Micromolar),
+            "nM" => Some(ConcentrationUnit::Nanomolar),
+            _ => None,
+        }
+    }
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            ConcentrationUnit::Molar => "M",
+            ConcentrationUnit::Millimolar => "mM",
+            ConcentrationUnit::Micromolar => "µM",
+            ConcentrationUnit::Nanomolar => "nM",
+        }
+    }
+    // Value of 1 unit in [mol/l]
+    pub fn in_molar(&self) -> f64 {
+        match self {
+            ConcentrationUnit::Molar => 1.0,
+            ConcentrationUnit::Millimolar => 1e-3,
+            ConcentrationUnit::Micromolar => 1e-6,
+            ConcentrationUnit::Nanomolar => 1e-9,
+        }
+    }
+    pub fn from_molar(&self, molar:f64) -> f64 { molar / self.in_molar() }
+    pub fn from_state(&self, state:f64) -> f64 { self.from_molar(to_molar(state)) }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum TimeUnit {
+    #[default]
+    Second,
+    Millisecond,
+    Microsecond,
+    Nanosecond,
+}
+
+impl TimeUnit {
+    pub fn from_symbol(symbol:&str) -> Option<Self> {
+        match symbol {
+            "s" => Some(TimeUnit::Second),
+            "ms" => Some(TimeUnit::Millisecond),
+            "µs" | "μs" | "us" => Some(TimeUnit::Microsecond),
+            "ns" => Some(TimeUnit::Nanosecond),
+            _ => None,
+        }
+    }
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            TimeUnit::Second => "s",
+            TimeUnit::Millisecond => "ms",
+            TimeUnit::Microsecond => "µs",
+            TimeUnit::Nanosecond => "ns",
+        }
+    }
+    // Value of 1 unit in [s]
+    pub fn in_seconds(&self) -> f64 {
+        match self {
+            TimeUnit::Second => 1.0,
+            TimeUnit::Millisecond => 1e-3,
+            TimeUnit::Microsecond => 1e-6,
+            TimeUnit::Nanosecond => 1e-9,
+        }
+    }
+    pub fn from_seconds(&self, seconds:f64) -> f64 { seconds / self.in_seconds() }
+}
+
+// Value of 1 unit in [Gy/s]
+fn dose_rate_factor(symbol:&str) -> Option<f64> {
+    match symbol {
+        "Gy/s" => Some(1.0),
+        "Gy/min" => Some(1.0 / 60.0),
+        "kGy/s" => Some(1e3),
+        "MGy/s" => Some(1e6),
+        _ => None,
+    }
+}
+
+// Value of 1 unit in [Gy]
+fn dose_factor(symbol:&str) -> Option<f64> {
+    match symbol {
+        "Gy" => Some(1.0),
+        "cGy" => Some(1e-2),
+        "mGy" => Some(1e-3),
+        _ => None,
+    }
+}
+
+// Pressures are only accepted for O2, whose solubility is known
+fn pressure(value:f64, symbol:&str) -> Option<PartialPressure> {
+    match symbol {
+        "mmHg" | "Torr" => Some(PartialPressure::MmHg(value)),
+        "%" => Some(PartialPressure::Percent(value)),
+        "atm" => Some(PartialPressure::Atm(value)),
+        _ => None,
+    }
+}
+
+// Output units of the concentrations and times
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+pub struct OutputUnits {
+    #[serde(default)]
+    pub concentration: ConcentrationUnit,
+    #[serde(default)]
+    pub time: TimeUnit,
+}
+
+impl OutputUnits {
+    pub fn time_header(&self) -> String {
+        format!("t [{}]", self.time.symbol())
+    }
+    pub fn species_header(&self, name:&str) -> String {
+        format!("{name} [{}]", self.concentration.symbol())
+    }
+}
+
+// Value of a configuration file: a number in the default unit (mol/l, s,
+// Gy/s) or a string with its unit, e.g. "75 µM", "40 mmHg", "10 ns".
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantity {
+    pub value: f64,
+    pub unit: String,
+}
+
+impl Quantity {
+    pub fn new(value:f64) -> Self {
+        Self { value: value, unit: String::new() }
+    }
+
+    pub fn parse(text:&str) -> Result<Self, RadioBioError> {
+        let text = text.trim();
+        let split = text.find(|c:char| !(c.is_ascii_digit() || "+-.eE".contains(c)))
+                        .unwrap_or(text.len());
+        let (number, unit) = text.split_at(split);
+        match number.trim().parse::<f64>() {
+            Ok(value) => Ok(Self { value: value, unit: unit.trim().to_string() }),
+            Err(_) => Err(RadioBioError::InvalidQuantity(format!(
+                "'{text}' is not a number followed by a unit"))),
+        }
+    }
+
+    fn unknown_unit(&self, kind:&str) -> RadioBioError {
+        RadioBioError::InvalidQuantity(format!(
+            "unknown {kind} unit '{}' in '{self}'", self.unit))
+    }
+
+    // [mol/l], O2 can also be given as a partial pressure
+    pub fn concentration(&self, species:&str) -> Result<f64, RadioBioError> {
+        if self.unit.is_empty() {
+            return Ok(self.value);
+        }
+        if let Some(unit) = ConcentrationUnit::from_symbol(&self.unit) {
+            return Ok(self.value * unit.in_molar());
+        }
+        match pressure(self.value, &self.unit) {
+            Some(p) if split_namespace(species).1 == "O2" => Ok(p.to_concentration(HENRY_O2)),
+            Some(_) => Err(RadioBioError::InvalidQuantity(format!(
+                "{species} is given as a pressure ({self}), only O2 has a known solubility"))),
+            None => Err(self.unknown_unit("concentration")),
+        }
+    }
+
+    // [s]
+    pub fn time(&self) -> Result<f64, RadioBioError> {
+        if self.unit.is_empty() {
+            return Ok(self.value);
+        }
+        match TimeUnit::from_symbol(&self.unit) {
+            Some(unit) => Ok(self.value * unit.in_seconds()),
+            None => Err(self.unknown_unit("time")),
+        }
+    }
+
+    // [Gy/s]
+    pub fn dose_rate(&self) -> Result<f64, RadioBioError> {
+        if self.unit.is_empty() {
+            return Ok(self.value);
+        }
+        match dose_rate_factor(&self.unit) {
+            Some(factor) => Ok(self.value * factor),
+            None => Err(self.unknown_unit("dose rate")),
+        }
+    }
+
+    // [Gy]
+    pub fn dose(&self) -> Result<f64, RadioBioError> {
+        if self.unit.is_empty() {
+            return Ok(self.value);
+        }
+        match dose_factor(&self.unit) {
+            Some(factor) => Ok(self.value * factor),
+            None => Err(self.unknown_unit("dose")),
+        }
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match self.unit.is_empty() {
+            true => write!(f, "{}", self.value),
+            false => write!(f, "{} {}", self.value, self.unit),
+        }
+    }
+}
+
+impl Serialize for Quantity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.unit.is_empty() {
+            true => serializer.serialize_f64(self.value),
+            false => serializer.serialize_str(&self.to_string()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Quantity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct QuantityVisitor;
+        impl de::Visitor<'_> for QuantityVisitor {
+            type Value = Quantity;
+            fn expecting(&self, f:&mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a number or a string such as \"75 µM\"")
+            }
+            fn visit_f64<E: de::Error>(self, v:f64) -> Result<Quantity, E> {
+                Ok(Quantity::new(v))
+            }
+            fn visit_i64<E: de::Error>(self, v:i64) -> Result<Quantity, E> {
+                Ok(Quantity::new(v as f64))
+            }
+            fn visit_u64<E: de::Error>(self, v:u64) -> Result<Quantity, E> {
+                Ok(Quantity::new(v as f64))
+            }
+            fn visit_str<E: de::Error>(self, v:&str) -> Result<Quantity, E> {
+                Quantity::parse(v).map_err(E::custom)
+            }
+        }
+        deserializer.deserialize_any(QuantityVisitor)
+    }
+}
+
+// Concentrations by species, converted to [mol/l] when read
+pub(crate) fn concentrations<'de, D>(deserializer: D)
+-> Result<HashMap<String, f64>, D::Error>
+where D: Deserializer<'de> {
+    let raw = HashMap::<String, Quantity>::deserialize(deserializer)?;
+    raw.into_iter()
+       .map(|(sp, q)| q.concentration(&sp).map(|cc| (sp, cc)))
+       .collect::<Result<_, _>>()
+       .map_err(de::Error::custom)
+}
+
+// Time converted to [s] when read
+pub(crate) fn seconds<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where D: Deserializer<'de> {
+    Quantity::deserialize(deserializer)?.time().map_err(de::Error::custom)
+}
+
+// Dose rate converted to [Gy/s] when read
+pub(crate) fn gray_per_second<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where D: Deserializer<'de> {
+    Quantity::deserialize(deserializer)?.dose_rate().map_err(de::Error::custom)
+}
+
+// Dose converted to [Gy] when read
+pub(crate) fn gray<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where D: Deserializer<'de> {
+    Quantity::deserialize(deserializer)?.dose().map_err(de::Error::custom)
+}
+
+// Concentration of any species converted to [mol/l] when read
+pub(crate) fn molar<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where D: Deserializer<'de> {
+    Quantity::deserialize(deserializer)?.concentration("").map_err(de::Error::custom)
+}
+
+/* -------------------------------------------------------------------------- */
+/*                                   TESTING                                  */
+/* -------------------------------------------------------------------------- */
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantities_are_converted() {
+        let q = |text:&str| Quantity::parse(text).unwrap();
+        assert_float_relative_eq!(q("75 µM").concentration("H2O2").unwrap(), 75e-6, 1e-12);
+        assert_float_relative_eq!(q("2mM").concentration("GSH").unwrap(), 2e-3, 1e-12);
+        assert_float_relative_eq!(q("1.5e-3").concentration("GSH").unwrap(), 1.5e-3, 1e-12);
+        assert_float_relative_eq!(q("760 mmHg").concentration("intra:O2").unwrap(),
+                                  HENRY_O2, 1e-12);
+        assert_float_relative_eq!(q("21 %").concentration("O2").unwrap(), 0.21*HENRY_O2, 1e-12);
+        assert!(q("40 mmHg").concentration("H2").is_err());
+        assert!(q("3 furlongs").concentration("O2").is_err());
+        assert_float_relative_eq!(q("10 ns").time().unwrap(), 1e-8, 1e-12);
+        assert_float_relative_eq!(q("2.5 us").time().unwrap(), 2.5e-6, 1e-12);
+        assert_float_relative_eq!(q("2 Gy/min").dose_rate().unwrap(), 2.0/60.0, 1e-12);
+        assert_float_relative_eq!(q("40 Gy/s").dose_rate().unwrap(), 40.0, 1e-12);
+        assert_float_relative_eq!(q("50 cGy").dose().unwrap(), 0.5, 1e-12);
+        assert!(Quantity::parse("µM").is_err());
+
+        let molar = to_molar(to_state(3e-5));
+        assert_float_relative_eq!(molar, 3e-5, 1e-12);
+        assert_float_relative_eq!(ConcentrationUnit::Micromolar.from_state(42.0), 42.0, 1e-12);
+        assert_float_relative_eq!(ConcentrationUnit::Nanomolar.from_molar(1e-6), 1e3, 1e-12);
+        assert_float_relative_eq!(TimeUnit::Microsecond.from_seconds(1e-3), 1e3, 1e-12);
+    }
+}